@@ -0,0 +1,304 @@
+//! Placeholder constraints (`{id:int}` or `{id:[0-9]+}`).
+//!
+//! A constraint restricts the path segments matched by a placeholder. It's compiled into the
+//! route's regex, and taken into account when checking routes for overlap, so that
+//! `/users/{id:int}` and `/users/me` can be used side by side.
+
+use regex::Regex;
+use regex_syntax::hir::{
+    Class, ClassBytes, ClassBytesRange, ClassUnicode, ClassUnicodeRange, Group, GroupKind, Hir,
+    HirKind, Literal, Repetition,
+};
+use regex_syntax::ParserBuilder;
+
+/// Predefined constraints that can be referred to by name.
+const NAMED: &[(&str, &str)] = &[
+    ("int", "-?[0-9]+"),
+    ("uint", "[0-9]+"),
+    ("alpha", "[a-zA-Z]+"),
+    ("alnum", "[a-zA-Z0-9]+"),
+    (
+        "uuid",
+        "[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+    ),
+];
+
+/// Regex used by placeholders without explicit constraint.
+const ANY: &str = "[^/]+";
+
+/// The set of path segments a placeholder can match.
+#[derive(Clone)]
+pub struct Constraint {
+    /// Regex matching a single segment. Never matches `/` and contains no capture groups.
+    regex: String,
+    /// Characters that can appear at the start of a matched segment.
+    first_chars: ClassUnicode,
+    /// Characters that can appear at the end of a matched segment.
+    last_chars: ClassUnicode,
+}
+
+impl Constraint {
+    /// The constraint used by placeholders without explicit constraint. Matches any non-empty
+    /// segment.
+    pub fn any() -> Self {
+        let hir = parse_regex(ANY, ANY).expect("internal error: invalid default constraint");
+        Self::from_hir(ANY.to_string(), &hir)
+    }
+
+    /// Parses the part of a placeholder following the `:`.
+    ///
+    /// `constraint` is either the name of a predefined constraint, or a regular expression.
+    pub fn parse(constraint: &str) -> Result<Self, String> {
+        let regex = NAMED
+            .iter()
+            .find(|(name, _)| *name == constraint)
+            .map(|(_, regex)| *regex)
+            .unwrap_or(constraint);
+
+        let hir = sanitize(parse_regex(constraint, regex)?)
+            .map_err(|msg| format!("invalid placeholder constraint `{}`: {}", constraint, msg))?;
+        if hir.is_match_empty() {
+            return Err(format!(
+                "invalid placeholder constraint `{}`: must not match an empty path segment",
+                constraint
+            ));
+        }
+
+        Ok(Self::from_hir(hir.to_string(), &hir))
+    }
+
+    fn from_hir(regex: String, hir: &Hir) -> Self {
+        Self {
+            regex,
+            first_chars: edge_chars(hir, false).0,
+            last_chars: edge_chars(hir, true).0,
+        }
+    }
+
+    /// Returns whether this is the constraint of placeholders without explicit constraint.
+    pub fn is_any(&self) -> bool {
+        self.regex == ANY
+    }
+
+    /// Returns the regex matching a segment (without anchors and capture group).
+    pub fn regex(&self) -> &str {
+        &self.regex
+    }
+
+    /// Returns whether the literal path segment `segment` is matched by this constraint.
+    pub fn matches(&self, segment: &str) -> bool {
+        Regex::new(&format!("^(?:{})$", self.regex))
+            .expect("internal error: invalid constraint regex")
+            .is_match(segment)
+    }
+
+    /// Returns the set of characters a matched segment can start with.
+    pub fn first_chars(&self) -> &ClassUnicode {
+        &self.first_chars
+    }
+
+    /// Returns the set of characters a matched segment can end with.
+    pub fn last_chars(&self) -> &ClassUnicode {
+        &self.last_chars
+    }
+
+    /// Returns `true` if `self` and `other` can't possibly match the same segment.
+    ///
+    /// This is a conservative check: It only looks at the first and last character of matched
+    /// segments, so it might return `false` for constraints that are in fact disjoint.
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        let disjoint = |a: &ClassUnicode, b: &ClassUnicode| {
+            let mut common = a.clone();
+            common.intersect(b);
+            common.ranges().is_empty()
+        };
+        disjoint(&self.first_chars, &other.first_chars)
+            || disjoint(&self.last_chars, &other.last_chars)
+    }
+}
+
+/// Parses the regex of `constraint`.
+fn parse_regex(constraint: &str, regex: &str) -> Result<Hir, String> {
+    ParserBuilder::new()
+        .build()
+        .parse(regex)
+        .map_err(|e| format!("invalid placeholder constraint `{}`: {}", constraint, e))
+}
+
+/// Prepares a user-provided constraint for use inside a route regex.
+///
+/// Removes `/` from all character classes (since a segment can never contain it), turns capture
+/// groups into non-capturing groups (since the route regex is accessed by capture index), and
+/// rejects anchors.
+fn sanitize(hir: Hir) -> Result<Hir, String> {
+    Ok(match hir.into_kind() {
+        HirKind::Empty => Hir::empty(),
+        HirKind::Literal(Literal::Unicode('/')) | HirKind::Literal(Literal::Byte(b'/')) => {
+            return Err("must not match `/`".to_string());
+        }
+        HirKind::Literal(lit) => Hir::literal(lit),
+        HirKind::Class(Class::Unicode(mut class)) => {
+            class.difference(&ClassUnicode::new(vec![ClassUnicodeRange::new('/', '/')]));
+            if class.ranges().is_empty() {
+                return Err("must not match `/`".to_string());
+            }
+            Hir::class(Class::Unicode(class))
+        }
+        HirKind::Class(Class::Bytes(mut class)) => {
+            class.difference(&ClassBytes::new(vec![ClassBytesRange::new(b'/', b'/')]));
+            if class.ranges().is_empty() {
+                return Err("must not match `/`".to_string());
+            }
+            Hir::class(Class::Bytes(class))
+        }
+        HirKind::Anchor(_) => return Err("anchors are not allowed".to_string()),
+        HirKind::WordBoundary(wb) => Hir::word_boundary(wb),
+        HirKind::Repetition(rep) => Hir::repetition(Repetition {
+            kind: rep.kind,
+            greedy: rep.greedy,
+            hir: Box::new(sanitize(*rep.hir)?),
+        }),
+        HirKind::Group(group) => Hir::group(Group {
+            kind: GroupKind::NonCapturing,
+            hir: Box::new(sanitize(*group.hir)?),
+        }),
+        HirKind::Concat(hirs) => {
+            Hir::concat(hirs.into_iter().map(sanitize).collect::<Result<_, _>>()?)
+        }
+        HirKind::Alternation(hirs) => {
+            Hir::alternation(hirs.into_iter().map(sanitize).collect::<Result<_, _>>()?)
+        }
+    })
+}
+
+/// Computes the set of characters a match of `hir` can start with (or end with, if `from_end` is
+/// `true`).
+///
+/// Also returns whether `hir` can match the empty string, in which case the first character
+/// of a match might come from whatever follows `hir`.
+fn edge_chars(hir: &Hir, from_end: bool) -> (ClassUnicode, bool) {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Anchor(_) | HirKind::WordBoundary(_) => {
+            (ClassUnicode::empty(), true)
+        }
+        HirKind::Literal(Literal::Unicode(c)) => {
+            (ClassUnicode::new(vec![ClassUnicodeRange::new(*c, *c)]), false)
+        }
+        HirKind::Literal(Literal::Byte(b)) => {
+            let c = char::from(*b);
+            (ClassUnicode::new(vec![ClassUnicodeRange::new(c, c)]), false)
+        }
+        HirKind::Class(Class::Unicode(class)) => (class.clone(), false),
+        HirKind::Class(Class::Bytes(class)) => {
+            let ranges = class
+                .iter()
+                .map(|r| ClassUnicodeRange::new(char::from(r.start()), char::from(r.end())));
+            (ClassUnicode::new(ranges), false)
+        }
+        HirKind::Repetition(rep) => {
+            let (chars, _) = edge_chars(&rep.hir, from_end);
+            (chars, rep.is_match_empty())
+        }
+        HirKind::Group(group) => edge_chars(&group.hir, from_end),
+        HirKind::Concat(hirs) => {
+            let mut chars = ClassUnicode::empty();
+            let hirs: Box<dyn Iterator<Item = &Hir>> = if from_end {
+                Box::new(hirs.iter().rev())
+            } else {
+                Box::new(hirs.iter())
+            };
+            for hir in hirs {
+                let (first, nullable) = edge_chars(hir, from_end);
+                chars.union(&first);
+                if !nullable {
+                    return (chars, false);
+                }
+            }
+            (chars, true)
+        }
+        HirKind::Alternation(hirs) => {
+            let mut chars = ClassUnicode::empty();
+            let mut any_nullable = false;
+            for hir in hirs {
+                let (first, nullable) = edge_chars(hir, from_end);
+                chars.union(&first);
+                any_nullable |= nullable;
+            }
+            (chars, any_nullable)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constraint(raw: &str) -> Constraint {
+        Constraint::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn named() {
+        let int = constraint("int");
+        assert!(int.matches("123"));
+        assert!(int.matches("-1"));
+        assert!(!int.matches("me"));
+        assert!(!int.matches(""));
+
+        let uuid = constraint("uuid");
+        assert!(uuid.matches("67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert!(!uuid.matches("67e55044"));
+    }
+
+    #[test]
+    fn sanitized() {
+        // `/` is removed from classes
+        let any = constraint(".+");
+        assert!(any.matches("a.b"));
+        assert!(!any.matches("a/b"));
+
+        // Capture groups are turned into non-capturing groups
+        let groups = constraint("(a|b)(?P<name>c)");
+        assert_eq!(Regex::new(groups.regex()).unwrap().captures_len(), 1);
+        assert!(groups.matches("bc"));
+    }
+
+    #[test]
+    fn slash() {
+        assert_eq!(
+            Constraint::parse("a/b").err().unwrap(),
+            "invalid placeholder constraint `a/b`: must not match `/`"
+        );
+    }
+
+    #[test]
+    fn anchor() {
+        assert_eq!(
+            Constraint::parse("^a").err().unwrap(),
+            "invalid placeholder constraint `^a`: anchors are not allowed"
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            Constraint::parse("[0-9]*").err().unwrap(),
+            "invalid placeholder constraint `[0-9]*`: must not match an empty path segment"
+        );
+    }
+
+    #[test]
+    fn disjoint() {
+        let int = constraint("int");
+        let alpha = constraint("alpha");
+        let any = Constraint::any();
+        assert!(int.is_disjoint(&alpha));
+        assert!(!int.is_disjoint(&any));
+        assert!(!alpha.is_disjoint(&any));
+        assert!(!int.is_disjoint(&constraint("uint")));
+        assert!(constraint("a?b").is_disjoint(&constraint("c")));
+        assert!(!constraint("a?b").is_disjoint(&constraint("b")));
+        assert!(constraint("[a-z]+\\.txt").is_disjoint(&constraint("[a-z]+\\.md")));
+        assert!(!constraint("[a-z]+\\.txt").is_disjoint(&constraint("[a-z]+t")));
+    }
+}
@@ -0,0 +1,154 @@
+//! Host patterns used by `#[host("{tenant}.example.com")]`.
+//!
+//! A host pattern consists of dot-separated labels, each of which is either literal text or a
+//! placeholder binding an entire label to a field. Host names are case-insensitive, so literal
+//! labels are stored in lowercase and matched against the lowercased request host.
+
+use super::parse::valid_ident;
+use proc_macro2::{Ident, Span};
+
+/// A parsed `#[host]` attribute.
+#[derive(Clone)]
+pub struct HostPattern {
+    /// The pattern as written in the attribute.
+    raw: String,
+    labels: Vec<HostLabel>,
+    /// Placeholder field names, in order of appearance.
+    placeholders: Vec<Ident>,
+}
+
+#[derive(Clone)]
+enum HostLabel {
+    /// `{ident}`
+    Placeholder,
+    /// Literal text, in lowercase.
+    Literal(String),
+}
+
+impl HostPattern {
+    pub fn parse(raw: String) -> Result<Self, String> {
+        if raw.is_empty() {
+            return Err("#[host] must not be empty".to_string());
+        }
+        if raw.contains(':') || raw.contains('/') {
+            return Err(format!(
+                "invalid host pattern `{}`: must not contain a port, scheme or path",
+                raw
+            ));
+        }
+
+        let mut placeholders = Vec::new();
+        let mut labels = Vec::new();
+        for label in raw.split('.') {
+            if label.is_empty() {
+                return Err(format!("invalid host pattern `{}`: empty label", raw));
+            }
+
+            if label.starts_with('{') && label.ends_with('}') {
+                let ident = &label[1..label.len() - 1];
+                if !valid_ident(ident) {
+                    return Err(format!(
+                        "host placeholder `{}` must be a valid identifier",
+                        ident
+                    ));
+                }
+
+                let ident = Ident::new(ident, Span::call_site());
+                if placeholders.contains(&ident) {
+                    return Err(format!("duplicate placeholders in host pattern `{}`", raw));
+                }
+                placeholders.push(ident);
+                labels.push(HostLabel::Placeholder);
+            } else if label.contains('{') || label.contains('}') {
+                return Err(format!(
+                    "invalid host pattern `{}`: placeholders must make up an entire label",
+                    raw
+                ));
+            } else {
+                labels.push(HostLabel::Literal(label.to_lowercase()));
+            }
+        }
+
+        Ok(Self {
+            raw,
+            labels,
+            placeholders,
+        })
+    }
+
+    /// Returns the pattern as written in the attribute.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Returns the placeholders in the pattern, in order of appearance.
+    pub fn placeholders(&self) -> &[Ident] {
+        &self.placeholders
+    }
+
+    /// Returns the labels of the pattern, with `None` standing for a placeholder.
+    pub fn labels(&self) -> impl Iterator<Item = Option<&str>> {
+        self.labels.iter().map(|label| match label {
+            HostLabel::Placeholder => None,
+            HostLabel::Literal(lit) => Some(lit.as_str()),
+        })
+    }
+
+    /// Returns `true` if there's a host name matched by both `self` and `other`.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.labels.len() == other.labels.len()
+            && self
+                .labels
+                .iter()
+                .zip(&other.labels)
+                .all(|pair| match pair {
+                    (HostLabel::Literal(a), HostLabel::Literal(b)) => a == b,
+                    _ => true,
+                })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(pattern: &str) -> HostPattern {
+        HostPattern::parse(pattern.to_string()).unwrap()
+    }
+
+    #[test]
+    fn labels() {
+        let tenant = host("{tenant}.Example.com");
+        assert_eq!(
+            tenant.labels().collect::<Vec<_>>(),
+            [None, Some("example"), Some("com")]
+        );
+        assert_eq!(tenant.placeholders().len(), 1);
+    }
+
+    #[test]
+    fn overlap() {
+        assert!(host("{tenant}.example.com").overlaps(&host("admin.example.com")));
+        assert!(host("{a}.{b}.com").overlaps(&host("x.{c}.com")));
+        assert!(!host("{tenant}.example.com").overlaps(&host("example.com")));
+        assert!(!host("{tenant}.example.com").overlaps(&host("{tenant}.example.org")));
+    }
+
+    #[test]
+    fn partial_label() {
+        assert_eq!(
+            HostPattern::parse("api-{version}.example.com".to_string()).err().unwrap(),
+            "invalid host pattern `api-{version}.example.com`: placeholders must make up an entire label"
+        );
+    }
+
+    #[test]
+    fn port() {
+        assert_eq!(
+            HostPattern::parse("example.com:8080".to_string())
+                .err()
+                .unwrap(),
+            "invalid host pattern `example.com:8080`: must not contain a port, scheme or path"
+        );
+    }
+}
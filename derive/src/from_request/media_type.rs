@@ -0,0 +1,102 @@
+//! Media ranges used by `#[consumes("application/json")]` and `#[produces("text/html")]`.
+//!
+//! A media range is a `type/subtype` pair, where the subtype (or both parts) may be the wildcard
+//! `*`. Media types are case-insensitive, so they're stored in lowercase and matched against the
+//! lowercased essence of the request's `Content-Type` (without any parameters).
+
+use super::parse::is_token_char;
+
+/// A parsed media range like `application/json` or `text/*`.
+#[derive(Clone)]
+pub struct MediaRange {
+    /// `type/subtype`, in lowercase.
+    essence: String,
+    /// Length of the type part of `essence`.
+    type_len: usize,
+}
+
+impl MediaRange {
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let essence = raw.to_ascii_lowercase();
+        let valid = match essence.find('/') {
+            Some(pos) => {
+                let (ty, subtype) = (&essence[..pos], &essence[pos + 1..]);
+                let token = |s: &str| !s.is_empty() && s.chars().all(is_token_char);
+                token(ty) && token(subtype) && (ty != "*" || subtype == "*")
+            }
+            None => false,
+        };
+        if !valid {
+            return Err(format!(
+                "invalid media type `{}` (expected `type/subtype`, `type/*` or `*/*`)",
+                raw
+            ));
+        }
+
+        Ok(Self {
+            type_len: essence.find('/').unwrap(),
+            essence,
+        })
+    }
+
+    /// Returns the media range as `type/subtype`, in lowercase.
+    pub fn as_str(&self) -> &str {
+        &self.essence
+    }
+
+    fn type_(&self) -> &str {
+        &self.essence[..self.type_len]
+    }
+
+    fn subtype(&self) -> &str {
+        &self.essence[self.type_len + 1..]
+    }
+
+    /// Returns `true` if there's a media type matched by both `self` and `other`.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        let part = |a: &str, b: &str| a == "*" || b == "*" || a == b;
+        part(self.type_(), other.type_()) && part(self.subtype(), other.subtype())
+    }
+}
+
+/// Returns `true` if a request can be accepted by both `a` and `b`, where an empty list accepts
+/// any request.
+pub fn ranges_overlap(a: &[MediaRange], b: &[MediaRange]) -> bool {
+    a.is_empty() || b.is_empty() || a.iter().any(|a| b.iter().any(|b| a.overlaps(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(raw: &str) -> MediaRange {
+        MediaRange::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn overlap() {
+        assert!(range("application/json").overlaps(&range("Application/JSON")));
+        assert!(range("application/*").overlaps(&range("application/json")));
+        assert!(range("*/*").overlaps(&range("text/plain")));
+        assert!(!range("application/json").overlaps(&range("text/json")));
+        assert!(!range("application/json").overlaps(&range("application/xml")));
+
+        assert!(ranges_overlap(&[], &[range("text/plain")]));
+        assert!(!ranges_overlap(
+            &[range("text/plain"), range("text/html")],
+            &[range("application/json")]
+        ));
+    }
+
+    #[test]
+    fn invalid() {
+        let err = |raw: &str| MediaRange::parse(raw).err().unwrap();
+        assert_eq!(
+            err("json"),
+            "invalid media type `json` (expected `type/subtype`, `type/*` or `*/*`)"
+        );
+        assert!(err("*/json").starts_with("invalid media type `*/json`"));
+        assert!(err("application/json; charset=utf-8")
+            .starts_with("invalid media type `application/json; charset=utf-8`"));
+    }
+}
@@ -27,8 +27,8 @@
 //! }
 //! ```
 //!
-//! * Request path is matched completely (there is no regex support, although we
-//!   use regex internally)
+//! * Request path is matched completely, by walking a segment trie generated
+//!   from all routes (see `trie.rs`)
 //! * Path segments either match a literal (`/user/`) or a placeholder using
 //!   `FromStr` (`/:id`). The placeholder must not contain `/`, of course.
 //! * Query params are ignored (but can be deserialized)
@@ -66,25 +66,53 @@
 //!
 //! Placeholders must implement `Extract`.
 
+mod constraint;
+mod host;
+mod media_type;
 mod parse;
-
-use self::parse::{FieldKind, ItemData, PathMap, VariantData};
+mod reverse;
+mod route_table;
+mod trie;
+
+use self::parse::{
+    standard_method, FieldKind, ItemData, PathInfo, PathMap, Route, TrailingSlash, VariantData,
+};
+use self::reverse::{check_path_fn_names, derive_reverse_routing};
+use self::host::HostPattern;
+use self::route_table::derive_route_table;
+use self::trie::Trie;
+use crate::utils::{gen_impl, option_inner, Errors};
+use indexmap::IndexSet;
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::{quote, ToTokens};
-use std::iter::{self, FromIterator};
+use std::iter::FromIterator;
 use synstructure::{AddBounds, Structure, VariantInfo};
 
-pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
-    let is_struct;
-    match &s.ast().data {
-        syn::Data::Union(_) => {
-            panic!("#[derive(FromRequest)] is not allowed on unions");
+pub fn derive_from_request(s: Structure<'_>) -> TokenStream {
+    expand(s).unwrap_or_else(|errors| errors.to_compile_error())
+}
+
+/// Generates the `FromRequest` impl, or returns all errors found in the input.
+fn expand(mut s: Structure<'_>) -> Result<TokenStream, Errors> {
+    let is_struct = match &s.ast().data {
+        syn::Data::Union(u) => {
+            return Err(syn::Error::new_spanned(
+                u.union_token,
+                "#[derive(FromRequest)] is not allowed on unions",
+            )
+            .into());
         }
-        syn::Data::Struct(_) => is_struct = true,
-        syn::Data::Enum(_) => is_struct = false,
-    }
+        syn::Data::Struct(_) => true,
+        syn::Data::Enum(_) => false,
+    };
 
-    let item_data = ItemData::parse(s.ast().ident.clone(), &s.ast().attrs, is_struct);
+    let mut errors = Errors::new();
+    let item_data = ItemData::parse(
+        s.ast().ident.clone(),
+        &s.ast().attrs,
+        is_struct,
+        &mut errors,
+    );
 
     let context = item_data.context().cloned().unwrap_or_else(|| {
         syn::parse_str("NoContext").expect("internal error: couldn't parse type")
@@ -93,162 +121,223 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
     let variant_data = s
         .variants()
         .iter()
-        .map(|variant| {
-            let data = VariantData::parse(&variant.ast(), is_struct);
-            if data.constructible() {
-                // can be created by us
-                match &variant.ast().fields {
-                    syn::Fields::Unnamed(_) => panic!(
-                        "tuple variants are not supported (`{}::{}`)",
-                        s.ast().ident,
-                        variant.ast().ident
-                    ),
-                    _ => {}
-                }
-            }
-            data
-        })
-        .collect::<Vec<_>>();
-    let pathmap = PathMap::build(&item_data, &variant_data);
-    let all_regexes = pathmap
-        .paths()
-        .map(|p| p.regex().as_str().to_string())
+        .map(|variant| VariantData::parse(&variant.ast(), is_struct, &item_data, &mut errors))
         .collect::<Vec<_>>();
-    let all_regexes = &all_regexes;
+    let pathmap = PathMap::build(&item_data, &variant_data, &mut errors);
+    check_path_fn_names(&variant_data, is_struct, &mut errors);
 
-    // Ensure that there's at least 1 way for us to instantiate the type
-    if !variant_data.iter().any(|v| v.constructible()) {
+    // Ensure that there's at least 1 way for us to instantiate the type. A route attribute that
+    // failed to parse has already been reported, so don't ask for one.
+    if !variant_data
+        .iter()
+        .any(|v| v.constructible() || v.has_route_attr())
+    {
         let what = if is_struct {
             "struct"
         } else {
             "at least one variant of"
         };
-        panic!(
-            "{} `{}` must be constructible (add a route attribute or a `#[forward]` field)",
-            what,
-            s.ast().ident
-        );
+        errors.push(syn::Error::new_spanned(
+            &s.ast().ident,
+            format!(
+                "{} `{}` must be constructible (add a route attribute or a `#[forward]` field)",
+                what,
+                s.ast().ident
+            ),
+        ));
     }
 
-    let capturing_regexes = pathmap
-        .paths()
-        .map(|path| {
-            let regex = path.regex();
-            if regex.captures_len() > 0 {
-                // Captures something, so we need to store it separately
-                let r = regex.as_str();
-                quote!(Some(Regex::new(#r).expect("internal error: generated invalid regex")))
-            } else {
-                quote!(None)
-            }
-        })
+    // Everything below relies on the input being valid
+    errors.finish(())?;
+    let trie = Trie::build(&pathmap);
+    let has_routes = pathmap.paths().next().is_some();
+
+    let has_hosts = variant_data.iter().any(|data| data.host().is_some());
+    let has_consumes = variant_data.iter().any(|data| !data.consumes().is_empty());
+    let has_produces = variant_data.iter().any(|data| !data.produces().is_empty());
+
+    let variants = variant_data
+        .iter()
+        // Variants without a route are only included if they're the fallback variant, since
+        // other variants are never constructed
+        .filter(|data| data.constructible())
+        .map(|data| data.variant_name().clone())
         .collect::<Vec<_>>();
 
-    let (variants, variant_matches_path): (Vec<_>, Vec<_>) = variant_data
+    // Arms of the `variant_matches_path` closure, one for every variant and path its routes match
+    let pathmap = &pathmap;
+    let variant_matches_path = variant_data
         .iter()
         .zip(s.variants())
-        .filter_map(|(data, variant)| {
-            if let Some(route) = data.routes().first() {
-                let matches_path = if route.placeholders().is_empty() {
-                    // If there's no placeholders, there's no FromStr impls we have to check
-                    quote!(true)
-                } else {
-                    let tys = route
+        .flat_map(|(data, variant)| {
+            let mut seen = Vec::new();
+            data.routes()
+                .iter()
+                .filter(|route| !route.placeholders().is_empty())
+                .filter_map(move |route| {
+                    // Routes of a variant that share a path have the same placeholders
+                    let index = pathmap.path_index(route);
+                    if seen.contains(&index) {
+                        return None;
+                    }
+                    seen.push(index);
+
+                    let parse = route
                         .placeholders()
-                        .iter()
-                        .map(|name| {
-                            variant
-                                .ast()
-                                .fields
-                                .iter()
-                                .find(|field| field.ident.as_ref() == Some(name))
-                                .expect("internal error: couldn't find field by name")
-                                .ty
-                                .clone()
-                        })
-                        .collect::<Vec<_>>();
-                    let indices = tys
                         .iter()
                         .enumerate()
-                        .map(|(i, _)| i + 1)
+                        .map(|(i, name)| {
+                            let ty = placeholder_ty(data, field_by_name(variant, name));
+                            let parse =
+                                parse_placeholder(data, route, name, ty, quote!(captures[#i]));
+                            if route.path().is_optional(name) {
+                                // Left out optional placeholders aren't captured
+                                quote!((captures[#i].is_empty() || #parse.is_ok()))
+                            } else {
+                                quote!(#parse.is_ok())
+                            }
+                        })
                         .collect::<Vec<_>>();
+                    let name = data.variant_name();
+                    Some(quote! {
+                        (Variant::#name, #index) => #( #parse )&&*,
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let variants = &variants;
 
-                    quote! {
-                        let caps = regex
-                            .captures(path)
-                            .expect("internal error: regex first matched but now didn't?");
-
-                        #( <#tys as FromStr>::from_str(
-                            caps
-                                .get(#indices)
-                                .expect("internal error: capture group did not match anything")
-                                .as_str()
-                        ).is_ok() )&&*
+    let variant_matches_host = variant_data
+        .iter()
+        .zip(s.variants())
+        .filter(|(data, _)| data.constructible())
+        .map(|(data, variant)| match data.host() {
+            Some(host) => {
+                let labels = host_labels(host);
+                let parse = host
+                    .placeholders()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| {
+                        let ty = &variant
+                            .ast()
+                            .fields
+                            .iter()
+                            .find(|field| field.ident.as_ref() == Some(name))
+                            .expect("internal error: couldn't find field by name")
+                            .ty;
+                        quote!(<#ty as FromStr>::from_str(caps[#i]).is_ok())
+                    })
+                    .collect::<Vec<_>>();
+
+                quote! {
+                    match host.and_then(|host| hyperdrive::support::match_host(host, #labels)) {
+                        Some(caps) => true #( && #parse )*,
+                        None => false,
                     }
-                };
-                Some((data.variant_name().clone(), matches_path))
-            } else {
-                // No `#[method]` on the variant.
-                if data.forward_field().is_some() {
-                    // Fallback variant, always matches
-                    Some((data.variant_name().clone(), quote!(true)))
-                } else {
-                    // Don't include this variant at all, since we'll never construct it
-                    assert!(!data.constructible());
-                    None
                 }
             }
+            None => quote!(true),
         })
-        .unzip();
-    let variants = &variants;
+        .collect::<Vec<_>>();
 
+    // Methods that don't have an associated constant on `http::Method` are created once and
+    // stored in a `lazy_static`. They're referred to by their index in this set.
+    let extension_methods = pathmap
+        .paths()
+        .flat_map(|path| path.method_map().map(|(method, _)| method))
+        .filter(|method| standard_method(method).is_none())
+        .collect::<IndexSet<_>>();
+    let extension_methods = &extension_methods;
+
+    let fallback = pathmap.fallback();
     let mut regex_match_arms = pathmap
         .paths()
         .enumerate()
         .flat_map(|(i, pathinfo)| {
+            let has_captures = pathinfo.regex().captures_len() > 0;
+            // With several candidates, the path's `FromStr` impls decide between them
+            let check_path = move |candidates: &[(VariantData, Route)]| {
+                if has_captures && candidates.len() > 1 {
+                    Some(i)
+                } else {
+                    None
+                }
+            };
+            let any_method = pathinfo.any_method();
+            let any_method_arm = if any_method.is_empty() {
+                None
+            } else {
+                // Placed after all arms for specific methods, which makes the "wrong method" arm
+                // below unnecessary
+                let variant = select_candidate(any_method, check_path(any_method), fallback);
+                Some(quote! {
+                    (Some(#i), _) => { #variant }
+                })
+            };
             pathinfo
                 .method_map()
-                .map(move |(method, variant)| {
-                    let variant = &variant.variant_name();
+                .map(move |(method, candidates)| {
+                    // `#[any]` routes are tried after the ones for the specific method
+                    let candidates = candidates
+                        .iter()
+                        .chain(any_method)
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    let pattern = method_pattern(extension_methods, i, method);
+                    let variant = select_candidate(&candidates, check_path(&candidates), fallback);
                     quote! {
-                        (Some(#i), &http::Method::#method) => Variant::#variant,
+                        #pattern => { #variant }
                     }
                 })
-                .chain(iter::once({
+                .chain(any_method_arm)
+                .chain(any_method.is_empty().then(|| {
                     // This arm matches when the path matches, but an incorrect method is used.
                     // Here, we can still #[forward] to another `FromRequest` impl, so this doesn't
                     // always.
 
-                    // This evaluates to a `&'static [Method]` or `Vec<Method>` containing all
+                    // This evaluates to a `Vec<&'static Method>` containing all
                     // methods accepted by the invoked route, ignoring any #[forward]-marked
                     // `FromRequest` impl.
                     let find_accepted_methods = {
-                        if pathinfo.regex().captures_len() == 0 {
-                            // No captures, no FromStr: We have a statically known list of allowed
-                            // methods.
-                            let methods = pathinfo.method_map().map(|(m, _)| m).collect::<Vec<_>>();
+                        if pathinfo.regex().captures_len() == 0 && !pathinfo.has_hosts() {
+                            // No captures, no FromStr, no hosts: We have a statically known list
+                            // of allowed methods.
+                            let methods = pathinfo
+                                .method_map()
+                                .map(|(m, _)| method_expr(extension_methods, m))
+                                .collect::<Vec<_>>();
 
                             quote! {
-                                &[
-                                    #( Method::#methods, )*
+                                vec![
+                                    #( #methods, )*
                                 ]
                             }
                         } else {
-                            // We have placeholders; check the request path against all variants that
-                            // share the same path pattern
-                            let (variants, methods): (Vec<_>, Vec<_>) = pathinfo
+                            // We have placeholders or hosts; check the request against all
+                            // variants that share the same path pattern
+                            let check_path = if pathinfo.regex().captures_len() > 0 {
+                                Some(i)
+                            } else {
+                                None
+                            };
+                            let (conditions, methods): (Vec<_>, Vec<_>) = pathinfo
                                 .method_map()
-                                .map(|(method, variant)| (variant.variant_name(), method))
+                                .map(|(method, candidates)| {
+                                    let conditions = candidates.iter().map(|(variant, _)| {
+                                        candidate_condition(variant, check_path, false)
+                                    });
+                                    (quote!(#(#conditions)||*), method_expr(extension_methods, method))
+                                })
                                 .unzip();
 
                             quote! {{
-                                let path = request.uri().path();
-                                let regex = REGEXES[#i].as_ref().unwrap();
                                 let mut methods = Vec::new();
 
                                 #(
-                                    if variant_matches_path(Variant::#variants, regex, path) {
-                                        methods.push(&http::Method::#methods);
+                                    if #conditions {
+                                        methods.push(#methods);
                                     }
                                 )*
                                 methods
@@ -268,25 +357,23 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
                             .iter()
                             .find(|v| v.ast().ident == fallback.variant_name())
                             .expect("couldn't find fallback variant");
-                        let construct = construct_variant(info, fallback);
+                        let construct = construct_variant(info, fallback, pathmap);
 
                         quote! {
                             (Some(#i), _) => {
-                                // FIXME `find_accepted_methods` needs access to `request.uri()`
-                                // in the `map_err`. Clean things up so we don't need this.
-                                let mut tmp_request = http::Request::new(());
-                                *tmp_request.uri_mut() = request.uri().clone();
+                                // The captures borrow from the request, so determine our accepted
+                                // methods before handing off to the fallback.
+                                let our_methods = #find_accepted_methods;
 
                                 let future = #construct;
-                                let future = future.map_err(move |mut e| {
+                                let future = future.map_err(move |(mut e, _)| {
                                     use hyperdrive::{Error, http::StatusCode};
 
                                     // If the #[forward]ed impl also failed with "wrong_method", add
                                     // our accepted methods to it.
                                     if let Some(err) = e.downcast_mut::<Error>() {
                                         if err.http_status() == StatusCode::METHOD_NOT_ALLOWED {
-                                            let request = tmp_request;
-                                            let mut our_methods = Vec::from(#find_accepted_methods);
+                                            let mut our_methods = Vec::from(our_methods);
                                             let inner_methods = err.allowed_methods()
                                                 .expect("`WrongMethod` but no `allowed_methods()`?");
 
@@ -301,16 +388,27 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
                                     }
                                 });
 
-                                return Box::new(future);
+                                return Box::new(future.map(Loop::Break));
                             }
                         }
                     } else {
                         // No fallback variant. Match the request path against all variants
                         // sharing the same path pattern, checking if the FromStr succeeds,
                         // and collecting all accepted methods.
+                        let not_found = if pathinfo.has_hosts() {
+                            // The path might only exist on other hosts
+                            quote! {
+                                if methods.is_empty() {
+                                    return Error::from_status(StatusCode::NOT_FOUND).into_future();
+                                }
+                            }
+                        } else {
+                            quote!()
+                        };
                         quote! {
                             (Some(#i), _) => {
                                 let methods = #find_accepted_methods;
+                                #not_found
                                 return Error::wrong_method(methods).into_future();
                             }
                         }
@@ -343,44 +441,144 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
         .zip(&variant_data)
         .filter_map(|(variant, data)| {
             if data.constructible() {
-                Some(construct_variant(variant, data))
+                Some(construct_variant(variant, data, pathmap))
             } else {
                 None
             }
         })
         .collect::<Vec<_>>();
 
-    // The `lazy_static!` declarations containing the route regexes
-    let statics = if all_regexes.is_empty() {
-        // No routes
+    // The `lazy_static!` declarations containing extension methods and the regexes of
+    // placeholders that can't be matched by the trie directly (each only if there are any)
+    let segment_regexes = trie.segment_regexes().collect::<Vec<_>>();
+    let segments = if segment_regexes.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            static ref SEGMENTS: Vec<Regex> = vec![
+                #(
+                    Regex::new(#segment_regexes).expect("internal error: generated invalid regex"),
+                )*
+            ];
+        }
+    };
+    let extension_methods_static = if extension_methods.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            static ref EXTENSION_METHODS: Vec<http::Method> = vec![
+                #(
+                    http::Method::from_bytes(#extension_methods.as_bytes())
+                        .expect("internal error: invalid HTTP method"),
+                )*
+            ];
+        }
+    };
+    let statics = if extension_methods_static.is_empty() && segments.is_empty() {
         quote! {}
     } else {
         quote! {
             lazy_static! {
-                static ref ROUTES: RegexSet = RegexSet::new(&[
-                    #(#all_regexes,)*
-                ][..]).expect("invalid regex from FromRequest derive");
+                #extension_methods_static
 
-                static ref REGEXES: Vec<Option<Regex>> = vec![
-                    #(#capturing_regexes,)*
-                ];
+                #segments
             }
         }
     };
 
-    // An expression evaluating to the index of the matching regex (or `None`)
-    let matching_regex = if all_regexes.is_empty() {
-        quote!(None)
+    // The `match_path` function and an expression evaluating to the matching path's index and
+    // captures (or `None`)
+    let (match_path, route_match) = if !has_routes {
+        (quote!(), quote!(None))
+    } else if pathmap.is_overlapping() {
+        let (indices, ranks): (Vec<_>, Vec<_>) = pathmap
+            .paths()
+            .enumerate()
+            .map(|(i, pathinfo)| (i, route_rank(extension_methods, i, &pathinfo)))
+            .unzip();
+        let route_match = quote! {{
+            // Routes overlap, so several paths might match. Use the one with the lowest-ranked
+            // route accepting the request, or the first one if no route does (which results in a
+            // "405 Method Not Allowed" or "404 Not Found" error).
+            let matches = match_path(path);
+            let route_rank = |index: usize, captures: &[&str]| -> Option<u32> {
+                match index {
+                    #( #indices => #ranks, )*
+                    _ => None,
+                }
+            };
+            matches
+                .iter()
+                .filter_map(|&(index, captures)| {
+                    route_rank(index, &captures).map(|rank| (rank, index, captures))
+                })
+                .min()
+                .map(|(_, index, captures)| (index, captures))
+                .or_else(|| matches.iter().min_by_key(|(index, _)| *index).cloned())
+        }};
+        (trie.match_path_fn(true), route_match)
+    } else {
+        (trie.match_path_fn(false), quote!(match_path(path)))
+    };
+
+    // With `#[trailing_slash(redirect)]`, requests that only match after adding or removing a
+    // trailing slash are redirected to the canonical path (even if there's a fallback variant).
+    let redirect = if item_data.trailing_slash() == TrailingSlash::Redirect && has_routes {
+        let is_route = if pathmap.is_overlapping() {
+            quote!(|path| !match_path(path).is_empty())
+        } else {
+            quote!(|path| match_path(path).is_some())
+        };
+        quote! {
+            if index.is_none() {
+                if let Some(location) =
+                    hyperdrive::support::trailing_slash_redirect(request.uri(), #is_route)
+                {
+                    return Error::redirect(location).into_future();
+                }
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    // Code obtaining the request host and checking it against `#[host]` patterns. Only emitted
+    // when the type uses `#[host]`.
+    let (request_host, host_matching) = if !has_hosts {
+        (quote!(), quote!())
+    } else {
+        (
+            quote! {
+                let host: Option<String> = hyperdrive::support::request_host(request);
+            },
+            quote! {
+                // Returns whether `host` matches the `#[host]` pattern of `var`, including the
+                // `FromStr` implementations of all host placeholders.
+                let variant_matches_host = |var: Variant, host: Option<&str>| -> bool {
+                    match var {
+                        #( Variant::#variants => { #variant_matches_host } )*
+                    }
+                };
+            },
+        )
+    };
+
+    // Code obtaining the media type of the request body, which is checked against `#[consumes]`
+    let request_content_type = if has_consumes {
+        quote! {
+            let content_type: Option<String> = hyperdrive::support::media_type(request);
+        }
+    } else {
+        quote!()
+    };
+
+    // The parsed `Accept` header, used to negotiate between variants with `#[produces]`
+    let request_accept = if has_produces {
+        quote! {
+            let accept = hyperdrive::support::Accept::parse(request);
+        }
     } else {
-        quote! {{
-            let matches = ROUTES.matches(path);
-            debug_assert!(
-                matches.iter().count() <= 1,
-                "internal error: FromRequest derive produced overlapping regexes (path={},method={},regexes={:?})",
-                path, method, &[ #(#all_regexes),* ]
-            );
-            matches.iter().next()
-        }}
+        quote!()
     };
 
     // Don't automatically add bounds, we'll do that ourselves
@@ -408,12 +606,25 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
         Vec::new()
     };
 
-    s.gen_impl(quote!(
+    let reverse_routing = derive_reverse_routing(&s, &variant_data);
+    let route_table = derive_route_table(&s, &variant_data);
+
+    // The fallback variant is the last resort, so it can't forward requests
+    let forwardable = match pathmap.fallback() {
+        Some(fallback) => {
+            let variant = fallback.variant_name();
+            quote!(variant != Variant::#variant)
+        }
+        None => quote!(true),
+    };
+
+    let captures = trie.captures();
+    let from_request = gen_impl(&s, quote!(
         extern crate hyperdrive;
         use hyperdrive::{
             FromBody, FromRequest, Guard, DefaultFuture, NoContext, BoxedError, Error,
-            http::{self, StatusCode}, hyper, lazy_static, regex::{RegexSet, Regex},
-            futures::{IntoFuture, Future},
+            http::{self, StatusCode}, hyper, lazy_static, regex::Regex,
+            futures::{IntoFuture, Future, future::{loop_fn, Loop}},
         };
         // Make sure `.as_ref()` always refers to the `AsRef` trait in libstd.
         // Otherwise the calling crate could override this.
@@ -432,41 +643,88 @@ pub fn derive_from_request(mut s: Structure<'_>) -> TokenStream {
             ) -> Self::Future {
                 // Step 0: `Variant` has all variants of the input enum that have a route attribute
                 // but without any data.
+                #[derive(Clone, Copy, PartialEq)]
                 enum Variant {
                     #(#variants,)*
                 }
 
-                // Returns whether `self`, with `regex`, matches `path`.
-                //
-                // This checks all path placeholder's `FromStr` implementations against the
-                // path segments and returns `true` if they all succeed.
-                //
-                // This is a closure instead of a function to allow use of the `impl`-level generics
-                // (if any).
-                let variant_matches_path = |var: Variant, regex: &Regex, path: &str| -> bool {
-                    match var {
-                        #( Variant::#variants => { #variant_matches_path } )*
-                    }
-                };
+                // The request body and context, and the variants that forwarded the request
+                type State<T> = (hyper::Body, <T as FromRequest>::Context, Vec<Variant>);
+                type Attempt<T> = DefaultFuture<Loop<T, State<T>>, BoxedError>;
+
+                // The request is routed in a loop: If a guard forwards it (see `Error::forward`),
+                // it is routed again, skipping all variants in `tried`.
+                let request = Arc::clone(request);
+                let route = move |(body, context, mut tried): State<Self>| -> Attempt<Self> {
+                    let request = &request;
+
+                    // Returns whether `var` matches the placeholders captured from the `index`th
+                    // path.
+                    //
+                    // This checks all path placeholder's `FromStr` implementations against the
+                    // captured path segments and returns `true` if they all succeed.
+                    //
+                    // This is a closure instead of a function to allow use of the `impl`-level
+                    // generics (if any).
+                    let variant_matches_path =
+                        |var: Variant, index: usize, captures: &[&str]| -> bool {
+                            match (var, index) {
+                                #(#variant_matches_path)*
+                                // No placeholders, so there's no FromStr impls we have to check
+                                _ => true,
+                            }
+                        };
+
+                    #host_matching
+
+                    // Step 1: Match the path against the generated segment trie and inspect the
+                    // HTTP method in order to find the route that matches.
+                    #statics
+                    #match_path
+
+                    let method = request.method();
+                    let path = request.uri().path();
+                    #request_host
+                    #request_content_type
+                    #request_accept
+                    let route_match: Option<(usize, [&str; #captures])> = #route_match;
+                    let (index, captures) = match route_match {
+                        Some((index, captures)) => (Some(index), captures),
+                        None => (None, [""; #captures]),
+                    };
+                    #redirect
 
-                // Step 1: Match against the generated regex set and inspect the HTTP
-                // method in order to find the route that matches.
-                #statics
+                    let variant = match (index, method) {
+                        #(#regex_match_arms)*
+                    };
 
-                let method = request.method();
-                let path = request.uri().path();
-                let index: Option<usize> = #matching_regex;
+                    let future = match variant {
+                        #( Variant::#variants => #variant_arms, )*
+                    };
 
-                let variant = match (index, method) {
-                    #(#regex_match_arms)*
+                    Box::new(future.then(move |result| match result {
+                        Ok(value) => Ok(Loop::Break(value)),
+                        Err((e, Some((body, context))))
+                            if #forwardable && hyperdrive::support::is_forward(&e) =>
+                        {
+                            tried.push(variant);
+                            Ok(Loop::Continue((body, context, tried)))
+                        }
+                        Err((e, _)) => Err(e),
+                    }))
                 };
 
-                match variant {
-                    #( Variant::#variants => #variant_arms, )*
-                }
+                Box::new(loop_fn((body, context, Vec::new()), route))
             }
         }
-    ))
+    ));
+
+    Ok(quote! {
+        #from_request
+
+        #reverse_routing
+        #route_table
+    })
 }
 
 /// Information about trait bounds that need to hold for a `FromRequest` impl to be applicable.
@@ -520,11 +778,21 @@ fn generate_trait_bounds(item: &ItemData, variants: &[VariantData]) -> Bounds {
 
     let mut bounds: Bounds = variants
         .iter()
-        .flat_map(|v| v.field_uses())
-        .map(|(field, field_kind)| {
-            let ty = field.ty.clone();
+        .flat_map(|v| {
+            // `#[guard]` types need the same bounds as guard fields
+            v.field_uses()
+                .map(move |(field, kind)| {
+                    let ty = match kind {
+                        FieldKind::PathSegment => placeholder_ty(v, field),
+                        _ => &field.ty,
+                    };
+                    (ty.clone(), kind)
+                })
+                .chain(v.guards().iter().map(|ty| (ty.clone(), FieldKind::Guard)))
+        })
+        .map(|(ty, field_kind)| {
             match field_kind {
-                FieldKind::PathSegment => Bounds {
+                FieldKind::PathSegment | FieldKind::Host => Bounds {
                     addl_ty_params: Vec::new(),
                     impl_bounds: vec![
                         quote!( #ty:
@@ -535,6 +803,21 @@ fn generate_trait_bounds(item: &ItemData, variants: &[VariantData]) -> Bounds {
                         ),
                     ],
                 },
+                FieldKind::Query | FieldKind::Header | FieldKind::Cookie => {
+                    // Optional parameters are parsed into the `Option`'s inner type
+                    let ty = option_inner(&ty).unwrap_or(&ty);
+                    Bounds {
+                        addl_ty_params: Vec::new(),
+                        impl_bounds: vec![
+                            quote!( #ty:
+                                ::std::str::FromStr + ::std::marker::Send + 'static
+                            ),
+                            quote!( <#ty as ::std::str::FromStr>::Err:
+                                ::std::error::Error + ::std::marker::Sync + ::std::marker::Send + 'static
+                            ),
+                        ],
+                    }
+                },
                 FieldKind::QueryParams => Bounds {
                     addl_ty_params: Vec::new(),
                     impl_bounds: vec![quote!( #ty:
@@ -638,143 +921,702 @@ fn generate_trait_bounds(item: &ItemData, variants: &[VariantData]) -> Bounds {
         })
         .collect();
 
+    // Placeholder fields marked with `#[default]` are created with `Default` if they're missing
+    let default_fields = variants.iter().flat_map(|v| {
+        v.field_uses()
+            .filter(move |(field, _)| v.has_default(field.ident.as_ref().unwrap()))
+    });
+    for (field, _) in default_fields {
+        let ty = &field.ty;
+        bounds
+            .impl_bounds
+            .push(quote!( #ty: ::std::default::Default ));
+    }
+
     bounds.addl_ty_params.extend(ty_params);
     bounds
 }
 
-/// Generates all the code needed to build an enum variant from a matching
-/// request.
+/// Returns the field of `variant` called `name`.
+fn field_by_name<'a>(variant: &VariantInfo<'a>, name: &Ident) -> &'a syn::Field {
+    variant
+        .ast()
+        .fields
+        .iter()
+        .find(|field| field.ident.as_ref() == Some(name))
+        .expect("internal error: couldn't find field by name")
+}
+
+/// Returns the type that the path placeholder bound to `field` is parsed into.
 ///
-/// Returns an expression of type `DefaultFuture<Self, BoxedError>`.
+/// This is the field's type, or the `Option`'s inner type if the placeholder is optional (see
+/// `VariantData::is_optional_placeholder`).
+fn placeholder_ty<'a>(data: &VariantData, field: &'a syn::Field) -> &'a syn::Type {
+    let is_optional = data.is_optional_placeholder(field.ident.as_ref().unwrap());
+    match option_inner(&field.ty) {
+        Some(inner) if is_optional => inner,
+        _ => &field.ty,
+    }
+}
+
+/// Generates an expression that converts the captured value of the placeholder
+/// `field` in `route` to the field's type `ty`.
 ///
-/// The generated code will do the following:
-/// * If the path has any segment placeholders:
-///   * Obtain the captures with the specific regex for this route
-///   * Call `FromStr` on all captured segments
-/// * If it has `query_params`
-///   * Deserialize from ?these&query=parameters
-/// * For each guard (= field that isn't mentioned in any attribute)
-///   * Chain all calls to the `from_request` methods
-/// * If it has a `body`
-///   * Chain the call to its `from_body` method
+/// `input` must evaluate to the captured `&str`. Unless the field is marked with
+/// `#[raw]`, the captured value is percent-decoded first. The expression
+/// evaluates to a `Result<#ty, BoxedError>`.
+fn parse_placeholder(
+    data: &VariantData,
+    route: &Route,
+    field: &Ident,
+    ty: &syn::Type,
+    input: TokenStream,
+) -> TokenStream {
+    if data.is_raw(field) {
+        quote!(<#ty as FromStr>::from_str(#input).map_err(BoxedError::from))
+    } else {
+        let decode = if route.path().rest_placeholder() == Some(field) {
+            quote!(hyperdrive::support::decode_rest)
+        } else {
+            quote!(hyperdrive::support::decode_segment)
+        };
+        quote! {
+            #decode(#input).and_then(|decoded| {
+                <#ty as FromStr>::from_str(&decoded).map_err(BoxedError::from)
+            })
+        }
+    }
+}
+
+/// Generates a statement that stores the value of the query parameter, header or cookie `name`
+/// in the `fld_X` variable for `field`.
 ///
-/// The code will also assume:
-/// * That `request` is the incoming request, and can be consumed.
-fn construct_variant(variant: &VariantInfo<'_>, data: &VariantData) -> TokenStream {
-    let field_by_name = |name: &Ident| -> &syn::Field {
-        variant
-            .ast()
-            .fields
-            .iter()
-            .find(|field| field.ident.as_ref() == Some(name))
-            .expect("internal error: couldn't find field by name")
+/// `kind` describes the value in error messages (eg. `"header"`). `parse` is the function that
+/// extracts and parses the value, which is called with `input` and the name, and returns a
+/// `Result<Option<T>, ParamError>`. If the field isn't an `Option`, a missing value is rejected
+/// with a `400 Bad Request` error.
+fn parse_param(
+    field: &syn::Field,
+    name: &str,
+    kind: &str,
+    parse: TokenStream,
+    input: TokenStream,
+) -> TokenStream {
+    let variable = Ident::new(
+        &format!("fld_{}", field.ident.as_ref().unwrap()),
+        Span::call_site(),
+    );
+    let (ty, present, missing) = match option_inner(&field.ty) {
+        Some(inner) => (inner, quote!(Some(v)), quote!(None)),
+        None => (
+            &field.ty,
+            quote!(v),
+            quote! {
+                return Error::with_source(
+                    StatusCode::BAD_REQUEST,
+                    hyperdrive::support::ParamError::Missing(#kind, #name),
+                )
+                .into_future()
+            },
+        ),
     };
+    quote! {
+        let #variable = match #parse::<#ty>(#input, #name) {
+            Ok(Some(v)) => #present,
+            Ok(None) => #missing,
+            Err(e) => return Error::with_source(StatusCode::BAD_REQUEST, e).into_future(),
+        };
+    }
+}
 
-    let placeholders = {
-        // If we have route attributes on this variant, they all have the same (order of)
-        // placeholders, so we only need to look at the first attribute.
-        match data.routes().first() {
-            Some(route) if !route.placeholders().is_empty() => {
-                // For each placeholder, get its captured string and parse it
-                let parse = route
-                    .placeholders()
-                    .iter()
-                    .enumerate()
-                    .map(|(i, field_name)| {
-                        let variable = Ident::new(&format!("fld_{}", field_name), Span::call_site());
-                        let capture = i + 1;
-                        let ty = &field_by_name(field_name).ty;
-                        quote! {
-                            let #variable = captures
-                                .get(#capture)
-                                .expect("internal error: capture group did not match anything")
-                                .as_str();
-                            let #variable = match <#ty as FromStr>::from_str(#variable) {
-                                Ok(v) => v,
-                                Err(e) => {
-                                    return Error::with_source(StatusCode::NOT_FOUND, e)
-                                        .into_future();
-                                }
-                            };
-                        }
-                    })
-                    .collect::<Vec<_>>();
+/// Generates an expression of type `&[Option<&str>]` containing the labels of a `#[host]`
+/// pattern, as expected by `hyperdrive::support::match_host`.
+fn host_labels(host: &HostPattern) -> TokenStream {
+    let labels = host.labels().map(|label| match label {
+        Some(label) => quote!(Some(#label)),
+        None => quote!(None),
+    });
+    quote!(&[ #(#labels),* ])
+}
 
-                quote! {
-                    // Re-match the path with the right regex and get the captures
-                    let captures = REGEXES[index.expect("no regex matched, but there's placeholders?")]
-                        .as_ref()
-                        .expect("internal error: no regex for route with placeholders")
-                        .captures(request.uri().path())
-                        .expect("internal error: regex first matched but now didn't?");
-
-                    #(#parse)*
-                }
-            }
-            _ => {
-                // No route (fallback route using #[forward]), or no placeholders.
-                // Nothing to do.
-                quote!()
+/// Generates an expression of type `&'static http::Method` referring to `method`.
+///
+/// `extension_methods` is the set of methods stored in the generated `EXTENSION_METHODS` static.
+fn method_expr(extension_methods: &IndexSet<&str>, method: &str) -> TokenStream {
+    match standard_method(method) {
+        Some(method) => quote!(&http::Method::#method),
+        None => {
+            let index = extension_methods
+                .get_index_of(method)
+                .expect("internal error: unknown extension method");
+            quote!(&EXTENSION_METHODS[#index])
+        }
+    }
+}
+
+/// Generates a pattern for the `match (index, method)` in the generated code that matches requests
+/// to the `index`th path regex using `method`.
+fn method_pattern(extension_methods: &IndexSet<&str>, index: usize, method: &str) -> TokenStream {
+    match standard_method(method) {
+        Some(method) => quote!((Some(#index), &http::Method::#method)),
+        None => {
+            let method_index = extension_methods
+                .get_index_of(method)
+                .expect("internal error: unknown extension method");
+            quote! {
+                (Some(#index), method) if *method == EXTENSION_METHODS[#method_index]
             }
         }
-    };
+    }
+}
 
-    let query = if let Some(query_params_field) = data.query_params_field() {
-        let ty = &field_by_name(&query_params_field).ty;
-        let variable = Ident::new(&format!("fld_{}", query_params_field), Span::call_site());
+/// Generates a `bool` expression that checks whether `variant` (a candidate for the matched path)
+/// accepts the request.
+///
+/// Variants in `tried` never accept the request, since they already forwarded it (see
+/// `Error::forward`).
+///
+/// This checks the `#[host]` pattern of the variant, and, if `check_path` is `Some(index)`, the
+/// `FromStr` impls of all path placeholders against the `captures` of the `index`th path. If
+/// `check_content_type` is `true`, the request's `Content-Type` is checked against the variant's
+/// `#[consumes]` attribute.
+fn candidate_condition(
+    variant: &VariantData,
+    check_path: Option<usize>,
+    check_content_type: bool,
+) -> TokenStream {
+    let name = variant.variant_name();
+    let host = if variant.host().is_some() {
+        quote!(variant_matches_host(Variant::#name, host.as_deref()))
+    } else {
+        quote!(true)
+    };
+    let path = match check_path {
+        Some(index) => quote!(variant_matches_path(Variant::#name, #index, &captures[..])),
+        None => quote!(true),
+    };
+    let content_type = if check_content_type && !variant.consumes().is_empty() {
+        let media_types = variant.consumes().iter().map(|range| range.as_str());
         quote! {
-            // Parse query params
-            let raw_query = request.uri().query().unwrap_or("");
-            let #variable = match serde_urlencoded::from_str::<#ty>(raw_query) {
-                Ok(val) => val,
-                Err(e) => return Error::with_source(StatusCode::BAD_REQUEST, e).into_future(),
-            };
+            hyperdrive::support::media_type_matches(
+                content_type.as_deref(),
+                &[ #(#media_types),* ],
+            )
         }
     } else {
-        quote!()
+        quote!(true)
     };
+    quote!((!tried.contains(&Variant::#name) && #host && #path && #content_type))
+}
 
-    // Last step, chain all the asynchronous operations (guards, #[body] and #[forward]).
-    // Reverse order because we have to chain everything with `.and_then`.
+/// Generates an expression of type `Variant` that selects the first of `candidates` (for the
+/// matched path) that accepts the request (see `candidate_condition`).
+///
+/// If any candidate uses `#[produces]`, the accepting candidate whose media types are preferred by
+/// the request's `Accept` header is selected instead (see `negotiate_candidate`).
+///
+/// If none of them match, the expression evaluates to `reject_request`.
+fn select_candidate(
+    candidates: &[(VariantData, Route)],
+    check_path: Option<usize>,
+    fallback: Option<&VariantData>,
+) -> TokenStream {
+    if candidates
+        .iter()
+        .any(|(candidate, _)| !candidate.produces().is_empty())
+    {
+        return negotiate_candidate(candidates, check_path, fallback);
+    }
 
-    // Construct the final value from the `fld_X` variables
-    let construct = variant.construct(|field, index| {
-        let name = if let Some(ident) = &field.ident {
-            ident.to_string()
+    let mut select = reject_request(candidates, check_path, fallback);
+    for (i, (candidate, _)) in candidates.iter().enumerate().rev() {
+        let variant = candidate.variant_name();
+        let is_last = i == candidates.len() - 1;
+        let accepts_any = candidate.host().is_none() && candidate.consumes().is_empty();
+        select = if accepts_any && (is_last || check_path.is_none()) {
+            // Accepts every request it hasn't forwarded (if the placeholders don't parse, we fail
+            // with a 404 later)
+            quote! {
+                if !tried.contains(&Variant::#variant) {
+                    Variant::#variant
+                } else {
+                    #select
+                }
+            }
         } else {
-            index.to_string()
-        };
-        Ident::new(&format!("fld_{}", name), Span::call_site())
-    });
-    let mut future = quote! {
-        Ok(#construct).into_future()
-    };
-
-    // Read the body
-    if let Some(body) = data.body_field() {
-        let ty = &field_by_name(body).ty;
-        let var = Ident::new(&format!("fld_{}", body), Span::call_site());
-        future = quote! {
-            <#ty as FromBody>::from_body(&request, body, context.as_ref())
-                .into_future()
-                .and_then(move |#var| #future)
+            let condition = candidate_condition(candidate, check_path, true);
+            quote! {
+                if #condition {
+                    Variant::#variant
+                } else {
+                    #select
+                }
+            }
         };
-    };
-
-    // Forward to another `FromRequest` implementor (can not be combined with #[body])
-    if let Some(forward) = data.forward_field() {
-        let ty = &field_by_name(forward).ty;
-        let var = Ident::new(&format!("fld_{}", forward), Span::call_site());
-        future = quote! {{
-            <#ty as FromRequest>::from_request_and_body(&request, body, context)
-                .into_future()
-                .and_then(move |#var| #future)
-        }};
     }
 
-    // Check all guards
-    // Reverse order so guards are evaluated top to bottom in declaration order.
-    for guard in data
+    select
+}
+
+/// Generates an expression of type `Variant` that selects the candidate preferred by the request's
+/// `Accept` header among all `candidates` accepting the request.
+///
+/// Candidates without `#[produces]` are only selected if no candidate with `#[produces]` is
+/// acceptable. Among equally preferred candidates, the first one is selected.
+fn negotiate_candidate(
+    candidates: &[(VariantData, Route)],
+    check_path: Option<usize>,
+    fallback: Option<&VariantData>,
+) -> TokenStream {
+    let (conditions, qualities): (Vec<_>, Vec<_>) = candidates
+        .iter()
+        .map(|(candidate, _)| {
+            let quality = if candidate.produces().is_empty() {
+                quote!(Some(0))
+            } else {
+                let media_types = candidate.produces().iter().map(|range| range.as_str());
+                quote!(accept.quality(&[ #(#media_types),* ]))
+            };
+            (candidate_condition(candidate, check_path, true), quality)
+        })
+        .unzip();
+    let indices = 0..candidates.len();
+    let variants = candidates
+        .iter()
+        .map(|(candidate, _)| candidate.variant_name());
+    let reject = reject_request(candidates, check_path, fallback);
+
+    let select_best = indices.clone().map(|index| {
+        quote! {
+            if best.map_or(true, |(best, _)| quality > best) {
+                best = Some((quality, #index));
+            }
+        }
+    });
+    quote! {{
+        // The quality (from the `Accept` header) and index of the best candidate so far
+        let mut best: Option<(u32, usize)> = None;
+        #(
+            if #conditions {
+                if let Some(quality) = #qualities {
+                    #select_best
+                }
+            }
+        )*
+
+        match best {
+            #( Some((_, #indices)) => Variant::#variants, )*
+            _ => #reject,
+        }
+    }}
+}
+
+/// Generates the expression used when none of `candidates` accepts the request.
+///
+/// This evaluates to the `fallback` variant. If there is none, it returns an error:
+/// * "406 Not Acceptable" if a candidate only rejected the request because of its `Accept`
+///   header,
+/// * "415 Unsupported Media Type" if a candidate only rejected the request because of its
+///   `Content-Type`,
+/// * "404 Not Found" otherwise.
+fn reject_request(
+    candidates: &[(VariantData, Route)],
+    check_path: Option<usize>,
+    fallback: Option<&VariantData>,
+) -> TokenStream {
+    if let Some(fallback) = fallback {
+        let variant = fallback.variant_name();
+        return quote!(Variant::#variant);
+    }
+
+    let conditions = |filter: fn(&VariantData) -> bool, check_content_type: bool| {
+        candidates
+            .iter()
+            .filter(|(candidate, _)| filter(candidate))
+            .map(|(candidate, _)| candidate_condition(candidate, check_path, check_content_type))
+            .collect::<Vec<_>>()
+    };
+    let not_acceptable = conditions(|candidate| !candidate.produces().is_empty(), true);
+    let unsupported = conditions(|candidate| !candidate.consumes().is_empty(), false);
+
+    let mut status = quote!(StatusCode::NOT_FOUND);
+    if !unsupported.is_empty() {
+        status = quote! {
+            if #(#unsupported)||* {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            } else {
+                #status
+            }
+        };
+    }
+    if !not_acceptable.is_empty() {
+        status = quote! {
+            if #(#not_acceptable)||* {
+                StatusCode::NOT_ACCEPTABLE
+            } else {
+                #status
+            }
+        };
+    }
+
+    quote! {
+        return Error::from_status(#status).into_future()
+    }
+}
+
+/// Generates an expression of type `Option<u32>` evaluating to the rank of the route that would
+/// handle the request if the `index`th path is used, or `None` if no route would accept it.
+fn route_rank(
+    extension_methods: &IndexSet<&str>,
+    index: usize,
+    pathinfo: &PathInfo<'_>,
+) -> TokenStream {
+    let check_path = if pathinfo.regex().captures_len() > 0 {
+        Some(index)
+    } else {
+        None
+    };
+    // Like in the generated `match (index, method)`, `#[any]` routes are tried last
+    let any_method = pathinfo.any_method();
+    let arm = |pattern: TokenStream, candidates: &[(VariantData, Route)]| {
+        let (conditions, ranks): (Vec<_>, Vec<_>) = candidates
+            .iter()
+            .chain(any_method)
+            .map(|(variant, route)| {
+                (candidate_condition(variant, check_path, true), route.rank())
+            })
+            .unzip();
+        quote! {
+            #pattern => {
+                #( if #conditions { return Some(#ranks); } )*
+                None
+            }
+        }
+    };
+    let arms = pathinfo
+        .method_map()
+        .map(|(method, candidates)| {
+            arm(method_pattern(extension_methods, index, method), candidates)
+        })
+        .collect::<Vec<_>>();
+    let rest = if any_method.is_empty() {
+        quote!(_ => None,)
+    } else {
+        arm(quote!(_), &[])
+    };
+
+    quote! {
+        match (Some(#index), method) {
+            #(#arms)*
+            #rest
+        }
+    }
+}
+
+/// Generates all the code needed to build an enum variant from a matching
+/// request.
+///
+/// Returns an expression evaluating to a boxed `Future` of `Self` that fails with a
+/// `(BoxedError, Option<(hyper::Body, Self::Context)>)`. The error carries `body` and `context` if
+/// a guard failed before they were consumed, which allows forwarding the request to another
+/// variant.
+///
+/// The generated code will do the following:
+/// * If the path has any segment placeholders:
+///   * Look up the captured segment of each placeholder field in the matched route
+///   * Call `FromStr` on all captured segments (fields missing from the route are `None` or
+///     `Default::default()`)
+/// * If it has a `#[host]` with placeholders:
+///   * Match the host against the pattern again to obtain the captured labels
+///   * Call `FromStr` on all captured labels
+/// * If it has `query_params`
+///   * Deserialize from ?these&query=parameters
+/// * For each `#[query]`, `#[header]` and `#[cookie]` field
+///   * Parse the value (`None` if it's missing and the field is an `Option`)
+/// * For each guard (= field that isn't mentioned in any attribute)
+///   * Chain all calls to the `from_request` methods
+/// * If it has a `body`
+///   * Chain the call to its `from_body` method
+///
+/// The code will also assume:
+/// * That `request` is the incoming request, and can be consumed.
+/// * That `body` and `context` are the request body and context.
+/// * That `index` and `captures` hold the index of the matched path (in `pathmap`) and the
+///   placeholders captured from it.
+/// * That `host` is the request host, if the variant has a `#[host]` attribute.
+fn construct_variant(
+    variant: &VariantInfo<'_>,
+    data: &VariantData,
+    pathmap: &PathMap,
+) -> TokenStream {
+    let field_by_name = |name: &Ident| field_by_name(variant, name);
+
+    // Each placeholder field is looked up in the captures of the route that matched, which is
+    // determined by the matched path (`index`).
+    let mut placeholder_fields = Vec::new();
+    for route in data.routes() {
+        for name in route.placeholders() {
+            if !placeholder_fields.contains(&name) {
+                placeholder_fields.push(name);
+            }
+        }
+    }
+    let placeholders = placeholder_fields.into_iter().map(|field_name| {
+        let field = field_by_name(field_name);
+        let variable = Ident::new(&format!("fld_{}", field_name), Span::call_site());
+        let ty = placeholder_ty(data, field);
+
+        // The parsed capture in every path matched by a route binding the field
+        let mut seen = Vec::new();
+        let (indices, parses): (Vec<_>, Vec<_>) = data
+            .routes()
+            .iter()
+            .filter_map(|route| {
+                let position = route.placeholders().iter().position(|p| p == field_name)?;
+                let index = pathmap.path_index(route);
+                if seen.contains(&index) {
+                    return None;
+                }
+                seen.push(index);
+                let input = quote!(captures[#position]);
+                // Left out optional placeholders aren't captured
+                let omitted = if route.path().is_optional(field_name) {
+                    Some(quote!(captures[#position].is_empty()))
+                } else {
+                    None
+                };
+                Some((
+                    (index, omitted),
+                    parse_placeholder(data, route, field_name, ty, input),
+                ))
+            })
+            .unzip();
+        let bound_by_all = data.routes().iter().all(|route| {
+            route.placeholders().contains(field_name) && !route.path().is_optional(field_name)
+        });
+
+        if bound_by_all && parses.len() == 1 {
+            // All routes share the same path, so the capture is always there
+            let parse = &parses[0];
+            return quote! {
+                let #variable = match #parse {
+                    Ok(v) => v,
+                    Err(e) => {
+                        return Error::with_source(StatusCode::NOT_FOUND, e)
+                            .into_future();
+                    }
+                };
+            };
+        }
+
+        let (present, missing) = if data.is_optional_placeholder(field_name) {
+            (quote!(Some(v)), quote!(None))
+        } else if data.has_default(field_name) {
+            (quote!(v), quote!(Default::default()))
+        } else {
+            (
+                quote!(v),
+                quote!(unreachable!("internal error: matched route doesn't bind placeholder")),
+            )
+        };
+        let arms = indices
+            .iter()
+            .zip(&parses)
+            .map(|((index, omitted), parse)| {
+                let omitted = omitted
+                    .as_ref()
+                    .map(|omitted| quote!(Some(#index) if #omitted => #missing,));
+                quote! {
+                    #omitted
+                    Some(#index) => match #parse {
+                        Ok(v) => #present,
+                        Err(e) => {
+                            return Error::with_source(StatusCode::NOT_FOUND, e).into_future();
+                        }
+                    },
+                }
+            });
+        quote! {
+            let #variable = match index {
+                #(#arms)*
+                _ => #missing,
+            };
+        }
+    });
+    let placeholders = quote!(#(#placeholders)*);
+
+    let host_placeholders = match data.host() {
+        Some(host) if !host.placeholders().is_empty() => {
+            let labels = host_labels(host);
+            let parse = host
+                .placeholders()
+                .iter()
+                .enumerate()
+                .map(|(i, field_name)| {
+                    let variable = Ident::new(&format!("fld_{}", field_name), Span::call_site());
+                    let ty = &field_by_name(field_name).ty;
+                    quote! {
+                        let #variable = match <#ty as FromStr>::from_str(host_captures[#i]) {
+                            Ok(v) => v,
+                            Err(e) => {
+                                return Error::with_source(StatusCode::NOT_FOUND, e)
+                                    .into_future();
+                            }
+                        };
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            quote! {
+                // Re-match the host to get the captures
+                let host_captures = host
+                    .as_ref()
+                    .and_then(|host| hyperdrive::support::match_host(host, #labels))
+                    .expect("internal error: host first matched but now didn't?");
+
+                #(#parse)*
+            }
+        }
+        _ => quote!(),
+    };
+
+    let query_fields = data.query_fields();
+    let query_pairs = if query_fields.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            let query_pairs = match hyperdrive::support::QueryPairs::parse(raw_query) {
+                Ok(pairs) => pairs,
+                Err(e) => return Error::with_source(StatusCode::BAD_REQUEST, e).into_future(),
+            };
+        }
+    };
+    let query_params = data.query_params_field().map(|query_params_field| {
+        let ty = &field_by_name(query_params_field).ty;
+        let variable = Ident::new(&format!("fld_{}", query_params_field), Span::call_site());
+        // Parameters bound to `#[query]` fields are not passed to the struct
+        let deserialize = if query_fields.is_empty() {
+            quote!(hyperdrive::query::from_str::<#ty>(raw_query))
+        } else {
+            let names = query_fields.iter().map(|(_, name)| name);
+            quote!(query_pairs.deserialize_without::<#ty>(&[ #(#names),* ]))
+        };
+        quote! {
+            let #variable = match #deserialize {
+                Ok(val) => val,
+                Err(e) => return Error::with_source(StatusCode::BAD_REQUEST, e).into_future(),
+            };
+        }
+    });
+    let query_fields = query_fields.iter().map(|(field, name)| {
+        parse_param(
+            field,
+            name,
+            "query parameter",
+            quote!(hyperdrive::support::QueryPairs::parse_param),
+            quote!(&query_pairs),
+        )
+    });
+    let query = if query_params.is_none() && data.query_fields().is_empty() {
+        quote!()
+    } else {
+        quote! {
+            // Parse query params
+            let raw_query = request.uri().query().unwrap_or("");
+            #query_pairs
+            #query_params
+            #(#query_fields)*
+        }
+    };
+
+    let headers = data.header_fields().iter().map(|(field, name)| {
+        parse_param(
+            field,
+            name,
+            "header",
+            quote!(hyperdrive::support::parse_header),
+            quote!(request),
+        )
+    });
+    let cookies = data.cookie_fields().iter().map(|(field, name)| {
+        parse_param(
+            field,
+            name,
+            "cookie",
+            quote!(hyperdrive::support::parse_cookie),
+            quote!(request),
+        )
+    });
+
+    // Last step, chain all the asynchronous operations (guards, #[body] and #[forward]).
+    // Reverse order because we have to chain everything with `.and_then`.
+    //
+    // The chain fails with a `(BoxedError, Option<(hyper::Body, Self::Context)>)`. As long as
+    // `body` and `context` haven't been consumed, a failing guard hands them back so that the
+    // request can be forwarded to the next variant.
+
+    // Construct the final value from the `fld_X` variables
+    let construct = variant.construct(|field, index| {
+        let name = if let Some(ident) = &field.ident {
+            ident.to_string()
+        } else {
+            index.to_string()
+        };
+        Ident::new(&format!("fld_{}", name), Span::call_site())
+    });
+    let mut future = quote! {
+        Ok(#construct).into_future()
+    };
+
+    // Read the body
+    if let Some(body) = data.body_field() {
+        let ty = &field_by_name(body).ty;
+        let var = Ident::new(&format!("fld_{}", body), Span::call_site());
+        future = quote! {
+            <#ty as FromBody>::from_body(&request, body, context.as_ref())
+                .into_future()
+                .map_err(|e| (e, None))
+                .and_then(move |#var| #future)
+        };
+    };
+
+    // Forward to another `FromRequest` implementor (can not be combined with #[body])
+    if let Some(forward) = data.forward_field() {
+        let ty = &field_by_name(forward).ty;
+        let var = Ident::new(&format!("fld_{}", forward), Span::call_site());
+        future = quote! {{
+            <#ty as FromRequest>::from_request_and_body(&request, body, context)
+                .into_future()
+                .map_err(|e| (e, None))
+                .and_then(move |#var| #future)
+        }};
+    }
+
+    // Whether the rest of the chain still needs `body` and `context`
+    let mut needs_state = data.body_field().is_some() || data.forward_field().is_some();
+
+    // Checks the guard `ty`, storing it in `var`, before continuing with `future`
+    let mut check_guard = |ty: &syn::Type, var: TokenStream, future: TokenStream| {
+        let state = if needs_state {
+            quote!(body, context)
+        } else {
+            quote!(_, _)
+        };
+        needs_state = true;
+        quote! {
+            <#ty as Guard>::from_request(&request, context.as_ref())
+                .into_future()
+                .then(move |result| match result {
+                    Ok(guard) => Ok((guard, body, context)),
+                    Err(e) => Err((e, Some((body, context)))),
+                })
+                .and_then(move |(#var, #state)| #future)
+        }
+    };
+
+    // Check all guard fields
+    // Reverse order so guards are evaluated top to bottom in declaration order.
+    for guard in data
         .guard_fields()
         .iter()
         .map(|fld| fld.ident.clone().unwrap())
@@ -782,11 +1624,13 @@ fn construct_variant(variant: &VariantInfo<'_>, data: &VariantData) -> TokenStre
     {
         let ty = &field_by_name(&guard).ty;
         let var = Ident::new(&format!("fld_{}", guard), Span::call_site());
-        future = quote! {
-            <#ty as Guard>::from_request(&request, context.as_ref())
-                .into_future()
-                .and_then(move |#var| #future)
-        };
+        future = check_guard(ty, quote!(#var), future);
+    }
+
+    // `#[guard]` attributes are checked before the guard fields, those on the enum first. A failing
+    // guard short-circuits the chain just like a guard field.
+    for ty in data.guards().iter().rev() {
+        future = check_guard(ty, quote!(_), future);
     }
 
     quote! {{
@@ -794,32 +1638,43 @@ fn construct_variant(variant: &VariantInfo<'_>, data: &VariantData) -> TokenStre
 
         #placeholders
 
+        #host_placeholders
+
         #query
 
-        let request = Arc::clone(request);
-        let future = #future;
+        #(#headers)*
 
-        Box::new(future) as DefaultFuture<Self, BoxedError>
+        #(#cookies)*
+
+        let request = Arc::clone(request);
+        let future: Box<
+            dyn Future<
+                Item = Self,
+                Error = (BoxedError, Option<(hyper::Body, <Self as FromRequest>::Context)>),
+            > + Send,
+        > = Box::new(#future);
+
+        future
     }}
 }
 
 #[cfg(test)]
 mod tests {
-    use super::derive_from_request;
-    use synstructure::test_derive;
+    use super::expand;
+    use synstructure::Structure;
 
     /// Expands the given item by putting a `#[derive(FromRequest)]` on it.
+    ///
+    /// Panics with all error messages (one per line) if the derive rejects the item.
     macro_rules! expand {
         (
             $i:item
-        ) => {
-            test_derive! {
-                derive_from_request {
-                    $i
-                }
-                expands to {} no_build
+        ) => {{
+            let input: syn::DeriveInput = syn::parse_quote!($i);
+            if let Err(errors) = expand(Structure::new(&input)) {
+                panic!("{}", errors);
             }
-        };
+        }};
     }
 
     #[test]
@@ -861,7 +1716,9 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "different placeholders used")]
+    #[should_panic(
+        expected = r#"routes `#[get("/{ph}")]` and `#[post("/{pl}")]` on variant `Variant` match the same paths"#
+    )]
     fn wrong_routes() {
         expand! {
             enum Routes {
@@ -872,6 +1729,66 @@ mod tests {
         }
     }
 
+    #[test]
+    #[should_panic(
+        expected = r#"field `name` is not bound by route `#[get("/u/{id}")]`, so it has to be an `Option` or marked with `#[default]`"#
+    )]
+    fn placeholder_missing_from_route() {
+        expand! {
+            enum Routes {
+                #[get("/u/{id}")]
+                #[get("/users/{name}/{id}")]
+                User {
+                    id: u32,
+                    name: String,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"field `month` is bound to an optional placeholder in route `#[get("/archive/{year}/{month?}")]`, so it has to be an `Option` or marked with `#[default]`"#
+    )]
+    fn optional_placeholder_not_option() {
+        expand! {
+            #[get("/archive/{year}/{month?}")]
+            struct Archive {
+                year: u16,
+                month: u8,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "optional placeholders must only be followed by other optional placeholders"
+    )]
+    fn optional_placeholder_not_last() {
+        expand! {
+            #[get("/archive/{year?}/{month}")]
+            struct Archive {
+                year: Option<u16>,
+                month: u8,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[default] can only be used on fields bound to a path placeholder")]
+    fn default_on_guard() {
+        expand! {
+            enum Routes {
+                #[get("/u/{id}")]
+                User {
+                    id: u32,
+                    #[default]
+                    guard: MyGuard,
+                },
+            }
+        }
+    }
+
     #[test]
     #[should_panic(
         expected = r#"duplicate route: `#[get("/{ph}")]` on `Variant` matches the same requests as `#[get("/{pl}")]` on `Var`"#
@@ -920,14 +1837,14 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "...-placeholders must not be followed by anything")]
+    #[should_panic(expected = "...-placeholders can only be followed by literal segments")]
     fn any_placeholder1() {
         expand! {
             enum Routes {
-                #[get("/{ph}/{rest...}/")]
+                #[get("/{rest...}/{ph?}")]
                 Variant {
                     #[allow(unused)]
-                    ph: u32,
+                    ph: Option<u32>,
                     #[allow(unused)]
                     rest: String,
                 },
@@ -936,7 +1853,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "...-placeholders must not be followed by anything")]
+    #[should_panic(expected = "...-placeholders can only be followed by literal segments")]
     fn any_placeholder2() {
         expand! {
             enum Routes {
@@ -952,7 +1869,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "...-placeholders must not be followed by anything")]
+    #[should_panic(expected = "...-placeholders can only be followed by literal segments")]
     fn any_placeholder3() {
         expand! {
             enum Routes {
@@ -1038,5 +1955,680 @@ mod tests {
         }
     }
 
+    #[test]
+    #[should_panic(expected = "#[raw] can only be used on fields bound to a path placeholder")]
+    fn raw_guard() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Index {
+                    #[raw]
+                    guard: MyGuard,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid HTTP method `PROP FIND` in `#[route]` attribute")]
+    fn route_invalid_method() {
+        expand! {
+            enum Routes {
+                #[route("PROP FIND", "/")]
+                Index,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "use `#[any(\"/path\")]` to match requests using any method")]
+    fn route_any_method() {
+        expand! {
+            enum Routes {
+                #[route("*", "/")]
+                Index,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"duplicate route: `#[any("/health")]` on `Health` matches the same requests as `#[any("/health")]` on `Status`"#
+    )]
+    fn any_duplicate() {
+        expand! {
+            enum Routes {
+                #[any("/health")]
+                Health,
+
+                #[any("/health")]
+                Status,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`#[route]` attributes must be of the form")]
+    fn route_missing_method() {
+        expand! {
+            enum Routes {
+                #[route("/")]
+                Index,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"duplicate route: `#[route(PROPFIND, "/{a}")]` on `A` matches the same requests as `#[route(PROPFIND, "/{b}")]` on `B`"#
+    )]
+    fn route_duplicate() {
+        expand! {
+            enum Routes {
+                #[route(PROPFIND, "/{a}")]
+                A { a: u8 },
+
+                #[route(PROPFIND, "/{b}")]
+                B { b: u8 },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"duplicate route: `#[get("/")]` on `A` matches the same requests as `#[get("/")]` on `B`"#
+    )]
+    fn route_duplicate_standard() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                A,
+
+                #[route(GET, "/")]
+                B,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "placeholders `{a}` and `{b}` must be separated by literal text")]
+    fn adjacent_placeholders() {
+        expand! {
+            enum Routes {
+                #[get("/{a}{b}")]
+                Index { a: u8, b: u8 },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "...-placeholders must make up an entire path segment (in `v{rest...}`)")]
+    fn mixed_rest_placeholder() {
+        expand! {
+            enum Routes {
+                #[get("/v{rest...}")]
+                Index { rest: String },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "unterminated placeholder in path segment `{a}.{b`")]
+    fn unterminated_placeholder() {
+        expand! {
+            enum Routes {
+                #[get("/{a}.{b")]
+                Index { a: u8 },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[prefix] must start with `/` and must not end with `/`")]
+    fn prefix_trailing_slash() {
+        expand! {
+            #[prefix("/api/")]
+            enum Routes {
+                #[get("/")]
+                Index,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`#[prefix]` is not valid on enum variants")]
+    fn prefix_on_variant() {
+        expand! {
+            enum Routes {
+                #[prefix("/api")]
+                #[get("/")]
+                Index,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"route `#[get("/api/users/me")]` overlaps with previously defined route `#[get("/api/users/{id}")]`"#
+    )]
+    fn prefix_overlap() {
+        expand! {
+            #[prefix("/api")]
+            enum Routes {
+                #[get("/users/{id}")]
+                User { id: u32 },
+
+                #[get("/users/me")]
+                Me,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"duplicate route: `#[get("/")]` on `Tenant` matches the same requests as `#[get("/")]` on `Other`"#
+    )]
+    fn host_duplicate() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                #[host("{tenant}.example.com")]
+                Tenant { tenant: String },
+
+                #[get("/")]
+                #[host("www.{domain}.com")]
+                Other { domain: String },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"route `#[get("/users/me")]` overlaps with previously defined route `#[get("/users/{id}")]`"#
+    )]
+    fn host_path_overlap() {
+        // Hosts can only tell apart routes with identical paths
+        expand! {
+            enum Routes {
+                #[get("/users/{id}")]
+                #[host("api.example.com")]
+                User { id: u32 },
+
+                #[get("/users/me")]
+                #[host("www.example.com")]
+                Me,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[host] can only be used together with a route attribute")]
+    fn host_without_route() {
+        expand! {
+            enum Routes {
+                #[host("{tenant}.example.com")]
+                Fallback {
+                    #[forward]
+                    inner: Inner,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "placeholder `{id}` is used in both the host and the path of variant `User`"
+    )]
+    fn host_and_path_placeholder() {
+        expand! {
+            enum Routes {
+                #[get("/users/{id}")]
+                #[host("{id}.example.com")]
+                User { id: u32 },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"duplicate route: `#[get("/users")]` on `Users` matches the same requests as `#[get("/users/")]` on `UsersSlash`"#
+    )]
+    fn trailing_slash_duplicate() {
+        expand! {
+            #[trailing_slash(match_both)]
+            enum Routes {
+                #[get("/users")]
+                Users,
+
+                #[get("/users/")]
+                UsersSlash,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[trailing_slash] must be one of")]
+    fn trailing_slash_invalid() {
+        expand! {
+            #[trailing_slash(lenient)]
+            enum Routes {
+                #[get("/")]
+                Index,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`rank` must be a non-negative integer (eg. `rank = 1`)")]
+    fn rank_invalid() {
+        expand! {
+            enum Routes {
+                #[get("/", rank = "high")]
+                Index,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"route `#[get("/users/me", rank = 1)]` overlaps with previously defined route `#[get("/users/{id}", rank = 1)]` (both would match path `/users/me`); use `rank = N` to specify which one is tried first"#
+    )]
+    fn rank_overlap() {
+        expand! {
+            enum Routes {
+                #[get("/users/{id}", rank = 1)]
+                User { id: u32 },
+
+                #[get("/users/me", rank = 1)]
+                Me,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"duplicate route: `#[post("/items")]` on `Any` matches the same requests as `#[post("/items")]` on `Json`"#
+    )]
+    fn consumes_duplicate() {
+        expand! {
+            enum Routes {
+                #[post("/items")]
+                #[consumes("application/*")]
+                Any,
+
+                #[post("/items")]
+                #[consumes("text/plain", "application/json")]
+                Json,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[consumes] can only be used together with a route attribute")]
+    fn consumes_without_route() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Index,
+
+                #[consumes("application/json")]
+                Fallback {
+                    #[forward]
+                    inner: Inner,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"#[consumes] must be of the form `#[consumes("application/json", ...)]`"#
+    )]
+    fn consumes_empty() {
+        expand! {
+            enum Routes {
+                #[post("/")]
+                #[consumes()]
+                Index,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = r#"duplicate route: `#[get("/")]` on `Json` matches the same requests as `#[get("/")]` on `Api`"#
+    )]
+    fn produces_duplicate() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                #[produces("text/html", "application/json")]
+                Json,
+
+                #[get("/")]
+                #[produces("application/json")]
+                Api,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "#[produces] requires concrete media types, but `text/*` contains a wildcard"
+    )]
+    fn produces_wildcard() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                #[produces("text/*")]
+                Index,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid header name `X Request Id`")]
+    fn header_invalid_name() {
+        expand! {
+            #[get("/")]
+            struct Index {
+                #[header("X Request Id")]
+                request_id: String,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "header `x-request-id` is bound to multiple fields (`id` is one of them)"
+    )]
+    fn header_duplicate() {
+        expand! {
+            #[get("/")]
+            struct Index {
+                #[header("X-Request-Id")]
+                request_id: String,
+                #[header("x-request-id")]
+                id: u64,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[body]/#[query_params]/#[query]/#[header]/#[cookie]/#[forward] must only be specified once")]
+    fn header_and_body() {
+        expand! {
+            #[post("/")]
+            struct Index {
+                #[body]
+                #[header("Content-Length")]
+                data: String,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "cannot mark a field with #[header] when the variant doesn't have a route attribute"
+    )]
+    fn header_without_route() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Index,
+
+                Fallback {
+                    #[forward]
+                    inner: Inner,
+                    #[header("X-Request-Id")]
+                    request_id: String,
+                },
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = r#"#[cookie] must be of the form `#[cookie("name")]`"#)]
+    fn cookie_malformed() {
+        expand! {
+            #[get("/")]
+            struct Index {
+                #[cookie(session)]
+                session: String,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid cookie name `session=1`")]
+    fn cookie_invalid_name() {
+        expand! {
+            #[get("/")]
+            struct Index {
+                #[cookie("session=1")]
+                session: String,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "cookie `session` is bound to multiple fields (`id` is one of them)")]
+    fn cookie_duplicate() {
+        expand! {
+            #[get("/")]
+            struct Index {
+                #[cookie("session")]
+                session: String,
+                #[cookie("Session")]
+                other: String,
+                #[cookie("session")]
+                id: u64,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "query parameter `page` is bound to multiple fields (`p` is one of them)"
+    )]
+    fn query_duplicate() {
+        expand! {
+            #[get("/")]
+            struct Index {
+                #[query]
+                page: u32,
+                #[query("page")]
+                p: u32,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "#[body]/#[query_params]/#[query]/#[header]/#[cookie]/#[forward] must only be specified once"
+    )]
+    fn query_and_query_params() {
+        expand! {
+            #[get("/")]
+            struct Index {
+                #[query]
+                #[query_params]
+                page: u32,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[query] requires a non-empty parameter name")]
+    fn query_empty_name() {
+        expand! {
+            #[get("/")]
+            struct Index {
+                #[query("")]
+                page: u32,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "tuple variants are not supported (`Routes::Item`)")]
+    fn tuple_variant() {
+        expand! {
+            enum Routes {
+                #[get("/{id}")]
+                Item(u32),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[guard] must be of the form `#[guard(MyGuard)]`")]
+    fn guard_malformed() {
+        expand! {
+            #[guard = "Admin"]
+            enum Routes {
+                #[get("/")]
+                Index,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "#[guard] is not valid on fields")]
+    fn guard_on_field() {
+        expand! {
+            #[get("/")]
+            struct Index {
+                #[guard(Admin)]
+                admin: Admin,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "#[guard] can only be used on variants with a route attribute or a `#[forward]` field"
+    )]
+    fn guard_unconstructible() {
+        expand! {
+            enum Routes {
+                #[get("/")]
+                Index,
+
+                #[guard(Admin)]
+                Unrouted,
+            }
+        }
+    }
+
+    #[test]
+    fn multiple_errors() {
+        let input: syn::DeriveInput = syn::parse_quote! {
+            #[prefix("/api/")]
+            enum Routes {
+                #[get("/users/{id}")]
+                User { id: u32 },
+
+                #[get("/users/{name}")]
+                Named { name: String },
+
+                #[post("/users/{id...}/{x}")]
+                Broken { id: u32, x: u32 },
+
+                #[get("/posts")]
+                #[consumes("json")]
+                Posts {
+                    #[header("X Page")]
+                    page: u32,
+                },
+            }
+        };
+
+        let errors = expand(Structure::new(&input)).err().unwrap().to_string();
+        assert_eq!(
+            errors.lines().collect::<Vec<_>>(),
+            [
+                "#[prefix] must start with `/` and must not end with `/`",
+                "...-placeholders can only be followed by literal segments",
+                "invalid media type `json` (expected `type/subtype`, `type/*` or `*/*`)",
+                "invalid header name `X Page`",
+                "duplicate route: `#[get(\"/users/{id}\")]` on `User` matches the same requests as \
+                 `#[get(\"/users/{name}\")]` on `Named`",
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "the reverse routing function of variant `To` would be called \
+                               `to_path`, which clashes with the generated `to_path` method \
+                               (rename the variant)")]
+    fn path_fn_clashes_with_to_path() {
+        expand! {
+            enum Routes {
+                #[get("/to")]
+                To,
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the reverse routing functions of variants `UserInfo` and \
+                               `User_Info` would both be called `user_info_path` (rename one of \
+                               the variants)")]
+    fn path_fn_duplicate() {
+        expand! {
+            enum Routes {
+                #[get("/a")]
+                UserInfo,
+
+                #[get("/b")]
+                User_Info,
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_route_is_not_unconstructible() {
+        let errors =
+            |input: syn::DeriveInput| expand(Structure::new(&input)).err().unwrap().to_string();
+
+        let invalid = [
+            errors(syn::parse_quote! {
+                enum Routes {
+                    #[get("/a/{b:(}")]
+                    A { b: String },
+                }
+            }),
+            errors(syn::parse_quote! {
+                enum Routes {
+                    #[get("/a/{b?}/c")]
+                    A { b: Option<String> },
+                }
+            }),
+            errors(syn::parse_quote! {
+                #[get("/a/{b}{c}")]
+                struct Route {
+                    b: String,
+                    c: String,
+                }
+            }),
+            errors(syn::parse_quote! {
+                enum Routes {
+                    #[get("/a/{b...}/{c...}")]
+                    A {
+                        b: String,
+                        c: String,
+                        #[header("X-Page")]
+                        page: u32,
+                    },
+                }
+            }),
+        ];
+        for errors in &invalid {
+            assert!(!errors.contains("constructible"), "{}", errors);
+            assert!(!errors.contains("route attribute"), "{}", errors);
+        }
+    }
+
     // TODO write lots more tests
 }
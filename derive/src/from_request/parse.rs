@@ -1,9 +1,14 @@
-use crate::utils::ByProxy;
+use super::constraint::Constraint;
+use super::host::HostPattern;
+use super::media_type::{ranges_overlap, MediaRange};
+use crate::utils::{option_inner, ByProxy, Errors};
 use indexmap::{map::Entry, IndexMap};
-use proc_macro2::{Ident, Span};
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::ToTokens;
 use regex::Regex;
-use std::{fmt, slice};
-use syn::{Attribute, Field, Lit, Meta, NestedMeta};
+use regex_syntax::hir::ClassUnicode;
+use std::{cmp::Ordering, fmt, iter, slice};
+use syn::{Attribute, Field, Fields, Lit, Meta, MetaList, NestedMeta};
 use synstructure::VariantAst;
 
 // Attributes need to be kept in sync with lib.rs
@@ -12,11 +17,35 @@ const METHOD_ATTRS: &[&str] = &[
     "get", "head", "post", "put", "delete", "connect", "options", "trace", "patch",
 ];
 
+/// The method of `#[any]` routes, which match requests using any method.
+///
+/// `*` is rejected by `#[route]`, so this never clashes with an actual method.
+pub const ANY_METHOD: &str = "*";
+
 /// All attributes used by this custom derive.
 fn our_attrs() -> impl Iterator<Item = &'static str> {
     METHOD_ATTRS
         .iter()
-        .chain(&["context", "body", "forward", "query_params"])
+        .chain(&[
+            "route",
+            "any",
+            "context",
+            "prefix",
+            "host",
+            "consumes",
+            "produces",
+            "trailing_slash",
+            "guard",
+            "body",
+            "forward",
+            "query_params",
+            "query",
+            "header",
+            "cookie",
+            "raw",
+            // `default` is left out on purpose, since `#[derive(Default)]` uses it on enum
+            // variants. It's only handled on fields.
+        ])
         .cloned()
 }
 
@@ -31,36 +60,192 @@ fn is_method(name: &Ident) -> bool {
     METHOD_ATTRS.iter().cloned().find(|a| name == *a).is_some()
 }
 
+/// If `method` is one of the methods with a dedicated attribute, returns the name of the
+/// associated constant on `http::Method`.
+pub fn standard_method(method: &str) -> Option<Ident> {
+    METHOD_ATTRS
+        .iter()
+        .find(|attr| attr.to_uppercase() == method)
+        .map(|_| Ident::new(method, Span::call_site()))
+}
+
+/// Returns whether `c` may appear in an HTTP method or media type (RFC 7230 `tchar`).
+pub fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
 /// Parsed attributes attached to the item that does `#[derive(FromRequest)]`.
 pub struct ItemData {
     name: Ident,
     context: Option<syn::Type>,
+    /// Path prefix prepended to all routes.
+    prefix: Option<String>,
+    /// Host pattern used by all variants without their own `#[host]` attribute.
+    host: Option<HostPattern>,
+    /// How requests differing from a route only in a trailing slash are treated.
+    trailing_slash: Option<TrailingSlash>,
+    /// Types of the guards specified with `#[guard]` on an enum, which apply to all variants.
+    guards: Vec<syn::Type>,
+}
+
+/// The policy specified with `#[trailing_slash(...)]`.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// `/foo` and `/foo/` are different paths (the default).
+    Strict,
+    /// Requests using the non-canonical form get redirected to the path declared in the route.
+    Redirect,
+    /// Routes match their path with and without a trailing slash.
+    MatchBoth,
 }
 
 impl ItemData {
-    pub fn parse(name: Ident, attrs: &[Attribute], is_struct: bool) -> Self {
+    /// Parses the attributes on the item, recording any errors in `errors`.
+    pub fn parse(name: Ident, attrs: &[Attribute], is_struct: bool, errors: &mut Errors) -> Self {
         let mut context = None;
+        let mut prefix = None;
+        let mut host = None;
+        let mut trailing_slash = None;
+        let mut guards = Vec::new();
 
         for attr in attrs {
-            let name = attr.parse_meta().unwrap().name();
-            if name == "context" {
-                let ty = syn::parse2(attr.tts.clone()).expect("#[context] must be given a type");
-                insert("#[context]", &mut context, ty);
+            if is_attr(attr, "guard") {
+                // On structs, `#[guard]` is handled by `VariantData::parse`
+                if !is_struct {
+                    guards.extend(errors.ok(parse_guard(attr)));
+                }
+                continue;
+            }
+
+            let meta = match parse_meta(attr, errors) {
+                Some(meta) => meta,
+                None => continue,
+            };
+            let name = meta.name();
+            if name == "prefix" {
+                let result = match &meta {
+                    Meta::List(list) => match list.nested.iter().collect::<Vec<_>>().as_slice() {
+                        [NestedMeta::Literal(Lit::Str(path))] => {
+                            let path = path.value();
+                            if !path.starts_with('/') || path.ends_with('/') {
+                                Err(syn::Error::new_spanned(
+                                    &list.nested,
+                                    "#[prefix] must start with `/` and must not end with `/`",
+                                ))
+                            } else {
+                                Ok(path)
+                            }
+                        }
+                        _ => Err(syn::Error::new_spanned(
+                            &meta,
+                            "#[prefix] must be of the form `#[prefix(\"/path\")]`",
+                        )),
+                    },
+                    _ => Err(syn::Error::new_spanned(
+                        &meta,
+                        "#[prefix] must be of the form `#[prefix(\"/path\")]`",
+                    )),
+                };
+                if let Some(path) = errors.ok(result) {
+                    errors.ok(insert("#[prefix]", &mut prefix, path, &meta));
+                }
+            } else if name == "host" {
+                if let Some(pattern) = errors.ok(parse_host(&meta)) {
+                    errors.ok(insert("#[host]", &mut host, pattern, &meta));
+                }
+            } else if name == "trailing_slash" {
+                let policy = match &meta {
+                    Meta::List(list) => match list.nested.iter().collect::<Vec<_>>().as_slice() {
+                        [NestedMeta::Meta(Meta::Word(policy))] if policy == "strict" => {
+                            Some(TrailingSlash::Strict)
+                        }
+                        [NestedMeta::Meta(Meta::Word(policy))] if policy == "redirect" => {
+                            Some(TrailingSlash::Redirect)
+                        }
+                        [NestedMeta::Meta(Meta::Word(policy))] if policy == "match_both" => {
+                            Some(TrailingSlash::MatchBoth)
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                match policy {
+                    Some(policy) => {
+                        errors.ok(insert("#[trailing_slash]", &mut trailing_slash, policy, &meta));
+                    }
+                    None => errors.push(syn::Error::new_spanned(
+                        &meta,
+                        "#[trailing_slash] must be one of `#[trailing_slash(strict)]`, `#[trailing_slash(redirect)]` or `#[trailing_slash(match_both)]`",
+                    )),
+                }
+            } else if name == "context" {
+                match syn::parse2(attr.tts.clone()) {
+                    // `#[context(MyContext)]` is parsed as a parenthesized type
+                    Ok(syn::Type::Paren(paren)) => {
+                        errors.ok(insert("#[context]", &mut context, *paren.elem, &meta));
+                    }
+                    Ok(ty) => {
+                        errors.ok(insert("#[context]", &mut context, ty, &meta));
+                    }
+                    Err(_) => errors.push(syn::Error::new_spanned(
+                        &meta,
+                        "#[context] must be given a type",
+                    )),
+                }
             } else if known_attr(&name) && !is_struct {
-                panic!(
-                    "`#[{}]` is not valid on enums (did you mean to place it on a variant instead?)",
-                    name
-                );
+                errors.push(syn::Error::new_spanned(
+                    &meta,
+                    format!(
+                        "`#[{}]` is not valid on enums (did you mean to place it on a variant instead?)",
+                        name
+                    ),
+                ));
             }
         }
 
-        Self { name, context }
+        Self {
+            name,
+            context,
+            prefix,
+            host,
+            trailing_slash,
+            guards,
+        }
     }
 
     /// Returns the custom context type (`None` if none was specified).
     pub fn context(&self) -> Option<&syn::Type> {
         self.context.as_ref()
     }
+
+    /// Returns the path prefix specified with `#[prefix]` (`""` if none was specified).
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_ref().map_or("", String::as_str)
+    }
+
+    /// Returns the host pattern specified with `#[host]` on the item.
+    pub fn host(&self) -> Option<&HostPattern> {
+        self.host.as_ref()
+    }
+
+    /// Returns the types of the guards that apply to all variants.
+    pub fn guards(&self) -> &[syn::Type] {
+        &self.guards
+    }
+
+    /// Returns the trailing slash policy (`Strict` if none was specified).
+    pub fn trailing_slash(&self) -> TrailingSlash {
+        self.trailing_slash.unwrap_or(TrailingSlash::Strict)
+    }
+
+    /// Parses the path of a route attribute, applying `#[prefix]` and `#[trailing_slash]`.
+    fn route_path(&self, path: String) -> Result<RoutePath, String> {
+        let mut path = RoutePath::parse(prefixed(self.prefix(), path))?;
+        if self.trailing_slash() == TrailingSlash::MatchBoth {
+            path.ignore_trailing_slash();
+        }
+        Ok(path)
+    }
 }
 
 /// Attribute data attached to an enum variant or struct.
@@ -68,6 +253,8 @@ impl ItemData {
 pub struct VariantData {
     /// Name of the variant.
     name: Ident,
+    /// The doc comment on the variant (or struct), with the leading space of each line removed.
+    doc: String,
     /// The parsed HTTP routes. There's one for each `#[method]`-style attribute
     /// on the variant.
     ///
@@ -78,11 +265,42 @@ pub struct VariantData {
     /// If this is empty and there's no `forward_field`, then this variant will not be created by
     /// the derived `FromRequest` implementation.
     routes: Vec<Route>,
+    /// Whether the variant has a route attribute, even if it failed to parse (and so isn't in
+    /// `routes`). Used to avoid follow-up errors that would ask for a route attribute.
+    has_route_attr: bool,
+    /// The host pattern the request host has to match (from `#[host]` on the variant, or else on
+    /// the item).
+    host: Option<HostPattern>,
+    /// Media types accepted in the request's `Content-Type` (from `#[consumes]`). Empty if any
+    /// request is accepted.
+    consumes: Vec<MediaRange>,
+    /// Media types of the response, negotiated using the request's `Accept` header (from
+    /// `#[produces]`). Empty if the variant doesn't take part in content negotiation.
+    produces: Vec<MediaRange>,
+    /// Types of the guards specified with `#[guard]` on the enum, followed by the ones on the
+    /// variant (or struct). They're checked before any guard fields.
+    guards: Vec<syn::Type>,
     body_field: Option<Field>,
     forward_field: Option<Field>,
     query_params_field: Option<Field>,
+    /// Fields marked with `#[query]` or `#[query("name")]`, along with the parameter name.
+    query_fields: Vec<(Field, String)>,
+    /// Fields marked with `#[header("Name")]`, along with the header name.
+    header_fields: Vec<(Field, String)>,
+    /// Fields marked with `#[cookie("name")]`, along with the cookie name.
+    cookie_fields: Vec<(Field, String)>,
     guard_fields: Vec<Field>,
     path_segment_fields: Vec<Field>,
+    host_fields: Vec<Field>,
+    /// Path segment fields marked with `#[raw]`, which receive the placeholder
+    /// without percent-decoding it first.
+    raw_fields: Vec<Ident>,
+    /// `Option` path segment fields that are missing from some routes. They're `None` if the
+    /// matched route doesn't contain them, and `Some` of the parsed placeholder otherwise.
+    optional_placeholders: Vec<Ident>,
+    /// Path segment fields marked with `#[default]`, which are set to `Default::default()` if the
+    /// matched route doesn't contain them.
+    default_fields: Vec<Ident>,
 }
 
 /// Describes where a field is decoded from.
@@ -90,8 +308,16 @@ pub struct VariantData {
 pub enum FieldKind {
     /// Field is decoded from `{placeholders}` in the URL.
     PathSegment,
+    /// Field is decoded from `{placeholders}` in the host name.
+    Host,
     /// Field is `Deserialize`d from query parameters.
     QueryParams,
+    /// Field is decoded from a single query parameter (`#[query("name")]`).
+    Query,
+    /// Field is decoded from a single request header (`#[header("Name")]`).
+    Header,
+    /// Field is decoded from a single cookie (`#[cookie("name")]`).
+    Cookie,
     /// Field is decoded from request body using `FromBody`.
     Body,
     /// Field is decoded from entire request using `FromRequest`.
@@ -101,183 +327,513 @@ pub enum FieldKind {
 }
 
 impl VariantData {
-    pub fn parse(ast: &VariantAst<'_>, is_struct: bool) -> Self {
-        // Collect all the route attributes on the variant
+    /// Parses the attributes on a variant (or struct), recording any errors in `errors`.
+    ///
+    /// The `#[prefix]`, `#[host]` and `#[trailing_slash]` attributes of `item` apply to all
+    /// routes.
+    pub fn parse(
+        ast: &VariantAst<'_>,
+        is_struct: bool,
+        item: &ItemData,
+        errors: &mut Errors,
+    ) -> Self {
+        // Collect all the route attributes and doc comments on the variant
         let mut routes = Vec::new();
+        let mut has_route_attr = false;
+        // Attributes that require a route attribute on the variant
+        let mut route_only = Vec::new();
+        let mut doc_lines = Vec::new();
+        let mut host = None;
+        let mut consumes = None;
+        let mut produces = None;
+        let mut guards = item.guards().to_vec();
+        // `#[guard]` attributes on the variant itself
+        let mut guard_attrs = Vec::new();
         for attr in ast.attrs {
-            let meta = attr.parse_meta().unwrap();
+            if is_attr(attr, "guard") {
+                guard_attrs.push(attr);
+                guards.extend(errors.ok(parse_guard(attr)));
+                continue;
+            }
+
+            let meta = match parse_meta(attr, errors) {
+                Some(meta) => meta,
+                None => continue,
+            };
             match &meta {
+                Meta::NameValue(nv) if nv.ident == "doc" => {
+                    if let Lit::Str(lit) = &nv.lit {
+                        let line = lit.value();
+                        doc_lines.push(line.strip_prefix(' ').unwrap_or(&line).to_string());
+                    }
+                }
                 Meta::List(list) if is_method(&meta.name()) => {
-                    routes.push(Route::parse(
-                        meta.name(),
+                    has_route_attr = true;
+                    let route = Route::parse(
+                        meta.name().to_string().to_uppercase(),
                         &list.nested.iter().collect::<Vec<_>>(),
-                    ));
+                        item,
+                        &meta,
+                    );
+                    routes.extend(errors.ok(route));
+                }
+                Meta::List(list) if meta.name() == "any" => {
+                    has_route_attr = true;
+                    let route = Route::parse(
+                        ANY_METHOD.to_string(),
+                        &list.nested.iter().collect::<Vec<_>>(),
+                        item,
+                        &meta,
+                    );
+                    routes.extend(errors.ok(route));
+                }
+                Meta::List(list) if meta.name() == "route" => {
+                    has_route_attr = true;
+                    let route =
+                        Route::parse_generic(&list.nested.iter().collect::<Vec<_>>(), item, &meta);
+                    routes.extend(errors.ok(route));
+                }
+                _ if meta.name() == "host" => {
+                    route_only.push(meta.clone());
+                    if let Some(pattern) = errors.ok(parse_host(&meta)) {
+                        errors.ok(insert("#[host]", &mut host, pattern, &meta));
+                    }
+                }
+                _ if meta.name() == "consumes" => {
+                    route_only.push(meta.clone());
+                    if let Some(ranges) = errors.ok(parse_media_ranges(&meta)) {
+                        errors.ok(insert("#[consumes]", &mut consumes, ranges, &meta));
+                    }
+                }
+                _ if meta.name() == "produces" => {
+                    route_only.push(meta.clone());
+                    let ranges = match errors.ok(parse_media_ranges(&meta)) {
+                        Some(ranges) => ranges,
+                        None => continue,
+                    };
+                    if let Some(range) = ranges.iter().find(|range| range.as_str().contains('*')) {
+                        errors.push(syn::Error::new_spanned(
+                            &meta,
+                            format!(
+                                "#[produces] requires concrete media types, but `{}` contains a wildcard",
+                                range.as_str()
+                            ),
+                        ));
+                        continue;
+                    }
+                    errors.ok(insert("#[produces]", &mut produces, ranges, &meta));
                 }
                 _ if known_attr(&meta.name()) && !is_struct => {
-                    panic!("`#[{}]` is not valid on enum variants", meta.name())
+                    errors.push(syn::Error::new_spanned(
+                        &meta,
+                        format!("`#[{}]` is not valid on enum variants", meta.name()),
+                    ))
                 }
                 _ => {}
             }
         }
 
-        // Since you're allowed to put multiple routes on a variant, they all
-        // must have the same placeholders.
-        // They also have to be in the same order because we want to access the
-        // captures by index.
-        if let Some((first, rest)) = routes.split_first() {
-            for route in rest {
-                if first.placeholders() != route.placeholders() {
-                    let first = first
-                        .placeholders()
-                        .iter()
-                        .map(|ident| ident.to_string())
-                        .collect::<Vec<_>>()
-                        .join(", ");
-                    let other = route
-                        .placeholders()
-                        .iter()
-                        .map(|ident| ident.to_string())
-                        .collect::<Vec<_>>()
-                        .join(", ");
-                    panic!(
-                        "different placeholders used on variant `{}`: `{}` vs. `{}` (they have to be in the same order)",
-                        ast.ident, first, other
-                    );
-                }
+        // The routes of a variant may use different placeholders (fields missing from a route
+        // are checked in `parse_fields`). The generated code finds the captures of the matched
+        // route by its path, so routes sharing a path must use the same placeholders.
+        for (i, route) in routes.iter().enumerate() {
+            let conflict = routes[..i].iter().find(|other| {
+                other.path.regex.as_str() == route.path.regex.as_str()
+                    && other.placeholders() != route.placeholders()
+            });
+            if let Some(other) = conflict {
+                errors.push(syn::Error::new_spanned(
+                    &route.tokens,
+                    format!(
+                        "routes `{}` and `{}` on variant `{}` match the same paths, so they have to use the same placeholders in the same order",
+                        other, route, ast.ident
+                    ),
+                ));
             }
         }
 
+        // Placeholders used by any of the routes
         let placeholders = routes
-            .first()
-            .map(|route| route.placeholders())
-            .unwrap_or(&[]);
+            .iter()
+            .flat_map(|route| route.placeholders())
+            .collect::<Vec<_>>();
 
-        // All placeholders must have fields with that name in the variant
-        for placeholder in placeholders {
+        if !has_route_attr {
+            for meta in &route_only {
+                errors.push(syn::Error::new_spanned(
+                    meta,
+                    format!(
+                        "#[{}] can only be used together with a route attribute",
+                        meta.name()
+                    ),
+                ));
+            }
+        }
+
+        let host = match host {
+            Some(host) => Some(host),
+            // Fallback variants match any host
+            None if routes.is_empty() => None,
+            None => item.host().cloned(),
+        };
+        let host_placeholders = host.as_ref().map_or(&[][..], HostPattern::placeholders);
+
+        // All placeholders must have fields with that name in the variant. Errors point at the
+        // route attribute, or at the variant if the placeholder comes from an inherited `#[host]`.
+        let path_placeholders = routes.iter().flat_map(|route| {
+            route
+                .placeholders()
+                .iter()
+                .map(move |p| (p, route.tokens.clone()))
+        });
+        let host_placeholders_spanned = host_placeholders
+            .iter()
+            .map(|p| (p, ast.ident.into_token_stream()));
+        for (placeholder, tokens) in path_placeholders.chain(host_placeholders_spanned) {
             if ast
                 .fields
                 .iter()
                 .find(|field| field.ident.as_ref() == Some(placeholder))
                 .is_none()
             {
-                panic!(
-                    "placeholder `{{{}}}` does not refer to an existing field on variant `{}`",
+                errors.push(syn::Error::new_spanned(
+                    tokens,
+                    format!(
+                        "placeholder `{{{}}}` does not refer to an existing field on variant `{}`",
+                        placeholder, ast.ident,
+                    ),
+                ));
+            }
+        }
+
+        if let Some(placeholder) = host_placeholders.iter().find(|p| placeholders.contains(p)) {
+            errors.push(syn::Error::new_spanned(
+                ast.ident,
+                format!(
+                    "placeholder `{{{}}}` is used in both the host and the path of variant `{}`",
                     placeholder, ast.ident,
-                );
+                ),
+            ));
+        }
+
+        let mut this = Self {
+            name: ast.ident.clone(),
+            doc: doc_lines.join("\n"),
+            routes,
+            has_route_attr,
+            host,
+            consumes: consumes.unwrap_or_default(),
+            produces: produces.unwrap_or_default(),
+            guards,
+            body_field: None,
+            forward_field: None,
+            query_params_field: None,
+            query_fields: Vec::new(),
+            header_fields: Vec::new(),
+            cookie_fields: Vec::new(),
+            guard_fields: Vec::new(),
+            path_segment_fields: Vec::new(),
+            host_fields: Vec::new(),
+            raw_fields: Vec::new(),
+            optional_placeholders: Vec::new(),
+            default_fields: Vec::new(),
+        };
+
+        if let Fields::Unnamed(fields) = ast.fields {
+            if !fields.unnamed.is_empty() {
+                errors.push(syn::Error::new_spanned(
+                    fields,
+                    format!(
+                        "tuple variants are not supported (`{}::{}`)",
+                        item.name, ast.ident
+                    ),
+                ));
+                return this;
+            }
+        }
+
+        this.parse_fields(ast, errors);
+
+        // Structs have to be constructible anyways, which is checked elsewhere
+        if !is_struct && !this.constructible() && !this.has_route_attr {
+            for attr in guard_attrs {
+                errors.push(syn::Error::new_spanned(
+                    attr,
+                    "#[guard] can only be used on variants with a route attribute or a `#[forward]` field",
+                ));
             }
         }
 
-        // Now check all attributes on the variant's fields
-        let mut body_field = None;
-        let mut forward_field = None;
-        let mut query_params_field = None;
-        let mut guard_fields = Vec::new();
-        let mut path_segment_fields = Vec::new();
+        this
+    }
+
+    /// Checks the attributes on the (named) fields of the variant and records how each field is
+    /// decoded.
+    fn parse_fields(&mut self, ast: &VariantAst<'_>, errors: &mut Errors) {
+        const FIELD_KINDS: &str = "#[body]/#[query_params]/#[query]/#[header]/#[cookie]/#[forward]";
+
+        let placeholders = self
+            .routes
+            .iter()
+            .flat_map(|route| route.placeholders())
+            .collect::<Vec<_>>();
+        let host_placeholders = self
+            .host
+            .as_ref()
+            .map_or(&[][..], HostPattern::placeholders);
+
         for field in ast.fields.iter() {
+            let ident = field
+                .ident
+                .as_ref()
+                .expect("internal error: unnamed field in named variant");
+
             // Every field must have a role
-            let mut field_kind = match &field.ident {
-                Some(ident) if placeholders.contains(ident) => {
-                    path_segment_fields.push(ident.clone());
-                    Some(FieldKind::PathSegment)
-                }
-                _ => None,
+            let mut field_kind = if placeholders.contains(&ident) {
+                Some(FieldKind::PathSegment)
+            } else if host_placeholders.contains(ident) {
+                Some(FieldKind::Host)
+            } else {
+                None
             };
+            let mut raw = None;
+            let mut default = None;
 
             for attr in &field.attrs {
-                let meta = attr.parse_meta().unwrap();
-                match &meta {
-                    Meta::Word(ident) if ident == "body" => {
-                        if let Some(ident) = &field.ident {
-                            insert("#[body]", &mut body_field, ident.clone());
-                        } else {
-                            panic!("#[body] is not supported on unnamed fields");
-                        }
+                if is_attr(attr, "guard") {
+                    errors.push(syn::Error::new_spanned(
+                        attr,
+                        "#[guard] is not valid on fields (fields without attribute are guards already)",
+                    ));
+                    continue;
+                }
 
-                        insert(
-                            "#[body]/#[query_params]/#[forward]",
-                            &mut field_kind,
-                            FieldKind::Body,
-                        );
+                let meta = match parse_meta(attr, errors) {
+                    Some(meta) => meta,
+                    None => continue,
+                };
+                let kind = match &meta {
+                    Meta::Word(name) if name == "body" => {
+                        errors.ok(insert(
+                            "#[body]",
+                            &mut self.body_field,
+                            field.clone(),
+                            &meta,
+                        ));
+                        FieldKind::Body
+                    }
+                    Meta::Word(name) if name == "query_params" => {
+                        errors.ok(insert(
+                            "#[query_params]",
+                            &mut self.query_params_field,
+                            field.clone(),
+                            &meta,
+                        ));
+                        FieldKind::QueryParams
                     }
-                    Meta::Word(ident) if ident == "query_params" => {
-                        if let Some(ident) = &field.ident {
-                            insert("#[query_params]", &mut query_params_field, ident.clone());
-                        } else {
-                            panic!("#[query_params] is not supported on unnamed fields");
+                    Meta::Word(name) | Meta::List(MetaList { ident: name, .. })
+                        if name == "query" =>
+                    {
+                        if let Some(name) = errors.ok(parse_query_name(&meta, ident)) {
+                            self.query_fields.push((field.clone(), name));
                         }
-
-                        insert(
-                            "#[body]/#[query_params]/#[forward]",
-                            &mut field_kind,
-                            FieldKind::QueryParams,
-                        );
+                        FieldKind::Query
                     }
-                    Meta::Word(ident) if ident == "forward" => {
-                        if let Some(ident) = &field.ident {
-                            insert("#[forward]", &mut forward_field, ident.clone());
-                        } else {
-                            panic!("#[forward] is not supported on unnamed fields");
+                    Meta::List(list) if list.ident == "header" => {
+                        if let Some(name) = errors.ok(parse_param_name(&meta, "X-Name")) {
+                            self.header_fields.push((field.clone(), name));
                         }
-
-                        insert(
-                            "#[body]/#[query_params]/#[forward]",
-                            &mut field_kind,
-                            FieldKind::Forward,
-                        );
+                        FieldKind::Header
+                    }
+                    Meta::List(list) if list.ident == "cookie" => {
+                        if let Some(name) = errors.ok(parse_param_name(&meta, "name")) {
+                            self.cookie_fields.push((field.clone(), name));
+                        }
+                        FieldKind::Cookie
+                    }
+                    Meta::Word(name) if name == "forward" => {
+                        errors.ok(insert(
+                            "#[forward]",
+                            &mut self.forward_field,
+                            field.clone(),
+                            &meta,
+                        ));
+                        FieldKind::Forward
+                    }
+                    Meta::Word(name) if name == "raw" => {
+                        if raw.is_some() {
+                            errors.push(syn::Error::new_spanned(
+                                &meta,
+                                "#[raw] must only be specified once",
+                            ));
+                        }
+                        raw = Some(meta);
+                        continue;
+                    }
+                    Meta::Word(name) if name == "default" => {
+                        if default.is_some() {
+                            errors.push(syn::Error::new_spanned(
+                                &meta,
+                                "#[default] must only be specified once",
+                            ));
+                        }
+                        default = Some(meta);
+                        continue;
                     }
                     _ if known_attr(&meta.name()) => {
-                        panic!("#[{}] is not valid on fields", meta.name());
+                        errors.push(syn::Error::new_spanned(
+                            &meta,
+                            format!("#[{}] is not valid on fields", meta.name()),
+                        ));
+                        continue;
                     }
-                    _ => {}
-                }
+                    _ => continue,
+                };
+
+                errors.ok(insert(FIELD_KINDS, &mut field_kind, kind, &meta));
             }
 
-            // If there's no #[body]/#[query_params] on the field and it doesn't appear as a path
+            // If there's no #[body]/#[query_params]/#[query]/#[header]/#[cookie] on the field and it doesn't appear as a path
             // segment placeholder, it's a guard.
             let field_kind = field_kind.unwrap_or(FieldKind::Guard);
 
-            if field_kind == FieldKind::Guard {
-                guard_fields.push(
-                    field
-                        .ident
-                        .clone()
-                        .expect("#[derive(FromRequest)] requires named fields"),
-                );
+            if let Some(meta) = raw {
+                if field_kind == FieldKind::PathSegment {
+                    self.raw_fields.push(ident.clone());
+                } else {
+                    errors.push(syn::Error::new_spanned(
+                        meta,
+                        "#[raw] can only be used on fields bound to a path placeholder",
+                    ));
+                }
+            }
+
+            if let Some(meta) = &default {
+                if field_kind == FieldKind::PathSegment {
+                    self.default_fields.push(ident.clone());
+                } else {
+                    errors.push(syn::Error::new_spanned(
+                        meta,
+                        "#[default] can only be used on fields bound to a path placeholder",
+                    ));
+                }
+            }
+
+            // Fields that some routes don't bind need a value to fall back to
+            if field_kind == FieldKind::PathSegment && default.is_none() {
+                let missing = self.routes.iter().find(|route| {
+                    !route.placeholders().contains(ident) || route.path().is_optional(ident)
+                });
+                if let Some(route) = missing {
+                    if option_inner(&field.ty).is_some() {
+                        self.optional_placeholders.push(ident.clone());
+                    } else if route.path().is_optional(ident) {
+                        errors.push(syn::Error::new_spanned(
+                            field,
+                            format!(
+                                "field `{}` is bound to an optional placeholder in route `{}`, so it has to be an `Option` or marked with `#[default]`",
+                                ident, route
+                            ),
+                        ));
+                    } else {
+                        errors.push(syn::Error::new_spanned(
+                            field,
+                            format!(
+                                "field `{}` is not bound by route `{}`, so it has to be an `Option` or marked with `#[default]`",
+                                ident, route
+                            ),
+                        ));
+                    }
+                }
+            }
+
+            match field_kind {
+                FieldKind::PathSegment => self.path_segment_fields.push(field.clone()),
+                FieldKind::Host => self.host_fields.push(field.clone()),
+                FieldKind::Guard => self.guard_fields.push(field.clone()),
+                _ => {}
             }
         }
 
-        if body_field.is_some() && forward_field.is_some() {
-            panic!("#[body] and #[forward] cannot be combined in the same variant/struct");
+        if let (Some(_), Some(forward)) = (&self.body_field, &self.forward_field) {
+            errors.push(syn::Error::new_spanned(
+                forward,
+                "#[body] and #[forward] cannot be combined in the same variant/struct",
+            ));
         }
 
         // If there's no route, deny all attributes on fields as well
-        if routes.is_empty() {
-            if body_field.is_some() {
-                panic!("cannot mark a field with #[body] when the variant doesn't have a route attribute");
+        if !self.has_route_attr {
+            let unrouted = self
+                .body_field
+                .iter()
+                .map(|fld| ("body", fld))
+                .chain(
+                    self.query_params_field
+                        .iter()
+                        .map(|fld| ("query_params", fld)),
+                )
+                .chain(self.query_fields.iter().map(|(fld, _)| ("query", fld)))
+                .chain(self.header_fields.iter().map(|(fld, _)| ("header", fld)))
+                .chain(self.cookie_fields.iter().map(|(fld, _)| ("cookie", fld)));
+            for (attr, fld) in unrouted {
+                errors.push(syn::Error::new_spanned(
+                    fld,
+                    format!(
+                        "cannot mark a field with #[{}] when the variant doesn't have a route attribute",
+                        attr
+                    ),
+                ));
             }
+        }
 
-            if query_params_field.is_some() {
-                panic!("cannot mark a field with #[query_params] when the variant doesn't have a route attribute");
+        for (i, (fld, name)) in self.query_fields.iter().enumerate() {
+            if self.query_fields[..i]
+                .iter()
+                .any(|(_, other)| other == name)
+            {
+                errors.push(syn::Error::new_spanned(
+                    fld,
+                    format!(
+                        "query parameter `{}` is bound to multiple fields (`{}` is one of them)",
+                        name,
+                        fld.ident.as_ref().unwrap()
+                    ),
+                ));
             }
         }
 
-        // Given a field name, returns the whole `Field`
-        let fld = |ident: Ident| -> Field {
-            ast.fields
+        for (i, (fld, name)) in self.header_fields.iter().enumerate() {
+            if self.header_fields[..i]
                 .iter()
-                .find(|fld| fld.ident.clone().unwrap() == ident)
-                .unwrap()
-                .clone()
-        };
+                .any(|(_, other)| other.eq_ignore_ascii_case(name))
+            {
+                errors.push(syn::Error::new_spanned(
+                    fld,
+                    format!(
+                        "header `{}` is bound to multiple fields (`{}` is one of them)",
+                        name,
+                        fld.ident.as_ref().unwrap()
+                    ),
+                ));
+            }
+        }
 
-        Self {
-            name: ast.ident.clone(),
-            routes,
-            body_field: body_field.map(fld),
-            forward_field: forward_field.map(fld),
-            query_params_field: query_params_field.map(fld),
-            guard_fields: guard_fields.into_iter().map(fld).collect(),
-            path_segment_fields: path_segment_fields.into_iter().map(fld).collect(),
+        // Unlike header names, cookie names are case-sensitive
+        for (i, (fld, name)) in self.cookie_fields.iter().enumerate() {
+            if self.cookie_fields[..i]
+                .iter()
+                .any(|(_, other)| other == name)
+            {
+                errors.push(syn::Error::new_spanned(
+                    fld,
+                    format!(
+                        "cookie `{}` is bound to multiple fields (`{}` is one of them)",
+                        name,
+                        fld.ident.as_ref().unwrap()
+                    ),
+                ));
+            }
         }
     }
 
@@ -286,10 +842,20 @@ impl VariantData {
         !self.routes.is_empty() || self.forward_field().is_some()
     }
 
+    /// Returns whether the variant has a route attribute, including ones that failed to parse.
+    pub fn has_route_attr(&self) -> bool {
+        self.has_route_attr
+    }
+
     pub fn variant_name(&self) -> &Ident {
         &self.name
     }
 
+    /// Returns the doc comment attached to the variant (empty if there is none).
+    pub fn doc(&self) -> &str {
+        &self.doc
+    }
+
     /// Returns the parsed route attributes attached to this variant.
     ///
     /// This might be empty, in which case the custom derive should just ignore
@@ -298,6 +864,30 @@ impl VariantData {
         &self.routes
     }
 
+    /// Returns the host pattern the request host has to match.
+    ///
+    /// If this is `None`, the variant matches requests to any host.
+    pub fn host(&self) -> Option<&HostPattern> {
+        self.host.as_ref()
+    }
+
+    /// Returns the media types accepted by the variant's routes (empty if any request is
+    /// accepted).
+    pub fn consumes(&self) -> &[MediaRange] {
+        &self.consumes
+    }
+
+    /// Returns the media types produced by the variant's routes (empty if the variant doesn't
+    /// take part in content negotiation).
+    pub fn produces(&self) -> &[MediaRange] {
+        &self.produces
+    }
+
+    /// Returns the types of the guards from `#[guard]` attributes, in the order they're checked.
+    pub fn guards(&self) -> &[syn::Type] {
+        &self.guards
+    }
+
     /// Returns the name of the field marked with `#[body]`.
     ///
     /// If this is `None`, the body is ignored.
@@ -325,6 +915,43 @@ impl VariantData {
             .map(|fld| fld.ident.as_ref().unwrap())
     }
 
+    /// Returns the fields marked with `#[query]`, along with the name of the query parameter.
+    pub fn query_fields(&self) -> &[(Field, String)] {
+        &self.query_fields
+    }
+
+    /// Returns the fields marked with `#[header]`, along with the name of the header (as
+    /// written in the attribute).
+    pub fn header_fields(&self) -> &[(Field, String)] {
+        &self.header_fields
+    }
+
+    /// Returns the fields marked with `#[cookie]`, along with the name of the cookie.
+    pub fn cookie_fields(&self) -> &[(Field, String)] {
+        &self.cookie_fields
+    }
+
+    /// Returns whether the path segment field `field` is marked with `#[raw]`.
+    ///
+    /// The placeholder value is passed to the field's `FromStr` impl without
+    /// percent-decoding it first.
+    pub fn is_raw(&self, field: &Ident) -> bool {
+        self.raw_fields.contains(field)
+    }
+
+    /// Returns whether the path segment field `field` is an `Option` that is `None` when the
+    /// matched route doesn't contain its placeholder.
+    ///
+    /// The placeholder is parsed into the `Option`'s inner type.
+    pub fn is_optional_placeholder(&self, field: &Ident) -> bool {
+        self.optional_placeholders.contains(field)
+    }
+
+    /// Returns whether the path segment field `field` is marked with `#[default]`.
+    pub fn has_default(&self, field: &Ident) -> bool {
+        self.default_fields.contains(field)
+    }
+
     /// Returns the list of fields that store guard objects.
     pub fn guard_fields(&self) -> &[Field] {
         &self.guard_fields
@@ -340,12 +967,28 @@ impl VariantData {
                     .iter()
                     .map(|fld| (fld, FieldKind::PathSegment)),
             )
+            .chain(self.host_fields.iter().map(|fld| (fld, FieldKind::Host)))
             .chain(self.body_field.as_ref().map(|fld| (fld, FieldKind::Body)))
             .chain(
                 self.query_params_field
                     .as_ref()
                     .map(|fld| (fld, FieldKind::QueryParams)),
             )
+            .chain(
+                self.query_fields
+                    .iter()
+                    .map(|(fld, _)| (fld, FieldKind::Query)),
+            )
+            .chain(
+                self.header_fields
+                    .iter()
+                    .map(|(fld, _)| (fld, FieldKind::Header)),
+            )
+            .chain(
+                self.cookie_fields
+                    .iter()
+                    .map(|(fld, _)| (fld, FieldKind::Cookie)),
+            )
             .chain(
                 self.forward_field
                     .as_ref()
@@ -354,45 +997,148 @@ impl VariantData {
     }
 }
 
-/// A parsed HTTP route attribute (eg. `#[get("/path/{placeholder}/bla/{rest...}")]`).
+/// A parsed HTTP route attribute (eg. `#[get("/path/{placeholder}/bla/{rest...}")]` or
+/// `#[route(PROPFIND, "/path")]`).
 #[derive(Clone)]
 pub struct Route {
-    /// Name of the associated constant on `http::Method`.
-    method: Ident,
+    /// The HTTP method, as it appears in requests (eg. `GET`).
+    method: String,
     path: RoutePath,
+    /// The rank specified with `rank = N`. Overlapping routes are tried in order of ascending
+    /// rank.
+    rank: Option<u32>,
+    /// The tokens of the attribute, used to point errors at it.
+    tokens: TokenStream,
 }
 
 impl Route {
-    fn parse(method: Ident, args: &[&NestedMeta]) -> Self {
+    /// Parses the arguments of a route attribute for the given (uppercase) method, like
+    /// `#[get("/path")]` or `#[any("/path")]`.
+    fn parse(
+        method: String,
+        args: &[&NestedMeta],
+        item: &ItemData,
+        meta: &Meta,
+    ) -> syn::Result<Self> {
+        let (args, rank) = split_rank(args)?;
         match args {
-            [NestedMeta::Literal(Lit::Str(path))] => {
-                let path = path.value();
+            [NestedMeta::Literal(Lit::Str(path))] => Ok(Self {
+                method,
+                path: item
+                    .route_path(path.value())
+                    .map_err(|msg| syn::Error::new_spanned(path, msg))?,
+                rank,
+                tokens: meta.into_token_stream(),
+            }),
+            _ => Err(syn::Error::new_spanned(
+                meta,
+                "route attributes must be of the form `#[method(\"/path/to/match\")]`",
+            )),
+        }
+    }
 
-                Self {
-                    method: Ident::new(&method.to_string().to_uppercase(), Span::call_site()),
-                    path: RoutePath::parse(path),
-                }
+    /// Parses the arguments of a `#[route(METHOD, "/path")]` attribute.
+    fn parse_generic(args: &[&NestedMeta], item: &ItemData, meta: &Meta) -> syn::Result<Self> {
+        let (args, rank) = split_rank(args)?;
+        let (method, path) = match args {
+            [NestedMeta::Meta(Meta::Word(method)), NestedMeta::Literal(Lit::Str(path))] => {
+                (method.to_string(), path)
+            }
+            [NestedMeta::Literal(Lit::Str(method)), NestedMeta::Literal(Lit::Str(path))] => {
+                (method.value(), path)
             }
             _ => {
-                panic!("route attributes must be of the form `#[method(\"/path/to/match\")]`");
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "`#[route]` attributes must be of the form `#[route(METHOD, \"/path/to/match\")]`",
+                ))
             }
+        };
+
+        if method.is_empty() || !method.chars().all(is_token_char) {
+            return Err(syn::Error::new_spanned(
+                args[0],
+                format!("invalid HTTP method `{}` in `#[route]` attribute", method),
+            ));
         }
+        if method == ANY_METHOD {
+            return Err(syn::Error::new_spanned(
+                args[0],
+                "use `#[any(\"/path\")]` to match requests using any method",
+            ));
+        }
+
+        Ok(Self {
+            method,
+            path: item
+                .route_path(path.value())
+                .map_err(|msg| syn::Error::new_spanned(path, msg))?,
+            rank,
+            tokens: meta.into_token_stream(),
+        })
     }
 
     pub fn placeholders(&self) -> &[Ident] {
         &self.path.placeholders
     }
+
+    /// Returns the HTTP method matched by this route (`ANY_METHOD` for `#[any]` routes).
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Returns whether this is an `#[any]` route matching every method.
+    pub fn matches_any_method(&self) -> bool {
+        self.method == ANY_METHOD
+    }
+
+    /// Returns the parsed path pattern of this route.
+    pub fn path(&self) -> &RoutePath {
+        &self.path
+    }
+
+    /// Returns the rank of this route (0 if none was specified).
+    pub fn rank(&self) -> u32 {
+        self.rank.unwrap_or(0)
+    }
 }
 
 impl fmt::Display for Route {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let method = self.method.to_string().to_lowercase();
-        if is_method(&self.method) {
-            write!(f, "#[{}(\"{}\")]", method, self.path.raw)
+        let rank = match self.rank {
+            Some(rank) => format!(", rank = {}", rank),
+            None => String::new(),
+        };
+        if self.matches_any_method() {
+            write!(f, "#[any(\"{}\"{})]", self.path.raw, rank)
+        } else if standard_method(&self.method).is_some() {
+            let method = self.method.to_lowercase();
+            write!(f, "#[{}(\"{}\"{})]", method, self.path.raw, rank)
+        } else if valid_ident(&self.method) {
+            write!(f, "#[route({}, \"{}\"{})]", self.method, self.path.raw, rank)
         } else {
-            // XXX this isn't yet implemented
-            write!(f, "#[route({}, \"{}\")]", method, self.path.raw)
+            write!(f, "#[route(\"{}\", \"{}\"{})]", self.method, self.path.raw, rank)
+        }
+    }
+}
+
+/// Splits the optional trailing `rank = N` argument off the arguments of a route attribute.
+fn split_rank<'a, 'b>(
+    args: &'a [&'b NestedMeta],
+) -> syn::Result<(&'a [&'b NestedMeta], Option<u32>)> {
+    match args.split_last() {
+        Some((NestedMeta::Meta(Meta::NameValue(nv)), rest)) if nv.ident == "rank" => {
+            match &nv.lit {
+                Lit::Int(rank) if rank.value() <= u64::from(u32::MAX) => {
+                    Ok((rest, Some(rank.value() as u32)))
+                }
+                _ => Err(syn::Error::new_spanned(
+                    nv,
+                    "`rank` must be a non-negative integer (eg. `rank = 1`)",
+                )),
+            }
         }
+        _ => Ok((args, None)),
     }
 }
 
@@ -417,55 +1163,81 @@ pub struct RoutePath {
     /// Sorted by order of appearance (this is important for associating the
     /// regex captures with the right field).
     placeholders: Vec<Ident>,
-    /// Placeholder field names, sorted by name.
-    placeholders_sorted: Vec<Ident>,
+    /// Whether the path is matched with and without trailing slash
+    /// (`#[trailing_slash(match_both)]`).
+    ignore_trailing_slash: bool,
 }
 
 impl RoutePath {
-    fn parse(path: String) -> Self {
+    fn parse(path: String) -> Result<Self, String> {
         if path == "*" {
-            return Self {
+            return Ok(Self {
                 raw: path,
                 regex: Regex::new("\\*").unwrap(),
                 segments: Vec::new(),
                 placeholders: Vec::new(),
-                placeholders_sorted: Vec::new(),
-            };
+                ignore_trailing_slash: false,
+            });
         }
 
         // Require paths to start with `/` to make them unambiguous.
         // They may or may not end with `/` - both ways refer to
-        // different resources.
+        // different resources (unless `#[trailing_slash(match_both)]` is
+        // used, see `ignore_trailing_slash`).
         if !path.starts_with("/") {
-            panic!("paths of route attributes must start with `/`");
+            return Err("paths of route attributes must start with `/`".to_string());
         }
 
-        let segments = path
-            .split('/')
-            .skip(1)
-            .map(|s| PathSegment::parse(s.into()))
-            .collect::<Vec<_>>();
+        let segments = split_segments(&path[1..])
+            .into_iter()
+            .map(PathSegment::parse)
+            .collect::<Result<Vec<_>, _>>()?;
 
         let mut regex = String::new();
         let mut placeholders = Vec::new();
         for (i, segment) in segments.iter().enumerate() {
             match segment {
                 PathSegment::Rest(ident) => {
-                    // "Rest" placeholder capturing *everything*. Only valid at the end.
-                    if i != segments.len() - 1 {
-                        panic!("...-placeholders must not be followed by anything");
+                    // "Rest" placeholder capturing *everything* up to a literal suffix (eg.
+                    // `/repos/{path...}/blob`).
+                    if !segments[i + 1..]
+                        .iter()
+                        .all(|segment| matches!(segment, PathSegment::Literal(_)))
+                    {
+                        return Err(
+                            "...-placeholders can only be followed by literal segments".to_string(),
+                        );
                     }
 
                     placeholders.push(ident.clone());
                     regex.push_str("/(.*)");
                 }
-                PathSegment::Placeholder(ident) => {
+                PathSegment::Optional(ident, _) => {
+                    // Optional placeholders can be left out, but only starting at the end
+                    if i == 0 {
+                        return Err(
+                            "the first path segment must not be an optional placeholder".to_string()
+                        );
+                    }
+                    if !segments[i + 1..]
+                        .iter()
+                        .all(|segment| matches!(segment, PathSegment::Optional(..)))
+                    {
+                        return Err(
+                            "optional placeholders must only be followed by other optional placeholders"
+                                .to_string(),
+                        );
+                    }
+
                     placeholders.push(ident.clone());
-                    regex.push_str("/([^/]+)");
+                    regex.push_str("(?:/");
+                    regex.push_str(&segment.regex());
+                    regex.push_str(")?");
                 }
-                PathSegment::Literal(literal) => {
+                PathSegment::Placeholder(..) | PathSegment::Literal(_) | PathSegment::Mixed(_) => {
+                    placeholders.extend(segment.placeholders().cloned());
                     regex.push('/');
-                    regex_syntax::escape_into(literal, &mut regex);
+                    regex.push_str(&segment.regex());
                 }
             }
         }
@@ -476,19 +1248,105 @@ impl RoutePath {
         let before = placeholders_sorted.len();
         placeholders_sorted.dedup();
         if placeholders_sorted.len() != before {
-            panic!("duplicate placeholders in route path `{}`", path);
+            return Err(format!("duplicate placeholders in route path `{}`", path));
         }
 
-        Self {
+        Ok(Self {
             raw: path,
             regex: Regex::new(&format!("^{}$", regex))
                 .expect("FromRequest derive created invalid regex"),
             segments,
             placeholders,
-            placeholders_sorted,
+            ignore_trailing_slash: false,
+        })
+    }
+
+    /// Makes the path match regardless of whether the request path has a trailing slash.
+    ///
+    /// This has no effect on the root path `/`, the asterisk path `*` and paths ending in a
+    /// `{rest...}` placeholder (which already matches trailing slashes).
+    fn ignore_trailing_slash(&mut self) {
+        match self.segments.last() {
+            None | Some(PathSegment::Rest(_)) => return,
+            _ if self.segments.len() == 1 && self.has_trailing_slash() => return,
+            _ => {}
+        }
+
+        let regex = self.regex.as_str();
+        let regex = regex.strip_suffix('$').expect("internal error: unanchored regex");
+        let regex = if self.has_trailing_slash() {
+            regex.strip_suffix('/').expect("internal error: no slash in regex")
+        } else {
+            regex
+        };
+        self.regex = Regex::new(&format!("{}/?$", regex))
+            .expect("FromRequest derive created invalid regex");
+        self.ignore_trailing_slash = true;
+    }
+
+    /// Returns whether the path ends with a `/`, ie. its last segment is empty.
+    fn has_trailing_slash(&self) -> bool {
+        match self.segments.last() {
+            Some(PathSegment::Literal(lit)) => lit.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Returns whether the path is matched with and without trailing slash.
+    pub fn ignores_trailing_slash(&self) -> bool {
+        self.ignore_trailing_slash
+    }
+
+    /// Returns the segments that are taken into account when matching requests.
+    ///
+    /// This excludes the empty segment created by a trailing slash when the trailing slash is
+    /// ignored.
+    pub fn matched_segments(&self) -> &[PathSegment] {
+        if self.ignore_trailing_slash && self.has_trailing_slash() {
+            &self.segments[..self.segments.len() - 1]
+        } else {
+            &self.segments
         }
     }
 
+    /// Returns the path pattern as written in the route attribute.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Returns the name of the `{rest...}` placeholder, if any.
+    pub fn rest_placeholder(&self) -> Option<&Ident> {
+        self.segments.iter().find_map(|segment| match segment {
+            PathSegment::Rest(ident) => Some(ident),
+            _ => None,
+        })
+    }
+
+    /// Returns the segments making up the path.
+    ///
+    /// If empty, this is the asterisk path `*`.
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// Returns whether `placeholder` is an optional placeholder (`{name?}`) of this path.
+    pub fn is_optional(&self, placeholder: &Ident) -> bool {
+        self.segments.iter().any(|segment| match segment {
+            PathSegment::Optional(ident, _) => ident == placeholder,
+            _ => false,
+        })
+    }
+
+    /// Returns the number of optional placeholders (`{name?}`), which are always the last
+    /// segments of the path.
+    pub fn optional_segments(&self) -> usize {
+        self.segments
+            .iter()
+            .rev()
+            .take_while(|segment| matches!(segment, PathSegment::Optional(..)))
+            .count()
+    }
+
     /// Returns `true` if `self` and `other` match the exact same set of paths.
     fn matches_same_paths(&self, other: &Self) -> bool {
         self.regex.as_str() == other.regex.as_str()
@@ -496,249 +1354,546 @@ impl RoutePath {
 
     /// Tries to find a route that can be matched by both `self` and `other`.
     pub fn find_overlap(&self, other: &Self) -> Option<String> {
-        use self::PathSegment::*;
-
-        if self.segments.is_empty() {
-            // self is "*"
-            if other.segments.is_empty() {
+        let (segments, other_segments) = (self.matched_segments(), other.matched_segments());
+        if segments.is_empty() || other_segments.is_empty() {
+            // "*" only overlaps with itself
+            if segments.is_empty() && other_segments.is_empty() {
                 return Some("*".into());
             } else {
                 return None;
             }
         }
 
-        let mut overlap = String::new();
-        let mut saw_rest = false;
-        for (a, b) in self.segments_fused().zip(other.segments_fused()) {
-            match (a, b) {
-                // If we reach any `Rest` placeholder there *must* be overlap
-                (Rest(_), Rest(_)) => {
-                    // Here we want to bail early to prevent an infinite loop (also we want the
-                    // shortest counterexample)
-                    overlap.push('/');
-                    overlap.push_str(&a.matching_string());
-                    return Some(overlap);
-                }
-                (Rest(_), other) | (other, Rest(_)) => {
-                    overlap.push('/');
-                    overlap.push_str(&other.matching_string());
-                    saw_rest = true;
-                }
+        // Both paths are expanded to every number of segments they can match, trying the
+        // shortest ones first to find the shortest counterexample. Beyond the combined length of
+        // both paths, longer `{rest...}` placeholders can't make a difference anymore.
+        let max = segments.len() + other_segments.len();
+        (1..=max).find_map(|len| segments_overlap(&self.expand(len)?, &other.expand(len)?))
+    }
 
-                (Placeholder(a), Placeholder(_)) => {
-                    overlap.push('/');
-                    overlap.push_str(&a.to_string());
-                }
+    /// Returns the segments matching request paths made up of `len` segments, or `None` if the
+    /// path can't match any of them.
+    ///
+    /// A `{rest...}` placeholder is repeated to make up the missing segments, and optional
+    /// placeholders are left out starting at the end.
+    fn expand(&self, len: usize) -> Option<Vec<&PathSegment>> {
+        let segments = self.matched_segments();
+        let rest = segments
+            .iter()
+            .position(|segment| matches!(segment, PathSegment::Rest(_)));
+        match rest {
+            Some(pos) if len >= segments.len() => {
+                let repeated = iter::repeat_n(&segments[pos], len - segments.len());
+                Some(
+                    segments[..=pos]
+                        .iter()
+                        .chain(repeated)
+                        .chain(&segments[pos + 1..])
+                        .collect(),
+                )
+            }
+            None if len <= segments.len() && len + self.optional_segments() >= segments.len() => {
+                Some(segments[..len].iter().collect())
+            }
+            _ => None,
+        }
+    }
+}
 
-                (Placeholder(_), Literal(lit)) | (Literal(lit), Placeholder(_)) => {
-                    overlap.push('/');
-                    overlap.push_str(&lit);
+/// Tries to find a path that is matched by both segment lists `a` and `b`, which must have the
+/// same length.
+///
+/// A `{rest...}` placeholder in either list matches a single segment here, and optional
+/// placeholders are matched like regular ones (see `RoutePath::expand`).
+fn segments_overlap(a: &[&PathSegment], b: &[&PathSegment]) -> Option<String> {
+    use self::PathSegment::*;
+
+    let mut overlap = String::new();
+    for (&a, &b) in a.iter().zip(b) {
+        let segment = match (a, b) {
+            // `Rest` placeholders match anything
+            (Rest(_), Rest(_)) => a.matching_string(),
+            (Rest(_), other) | (other, Rest(_)) => other.matching_string(),
+
+            (Literal(a), Literal(b)) => {
+                if a != b {
+                    return None;
                 }
+                a.clone()
+            }
 
-                (Literal(a), Literal(b)) => {
-                    if a == b {
-                        overlap.push('/');
-                        overlap.push_str(&a);
-                    } else {
-                        return None;
-                    }
+            (Literal(lit), other) | (other, Literal(lit)) => {
+                if !other.matches_literal(lit) {
+                    return None;
                 }
+                lit.clone()
             }
-        }
 
-        if self.segments.len() == other.segments.len() || saw_rest {
-            Some(overlap)
-        } else {
-            // Different segment count can only overlap with "rest" placeholders, which is handled
-            // above already
-            None
-        }
-    }
+            (a, b) => {
+                if a.is_disjoint(b) {
+                    return None;
+                }
+                a.matching_string()
+            }
+        };
 
-    /// Returns an iterator over the path segments, fusing any "rest" placeholder (`{rest...}`).
-    ///
-    /// If the last placeholder is a "rest" placeholder, it will be yielded indefinitely.
-    fn segments_fused(&self) -> impl Iterator<Item = &PathSegment> {
-        assert!(
-            !self.segments.is_empty(),
-            "`*` path has no segments to iterate over"
-        );
-        SegmentsFused::Unfused(self.segments.iter())
+        overlap.push('/');
+        overlap.push_str(&segment);
     }
-}
 
-enum SegmentsFused<'a> {
-    Unfused(slice::Iter<'a, PathSegment>),
-    Fused(&'a PathSegment),
+    Some(overlap)
 }
 
-impl<'a> Iterator for SegmentsFused<'a> {
-    type Item = &'a PathSegment;
-
-    fn next(&mut self) -> Option<&'a PathSegment> {
-        match self {
-            SegmentsFused::Unfused(iter) => match iter.next() {
-                None => None,
-                Some(segment @ PathSegment::Rest(_)) => {
-                    *self = SegmentsFused::Fused(segment);
-                    Some(segment)
-                }
-                Some(other) => Some(other),
-            },
-            SegmentsFused::Fused(segment) => Some(segment),
+/// Splits a path (without the leading `/`) into its segments.
+///
+/// Slashes inside a placeholder's constraint (eg. `{name:[^/]+}`) don't separate segments.
+fn split_segments(path: &str) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    // Nesting depth of braces
+    let mut depth = 0;
+    for c in path.chars() {
+        match c {
+            '/' if depth == 0 => {
+                segments.push(String::new());
+                continue;
+            }
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            _ => {}
         }
+        segments.last_mut().unwrap().push(c);
     }
+    segments
 }
 
 /// Segment of a request path pattern.
 #[derive(Clone)]
 pub enum PathSegment {
-    /// `{ident}`
-    Placeholder(Ident),
+    /// `{ident}` or `{ident:constraint}`
+    Placeholder(Ident, Constraint),
     /// `{ident...}`
+    ///
+    /// Can only be followed by `Literal` segments.
     Rest(Ident),
+    /// `{ident?}` or `{ident?:constraint}`
+    ///
+    /// Only valid as one of the last segments of a path, which may be left out.
+    Optional(Ident, Constraint),
     /// `anything else`
     Literal(String),
+    /// Literal text combined with placeholders, eg. `{name}.{ext}` or `v{major}`.
+    ///
+    /// Only contains `Placeholder` and `Literal` parts, and never two `Placeholder`s in a row.
+    Mixed(Vec<PathSegment>),
 }
 
 impl PathSegment {
-    fn parse(segment: String) -> Self {
-        if segment.starts_with('{') && segment.ends_with('}') {
-            let inner = &segment[1..segment.len() - 1];
-            if inner.ends_with("...") {
-                let ident = &inner[..inner.len() - 3];
-                if !valid_ident(ident) {
-                    panic!("placeholder `{}` must be a valid identifier", inner);
+    fn parse(segment: String) -> Result<Self, String> {
+        let mut parts = parse_segment_parts(&segment)?;
+        if parts.len() > 1 {
+            for window in parts.windows(2) {
+                match window {
+                    [PathSegment::Rest(_), _] | [_, PathSegment::Rest(_)] => {
+                        return Err(format!(
+                            "...-placeholders must make up an entire path segment (in `{}`)",
+                            segment
+                        ));
+                    }
+                    [PathSegment::Optional(..), _] | [_, PathSegment::Optional(..)] => {
+                        return Err(format!(
+                            "optional placeholders must make up an entire path segment (in `{}`)",
+                            segment
+                        ));
+                    }
+                    [PathSegment::Placeholder(a, _), PathSegment::Placeholder(b, _)] => {
+                        return Err(format!(
+                            "placeholders `{{{}}}` and `{{{}}}` must be separated by literal text",
+                            a, b
+                        ));
+                    }
+                    _ => {}
                 }
+            }
 
-                PathSegment::Rest(Ident::new(ident, Span::call_site()))
-            } else {
-                // Else the placeholder must be a valid ident that will store a segment
-                if !valid_ident(inner) {
-                    panic!("placeholder `{}` must be a valid identifier", inner);
-                }
+            Ok(PathSegment::Mixed(parts))
+        } else {
+            Ok(parts
+                .pop()
+                .unwrap_or_else(|| PathSegment::Literal(String::new())))
+        }
+    }
 
-                PathSegment::Placeholder(Ident::new(inner, Span::call_site()))
+    /// Returns the placeholders in this segment, in order of appearance.
+    pub fn placeholders(&self) -> impl Iterator<Item = &Ident> {
+        let parts = match self {
+            PathSegment::Mixed(parts) => parts.as_slice(),
+            other => slice::from_ref(other),
+        };
+        parts.iter().filter_map(|part| match part {
+            PathSegment::Placeholder(ident, _)
+            | PathSegment::Rest(ident)
+            | PathSegment::Optional(ident, _) => Some(ident),
+            _ => None,
+        })
+    }
+
+    /// Returns a regex matching this segment, with a capture group for each placeholder.
+    pub fn regex(&self) -> String {
+        match self {
+            PathSegment::Placeholder(_, constraint) | PathSegment::Optional(_, constraint) => {
+                format!("({})", constraint.regex())
+            }
+            PathSegment::Rest(_) => "(.*)".to_string(),
+            PathSegment::Literal(literal) => regex_syntax::escape(literal),
+            PathSegment::Mixed(parts) => parts.iter().map(PathSegment::regex).collect(),
+        }
+    }
+
+    /// Returns whether the literal path segment `literal` is matched by `self`.
+    fn matches_literal(&self, literal: &str) -> bool {
+        match self {
+            PathSegment::Placeholder(_, constraint) | PathSegment::Optional(_, constraint) => {
+                constraint.matches(literal)
             }
+            PathSegment::Rest(_) => true,
+            PathSegment::Literal(lit) => lit == literal,
+            PathSegment::Mixed(_) => Regex::new(&format!("^{}$", self.regex()))
+                .expect("internal error: invalid segment regex")
+                .is_match(literal),
+        }
+    }
+
+    /// Returns `true` if `self` and `other` can't possibly match the same path segment.
+    ///
+    /// This is conservative and might return `false` for segments that are in fact disjoint.
+    /// Neither segment may be a `Rest` placeholder.
+    fn is_disjoint(&self, other: &Self) -> bool {
+        match (self, other) {
+            (PathSegment::Literal(lit), other) | (other, PathSegment::Literal(lit)) => {
+                !other.matches_literal(lit)
+            }
+            (
+                PathSegment::Placeholder(_, a) | PathSegment::Optional(_, a),
+                PathSegment::Placeholder(_, b) | PathSegment::Optional(_, b),
+            ) => a.is_disjoint(b),
+            _ => {
+                // Compare the literal text and character sets at both ends of the segments
+                let (a_prefix, a_first) = self.edge(false);
+                let (b_prefix, b_first) = other.edge(false);
+                let (a_suffix, a_last) = self.edge(true);
+                let (b_suffix, b_last) = other.edge(true);
+                edges_disjoint(&a_prefix, a_first, &b_prefix, b_first)
+                    || edges_disjoint(&a_suffix, a_last, &b_suffix, b_last)
+            }
+        }
+    }
+
+    /// Returns the literal text at the start (or end, if `from_end` is `true`) of the segment,
+    /// along with the characters that can follow it (or precede it).
+    ///
+    /// The literal text is returned in reverse order if `from_end` is `true`.
+    fn edge(&self, from_end: bool) -> (Vec<char>, &ClassUnicode) {
+        let parts = match self {
+            PathSegment::Mixed(parts) => parts.as_slice(),
+            other => slice::from_ref(other),
+        };
+        let parts: Box<dyn Iterator<Item = &PathSegment>> = if from_end {
+            Box::new(parts.iter().rev())
         } else {
-            // literal
-            PathSegment::Literal(segment)
+            Box::new(parts.iter())
+        };
+
+        let mut literal = Vec::new();
+        for part in parts {
+            match part {
+                PathSegment::Literal(lit) if from_end => literal.extend(lit.chars().rev()),
+                PathSegment::Literal(lit) => literal.extend(lit.chars()),
+                PathSegment::Placeholder(_, constraint) | PathSegment::Optional(_, constraint)
+                    if from_end =>
+                {
+                    return (literal, constraint.last_chars())
+                }
+                PathSegment::Placeholder(_, constraint) | PathSegment::Optional(_, constraint) => {
+                    return (literal, constraint.first_chars())
+                }
+                PathSegment::Rest(_) | PathSegment::Mixed(_) => {
+                    unreachable!("invalid part in mixed segment")
+                }
+            }
         }
+
+        unreachable!("mixed segment without placeholder")
     }
 
     /// Creates an example path segment that would match `self`.
     fn matching_string(&self) -> String {
         match self {
-            PathSegment::Placeholder(ident) => ident.to_string(),
+            PathSegment::Placeholder(ident, _) | PathSegment::Optional(ident, _) => {
+                ident.to_string()
+            }
             PathSegment::Rest(ident) => format!("{}...", ident),
             PathSegment::Literal(lit) => lit.clone(),
+            PathSegment::Mixed(parts) => parts.iter().map(PathSegment::matching_string).collect(),
         }
     }
 }
 
+/// Splits a path segment into literal text and placeholders.
+fn parse_segment_parts(segment: &str) -> Result<Vec<PathSegment>, String> {
+    let mut parts = Vec::new();
+    let mut rest = segment;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(PathSegment::Literal(rest[..start].to_string()));
+        }
+
+        // Find the matching `}` (constraints may contain braces themselves)
+        let mut depth = 0;
+        let end = rest[start..]
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .map(|(i, _)| start + i)
+            .ok_or_else(|| format!("unterminated placeholder in path segment `{}`", segment))?;
+
+        parts.push(parse_placeholder(&rest[start + 1..end])?);
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(PathSegment::Literal(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+/// Parses the contents of a placeholder (without the surrounding braces).
+fn parse_placeholder(inner: &str) -> Result<PathSegment, String> {
+    if let Some(ident) = inner.strip_suffix("...") {
+        if !valid_ident(ident) {
+            return Err(format!(
+                "placeholder `{}` must be a valid identifier",
+                inner
+            ));
+        }
+
+        Ok(PathSegment::Rest(Ident::new(ident, Span::call_site())))
+    } else {
+        // Else the placeholder must be a valid ident that will store a segment, optionally
+        // followed by a constraint
+        let (ident, constraint) = match inner.find(':') {
+            Some(pos) => (&inner[..pos], Constraint::parse(&inner[pos + 1..])?),
+            None => (inner, Constraint::any()),
+        };
+        let (ident, optional) = match ident.strip_suffix('?') {
+            Some(ident) => (ident, true),
+            None => (ident, false),
+        };
+        if !valid_ident(ident) {
+            return Err(format!(
+                "placeholder `{}` must be a valid identifier",
+                ident
+            ));
+        }
+
+        let ident = Ident::new(ident, Span::call_site());
+        if optional {
+            Ok(PathSegment::Optional(ident, constraint))
+        } else {
+            Ok(PathSegment::Placeholder(ident, constraint))
+        }
+    }
+}
+
+/// Checks whether the ends of two segments are disjoint.
+///
+/// Each end is given as the literal text at the end (in the order seen from the end) and the set
+/// of characters that can follow that text.
+fn edges_disjoint(
+    a_lit: &[char],
+    a_chars: &ClassUnicode,
+    b_lit: &[char],
+    b_chars: &ClassUnicode,
+) -> bool {
+    let contains = |class: &ClassUnicode, c: char| {
+        class
+            .ranges()
+            .iter()
+            .any(|range| range.start() <= c && c <= range.end())
+    };
+
+    if a_lit.iter().zip(b_lit).any(|(a, b)| a != b) {
+        return true;
+    }
+
+    match a_lit.len().cmp(&b_lit.len()) {
+        Ordering::Equal => {
+            let mut common = a_chars.clone();
+            common.intersect(b_chars);
+            a_lit.is_empty() && common.ranges().is_empty()
+        }
+        Ordering::Greater => !contains(b_chars, a_lit[b_lit.len()]),
+        Ordering::Less => !contains(a_chars, b_lit[a_lit.len()]),
+    }
+}
+
+/// Maps HTTP methods to the candidate variants handling them.
+///
+/// Each method maps to a list of candidate variants with different `#[host]` patterns, ordered by
+/// the order in which they should be tried.
+type MethodMap = IndexMap<String, Vec<(VariantData, Route)>>;
+
 /// Maps generated path regexes to method->variant maps.
 pub struct PathMap {
-    regex_map: IndexMap<ByProxy<Regex, str>, IndexMap<Ident, (VariantData, Route)>>,
+    regex_map: IndexMap<ByProxy<Regex, str>, MethodMap>,
     fallback: Option<VariantData>,
+    /// Whether there are different path regexes that can match the same request path.
+    overlapping: bool,
 }
 
 impl PathMap {
-    pub fn build(item: &ItemData, variants: &[VariantData]) -> Self {
+    /// Builds the map from the parsed variants, recording overlapping and duplicate routes in
+    /// `errors`.
+    pub fn build(item: &ItemData, variants: &[VariantData], errors: &mut Errors) -> Self {
         let mut this = Self {
             regex_map: IndexMap::new(),
             fallback: None,
+            overlapping: false,
         };
 
         for variant in variants {
             if variant.routes.is_empty() && variant.forward_field.is_some() {
-                if let Some(prev) = this.fallback {
-                    panic!(
-                        "cannot define multiple fallback variants – `{ty}::{v1}` and `{ty}::{v2}` \
-                         both use `#[forward]` without a route attribute",
-                        ty = item.name,
-                        v1 = prev.name,
-                        v2 = variant.name,
-                    );
+                if let Some(prev) = &this.fallback {
+                    errors.push(syn::Error::new_spanned(
+                        &variant.name,
+                        format!(
+                            "cannot define multiple fallback variants – `{ty}::{v1}` and `{ty}::{v2}` \
+                             both use `#[forward]` without a route attribute",
+                            ty = item.name,
+                            v1 = prev.name,
+                            v2 = variant.name,
+                        ),
+                    ));
                 } else {
                     this.fallback = Some(variant.clone());
                 }
             }
 
             for route in &variant.routes {
-                // Check for overlap with all previously registered routes
-                for prev_route in this
+                // Check for overlap with all previously registered routes. Overlapping routes
+                // need different ranks, so that it's clear which one is tried first. Only routes
+                // with the exact same path pattern can be distinguished by their host.
+                for (_, prev_route) in this
                     .regex_map
                     .values()
-                    .flat_map(|m| m.values().map(|(_, r)| r))
-                    .filter(|r| !r.path.matches_same_paths(&route.path))
+                    .flat_map(|m| m.values().flatten())
+                    .filter(|(_, r)| !r.path.matches_same_paths(&route.path))
                 {
                     if let Some(overlap) = prev_route.path.find_overlap(&route.path) {
-                        panic!(
-                            "route `{}` overlaps with previously defined route `{}` (both would match path `{}`)",
-                            route, prev_route, overlap
-                        );
+                        if prev_route.rank() == route.rank() {
+                            errors.push(syn::Error::new_spanned(
+                                &route.tokens,
+                                format!(
+                                    "route `{}` overlaps with previously defined route `{}` (both would match path `{}`); \
+                                     use `rank = N` to specify which one is tried first",
+                                    route, prev_route, overlap
+                                ),
+                            ));
+                        }
+
+                        this.overlapping = true;
                     }
                 }
 
-                this.add_route(variant.clone(), route.clone());
+                errors.ok(this.add_route(variant.clone(), route.clone()));
             }
         }
 
         // For each GET route, register a matching HEAD route if none exists
-        let any_head_overlaps_with = |new_route: &Route| {
+        let any_head_overlaps_with = |new_variant: &VariantData, new_route: &Route| {
             this.regex_map
                 .values()
-                .flat_map(|map| {
-                    map.iter().filter_map(|(method, (_, route))| {
-                        if method.to_string() == "HEAD" {
-                            Some(route)
-                        } else {
-                            None
-                        }
-                    })
+                .flat_map(|map| map.get("HEAD").into_iter().flatten())
+                .any(|(variant, route)| {
+                    route.path.find_overlap(&new_route.path).is_some()
+                        && route.rank() == new_route.rank()
+                        && hosts_overlap(variant.host(), new_variant.host())
+                        && ranges_overlap(variant.consumes(), new_variant.consumes())
+                        && ranges_overlap(variant.produces(), new_variant.produces())
                 })
-                .any(|route| route.path.find_overlap(&new_route.path).is_some())
         };
         let mut implied_head_routes = Vec::new();
         for route_map in this.regex_map.values() {
-            for (method, (variant, route)) in route_map.iter() {
-                if method.to_string() == "GET" {
-                    let head = Route {
-                        method: Ident::new("HEAD", Span::call_site()),
-                        path: route.path.clone(),
-                    };
-                    if !any_head_overlaps_with(&head) {
-                        implied_head_routes.push((variant.clone(), head));
-                    }
+            for (variant, route) in route_map.get("GET").into_iter().flatten() {
+                let head = Route {
+                    method: "HEAD".to_string(),
+                    path: route.path.clone(),
+                    rank: route.rank,
+                    tokens: route.tokens.clone(),
+                };
+                if !any_head_overlaps_with(variant, &head) {
+                    implied_head_routes.push((variant.clone(), head));
                 }
             }
         }
 
         for (variant, route) in implied_head_routes {
-            this.add_route(variant, route);
+            errors.ok(this.add_route(variant, route));
         }
 
         this
     }
 
-    fn add_route(&mut self, variant: VariantData, route: Route) {
+    fn add_route(&mut self, variant: VariantData, route: Route) -> syn::Result<()> {
         let reg = ByProxy::new(route.path.regex.clone(), Regex::as_str);
         let entry = self.regex_map.entry(reg);
-        let route_map = entry.or_insert_with(IndexMap::new);
+        let route_map = entry.or_default();
         match route_map.entry(route.method.clone()) {
             Entry::Vacant(v) => {
                 // Map this path regex and method to the variant it was placed on:
-                v.insert((variant, route));
+                v.insert(vec![(variant, route)]);
             }
-            Entry::Occupied(old) => {
-                // duplicate path declaration
-                let old = old.get();
-                panic!(
-                    "duplicate route: `{}` on `{}` matches the same requests as `{}` on `{}`",
-                    old.1, old.0.name, route, variant.name
-                );
+            Entry::Occupied(mut candidates) => {
+                // The same path and method may be used for different hosts, media types or with
+                // different ranks, as long as it's clear which candidate should be tried first.
+                for old in candidates.get() {
+                    if hosts_overlap(old.0.host(), variant.host())
+                        && host_rank(old.0.host()) == host_rank(variant.host())
+                        && ranges_overlap(old.0.consumes(), variant.consumes())
+                        && old.0.consumes().is_empty() == variant.consumes().is_empty()
+                        && ranges_overlap(old.0.produces(), variant.produces())
+                        && old.0.produces().is_empty() == variant.produces().is_empty()
+                        && old.1.rank() == route.rank()
+                    {
+                        // duplicate path declaration
+                        return Err(syn::Error::new_spanned(
+                            &route.tokens,
+                            format!(
+                                "duplicate route: `{}` on `{}` matches the same requests as `{}` on `{}`",
+                                old.1, old.0.name, route, variant.name
+                            ),
+                        ));
+                    }
+                }
+
+                let candidates = candidates.get_mut();
+                candidates.push((variant, route));
+                candidates.sort_by_key(|(variant, route)| {
+                    (
+                        route.rank(),
+                        host_rank(variant.host()),
+                        variant.consumes().is_empty(),
+                        variant.produces().is_empty(),
+                    )
+                });
             }
         }
+
+        Ok(())
     }
 
     /// Returns an iterator over all unique paths in this map.
@@ -749,38 +1904,282 @@ impl PathMap {
         })
     }
 
+    /// Returns the index of the path matched by `route` in the iteration order of `paths()`.
+    pub fn path_index(&self, route: &Route) -> usize {
+        self.regex_map
+            .keys()
+            .position(|regex| regex.as_ref().as_str() == route.path.regex.as_str())
+            .expect("internal error: route not in path map")
+    }
+
     /// Returns the fallback variant, a variant using `#[forward]`, without a route attribute.
     pub fn fallback(&self) -> Option<&VariantData> {
         self.fallback.as_ref()
     }
+
+    /// Returns whether a request path can be matched by more than one path regex (which requires
+    /// the routes to be ranked).
+    pub fn is_overlapping(&self) -> bool {
+        self.overlapping
+    }
 }
 
 pub struct PathInfo<'a> {
     regex: &'a Regex,
-    method_map: &'a IndexMap<Ident, (VariantData, Route)>,
+    method_map: &'a MethodMap,
 }
 
 impl<'a> PathInfo<'a> {
     /// Returns the regex used to match this path.
     pub fn regex(&self) -> &'a Regex {
-        &self.regex
+        self.regex
+    }
+
+    /// Returns the path pattern shared by all routes matched by this path.
+    pub fn path(&self) -> &'a RoutePath {
+        self.method_map
+            .values()
+            .flatten()
+            .map(|(_, route)| route.path())
+            .next()
+            .expect("internal error: path without routes")
+    }
+
+    /// Returns an iterator over the `Method => Candidates` mappings for this path.
+    ///
+    /// The candidate variants are sorted in the order they should be tried in: By rank first,
+    /// then variants with more specific `#[host]` patterns come first, variants without `#[host]`
+    /// come last.
+    ///
+    /// `#[any]` routes are not included (see `any_method`).
+    pub fn method_map(&self) -> impl Iterator<Item = (&'a str, &'a [(VariantData, Route)])> {
+        self.method_map
+            .iter()
+            .filter(|(k, _)| k.as_str() != ANY_METHOD)
+            .map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    /// Returns the candidates of the `#[any]` routes of this path, sorted like the candidates in
+    /// `method_map`.
+    ///
+    /// These are tried after the candidates for the specific method of a request.
+    pub fn any_method(&self) -> &'a [(VariantData, Route)] {
+        self.method_map.get(ANY_METHOD).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns whether any variant matched by this path is restricted to some hosts.
+    pub fn has_hosts(&self) -> bool {
+        self.method_map
+            .values()
+            .flatten()
+            .any(|(variant, _)| variant.host().is_some())
+    }
+}
+
+/// Parses the arguments of a `#[consumes("type/subtype", ...)]` or `#[produces(...)]` attribute.
+fn parse_media_ranges(meta: &Meta) -> syn::Result<Vec<MediaRange>> {
+    let usage = || {
+        syn::Error::new_spanned(
+            meta,
+            format!(
+                "#[{0}] must be of the form `#[{0}(\"application/json\", ...)]`",
+                meta.name()
+            ),
+        )
+    };
+    match meta {
+        Meta::List(list) if !list.nested.is_empty() => list
+            .nested
+            .iter()
+            .map(|nested| match nested {
+                NestedMeta::Literal(Lit::Str(media_type)) => MediaRange::parse(&media_type.value())
+                    .map_err(|msg| syn::Error::new_spanned(media_type, msg)),
+                _ => Err(usage()),
+            })
+            .collect(),
+        _ => Err(usage()),
+    }
+}
+
+/// Returns whether there's a host matched by both `a` and `b` (`None` matches any host).
+fn hosts_overlap(a: Option<&HostPattern>, b: Option<&HostPattern>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.overlaps(b),
+        _ => true,
+    }
+}
+
+/// Key used to order route candidates that only differ in their host: Host patterns with fewer
+/// placeholders are more specific and tried first, routes without `#[host]` are tried last.
+fn host_rank(host: Option<&HostPattern>) -> (bool, usize) {
+    (host.is_none(), host.map_or(0, |host| host.placeholders().len()))
+}
+
+/// Prepends the `#[prefix]` path to a route path.
+///
+/// The asterisk path is left alone, as are invalid paths (so that `RoutePath::parse` can reject
+/// them).
+fn prefixed(prefix: &str, path: String) -> String {
+    if path.starts_with('/') {
+        format!("{}{}", prefix, path)
+    } else {
+        path
+    }
+}
+
+/// Parses the argument of a `#[host("pattern")]` attribute.
+fn parse_host(meta: &Meta) -> syn::Result<HostPattern> {
+    if let Meta::List(list) = meta {
+        if let [NestedMeta::Literal(Lit::Str(host))] =
+            list.nested.iter().collect::<Vec<_>>().as_slice()
+        {
+            return HostPattern::parse(host.value())
+                .map_err(|msg| syn::Error::new_spanned(host, msg));
+        }
+    }
+
+    Err(syn::Error::new_spanned(
+        meta,
+        "#[host] must be of the form `#[host(\"api.example.com\")]`",
+    ))
+}
+
+/// Returns the name of the query parameter bound to `field` by a `#[query]` or
+/// `#[query("name")]` attribute.
+fn parse_query_name(meta: &Meta, field: &Ident) -> syn::Result<String> {
+    let name = match meta {
+        Meta::Word(_) => return Ok(field.to_string()),
+        Meta::List(list) => match list.nested.iter().collect::<Vec<_>>().as_slice() {
+            [NestedMeta::Literal(Lit::Str(name))] => name.value(),
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "#[query] must be of the form `#[query]` or `#[query(\"name\")]`",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                meta,
+                "#[query] must be of the form `#[query]` or `#[query(\"name\")]`",
+            ))
+        }
+    };
+
+    if name.is_empty() {
+        return Err(syn::Error::new_spanned(
+            meta,
+            "#[query] requires a non-empty parameter name",
+        ));
     }
 
-    /// Returns an iterator over the `Method => Variant` mappings for this path.
-    pub fn method_map(&self) -> impl Iterator<Item = (&'a Ident, &'a VariantData)> {
-        self.method_map.iter().map(|(k, v)| (k, &v.0))
+    Ok(name)
+}
+
+/// Parses the argument of a `#[header("Name")]` or `#[cookie("name")]` attribute.
+///
+/// `example` is used as the argument in the error message if the attribute is malformed.
+fn parse_param_name(meta: &Meta, example: &str) -> syn::Result<String> {
+    let name = match meta {
+        Meta::List(list) => match list.nested.iter().collect::<Vec<_>>().as_slice() {
+            [NestedMeta::Literal(Lit::Str(name))] => name,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    format!(
+                        "#[{0}] must be of the form `#[{0}(\"{1}\")]`",
+                        meta.name(),
+                        example
+                    ),
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                meta,
+                format!(
+                    "#[{0}] must be of the form `#[{0}(\"{1}\")]`",
+                    meta.name(),
+                    example
+                ),
+            ))
+        }
+    };
+
+    let value = name.value();
+    if value.is_empty() || !value.chars().all(is_token_char) {
+        return Err(syn::Error::new_spanned(
+            name,
+            format!("invalid {} name `{}`", meta.name(), value),
+        ));
     }
+
+    Ok(value)
 }
 
-fn insert<T>(name: &str, slot: &mut Option<T>, value: T) {
+/// Stores `value` in `slot`, or reports an error at `tokens` if the slot is already occupied.
+fn insert<T>(
+    name: &str,
+    slot: &mut Option<T>,
+    value: T,
+    tokens: &impl ToTokens,
+) -> syn::Result<()> {
     if slot.is_some() {
-        panic!("{} must only be specified once", name);
+        return Err(syn::Error::new_spanned(
+            tokens,
+            format!("{} must only be specified once", name),
+        ));
     }
 
     *slot = Some(value);
+    Ok(())
+}
+
+/// Returns whether `attr` is named `name`, without looking at its arguments.
+fn is_attr(attr: &Attribute, name: &str) -> bool {
+    attr.path.leading_colon.is_none()
+        && attr.path.segments.len() == 1
+        && attr.path.segments.iter().all(|segment| segment.ident == name)
+}
+
+/// Parses the type in a `#[guard(MyGuard)]` attribute.
+///
+/// This doesn't go through `Meta`, since types like `auth::Admin` or `Role<Admin>` aren't valid
+/// there.
+fn parse_guard(attr: &Attribute) -> syn::Result<syn::Type> {
+    match syn::parse2(attr.tts.clone()) {
+        // `#[guard(MyGuard)]` is parsed as a parenthesized type
+        Ok(syn::Type::Paren(paren)) => Ok(*paren.elem),
+        _ => Err(syn::Error::new_spanned(
+            attr,
+            "#[guard] must be of the form `#[guard(MyGuard)]`",
+        )),
+    }
 }
 
-fn valid_ident(s: &str) -> bool {
+/// Parses an attribute as a `Meta`.
+///
+/// Attributes of other derives or tools that don't follow that syntax are skipped. Errors are only
+/// recorded for our own attributes.
+fn parse_meta(attr: &Attribute, errors: &mut Errors) -> Option<Meta> {
+    match attr.parse_meta() {
+        Ok(meta) => Some(meta),
+        Err(e) => {
+            let ours = attr.path.segments.len() == 1
+                && attr
+                    .path
+                    .segments
+                    .iter()
+                    .any(|segment| known_attr(&segment.ident) || is_method(&segment.ident));
+            if ours {
+                errors.push(e);
+            }
+            None
+        }
+    }
+}
+
+pub fn valid_ident(s: &str) -> bool {
     if s.is_empty() || s == "_" {
         return false;
     }
@@ -790,10 +2189,9 @@ fn valid_ident(s: &str) -> bool {
         _ => return false,
     }
 
-    s.chars().skip(1).all(|c| match c {
-        'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => true,
-        _ => false,
-    })
+    s.chars()
+        .skip(1)
+        .all(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '_'))
 }
 
 #[cfg(test)]
@@ -818,7 +2216,8 @@ mod tests {
         macro_rules! intersect {
             ($a:literal, $b:literal) => {{
                 RoutePath::parse($a.to_string())
-                    .find_overlap(&RoutePath::parse($b.to_string()))
+                    .unwrap()
+                    .find_overlap(&RoutePath::parse($b.to_string()).unwrap())
                     .as_ref()
                     .map(|s| s.as_str())
             }};
@@ -842,6 +2241,123 @@ mod tests {
         assert_eq!(intersect!("/lit/bla", "/blit/{b...}"), None);
         assert_eq!(intersect!("*", "/{b...}"), None);
         assert_eq!(intersect!("*", "/"), None);
+        assert_eq!(intersect!("/", "*"), None);
         assert_eq!(intersect!("*", "*"), Some("*"));
+
+        // Constraints
+        assert_eq!(intersect!("/users/me", "/users/{id:int}"), None);
+        assert_eq!(intersect!("/users/{id:int}", "/users/me"), None);
+        assert_eq!(intersect!("/users/123", "/users/{id:int}"), Some("/users/123"));
+        assert_eq!(intersect!("/{id:int}", "/{name:alpha}"), None);
+        assert_eq!(intersect!("/{id:int}", "/{name}"), Some("/id"));
+        assert_eq!(intersect!("/{id:int}", "/{b...}"), Some("/id"));
+        assert_eq!(intersect!("/{id:[0-9]{2}}/a", "/{b:[a-z]+}/a"), None);
+
+        // Placeholders mixed with literal text
+        assert_eq!(intersect!("/{name}.{ext}", "/index.html"), Some("/index.html"));
+        assert_eq!(intersect!("/{name}.{ext}", "/index"), None);
+        assert_eq!(intersect!("/report-{year}.csv", "/report-2019.csv"), Some("/report-2019.csv"));
+        assert_eq!(intersect!("/report-{year}.csv", "/report-{year}.pdf"), None);
+        assert_eq!(intersect!("/report-{year}.csv", "/summary-{year}.csv"), None);
+        assert_eq!(intersect!("/report-{year}.csv", "/{name}.csv"), Some("/report-year.csv"));
+        assert_eq!(intersect!("/v{major}", "/{version:int}"), None);
+        assert_eq!(intersect!("/v{major}", "/{version}"), Some("/vmajor"));
+        assert_eq!(intersect!("/v{major}", "/w{major}"), None);
+        assert_eq!(intersect!("/v{major}", "/{x:[a-z]+}"), Some("/vmajor"));
+        assert_eq!(intersect!("/{a}.txt", "/{b:[a-z]+}.md"), None);
+        assert_eq!(intersect!("/{a:[0-9]+}x", "/{b:[a-z]+}x"), None);
+        assert_eq!(intersect!("/v{major}/{rest...}", "/{b...}"), Some("/vmajor/rest..."));
+
+        // Optional placeholders
+        assert_eq!(intersect!("/a/{x?}", "/a"), Some("/a"));
+        assert_eq!(intersect!("/a", "/a/{x?}"), Some("/a"));
+        assert_eq!(intersect!("/a/{x?}", "/a/b"), Some("/a/b"));
+        assert_eq!(intersect!("/a/{x?}/{y?}", "/a/b/c"), Some("/a/b/c"));
+        assert_eq!(intersect!("/a/{x?}", "/a/b/c"), None);
+        assert_eq!(intersect!("/a/{x?}", "/b/{y?}"), None);
+        assert_eq!(intersect!("/a/{x?}", "/{y}/{z}"), Some("/a/x"));
+        assert_eq!(intersect!("/a/{x?:int}", "/a/{y:alpha}"), None);
+        assert_eq!(intersect!("/a/{x?}", "/{b...}"), Some("/a"));
+        assert_eq!(intersect!("/a/{x?}", "/a/{b...}"), Some("/a/x"));
+
+        // Rest placeholders followed by literal segments
+        assert_eq!(intersect!("/r/{p...}/blob", "/r/a/blob"), Some("/r/a/blob"));
+        assert_eq!(intersect!("/r/{p...}/blob", "/r/a/b/blob"), Some("/r/a/b/blob"));
+        assert_eq!(intersect!("/r/{p...}/blob", "/r/blob"), None);
+        assert_eq!(intersect!("/r/{p...}/blob", "/r/a/tree"), None);
+        assert_eq!(intersect!("/r/{p...}/blob", "/r/{p...}/tree"), None);
+        assert_eq!(intersect!("/r/{p...}/blob", "/r/{x}/{y}"), Some("/r/x/blob"));
+        assert_eq!(intersect!("/r/{p...}/blob", "/r/{x}/tree/{y}"), Some("/r/x/tree/blob"));
+        assert_eq!(intersect!("/r/{p...}/blob", "/{q...}"), Some("/r/p.../blob"));
+        assert_eq!(intersect!("/r/{p...}/blob", "/r/{q...}"), Some("/r/p.../blob"));
+        assert_eq!(intersect!("/{p...}/a", "/b/{q...}"), Some("/b/a"));
+        assert_eq!(intersect!("/{p...}/a/b", "/{q...}/c"), None);
+        assert_eq!(intersect!("/r/{p...}/blob", "/r/{x}/{y?}"), Some("/r/x/blob"));
+    }
+
+    #[test]
+    fn trailing_slash() {
+        let both = |path: &str| {
+            let mut path = RoutePath::parse(path.to_string()).unwrap();
+            path.ignore_trailing_slash();
+            path
+        };
+
+        assert_eq!(both("/").regex.as_str(), "^/$");
+        assert_eq!(both("/a").regex.as_str(), "^/a/?$");
+        assert_eq!(both("/a/").regex.as_str(), "^/a/?$");
+        assert_eq!(both("/a/{rest...}").regex.as_str(), "^/a/(.*)$");
+        assert_eq!(both("/a/{rest...}/b").regex.as_str(), "^/a/(.*)/b/?$");
+        assert!(both("/a").matches_same_paths(&both("/a/")));
+        assert_eq!(
+            both("/a/b/").find_overlap(&both("/a/{x}")).as_deref(),
+            Some("/a/b")
+        );
+        assert_eq!(both("/a/").find_overlap(&both("/a/{x}")), None);
+    }
+
+    #[test]
+    fn optional() {
+        let parse = |path: &str| RoutePath::parse(path.to_string());
+
+        let path = parse("/archive/{year}/{month?}/{day?:int}").unwrap();
+        assert_eq!(
+            path.regex.as_str(),
+            r"^/archive/([^/]+)(?:/([^/]+))?(?:/(\-?[0-9]+))?$"
+        );
+        assert_eq!(path.placeholders, ["year", "month", "day"]);
+        assert_eq!(path.optional_segments(), 2);
+        assert!(path.is_optional(&Ident::new("month", Span::call_site())));
+        assert!(!path.is_optional(&Ident::new("year", Span::call_site())));
+
+        assert_eq!(
+            parse("/{page?}").err().unwrap(),
+            "the first path segment must not be an optional placeholder"
+        );
+        assert_eq!(
+            parse("/a/{x?}/b").err().unwrap(),
+            "optional placeholders must only be followed by other optional placeholders"
+        );
+        assert_eq!(
+            parse("/a/{x?}/{rest...}").err().unwrap(),
+            "optional placeholders must only be followed by other optional placeholders"
+        );
+        assert_eq!(
+            parse("/a/{x?}.txt").err().unwrap(),
+            "optional placeholders must make up an entire path segment (in `{x?}.txt`)"
+        );
+        assert_eq!(
+            parse("/a/{x?}/{x?}").err().unwrap(),
+            "duplicate placeholders in route path `/a/{x?}/{x?}`"
+        );
+    }
+
+    #[test]
+    fn segments() {
+        let segments = |path: &str| split_segments(path);
+        assert_eq!(segments(""), [""]);
+        assert_eq!(segments("a/b"), ["a", "b"]);
+        assert_eq!(segments("a/{x:[^/]+}/b"), ["a", "{x:[^/]+}", "b"]);
+        assert_eq!(segments("{n:[^/]+}.{e:[^/]{2}}/c"), ["{n:[^/]+}.{e:[^/]{2}}", "c"]);
     }
 }
@@ -0,0 +1,309 @@
+//! Reverse routing: Generates request paths from route values.
+//!
+//! For every variant with a route attribute, an associated function
+//! `<variant_name>_path` is generated that takes the values of all placeholders
+//! and returns the matching request path. Additionally, a `to_path(&self)`
+//! method returns the path of any value that has one.
+
+use super::parse::{PathSegment, Route, VariantData};
+use crate::utils::{option_inner, snake_case, Errors};
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+use synstructure::{Structure, VariantInfo};
+
+/// Generates an inherent impl block containing the reverse routing functions.
+///
+/// Returns an empty `TokenStream` if the type has no routed variants.
+pub fn derive_reverse_routing(s: &Structure<'_>, variants: &[VariantData]) -> TokenStream {
+    let ast = s.ast();
+    let name = &ast.ident;
+    let vis = &ast.vis;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let is_struct = matches!(ast.data, syn::Data::Struct(_));
+
+    let mut path_fns = Vec::new();
+    let mut to_path_arms = Vec::new();
+    let mut to_path_bounds = Vec::new();
+    // Whether `to_path_arms` cover every value, or a `None` arm is needed
+    let mut exhaustive = true;
+    for (variant, data) in s.variants().iter().zip(variants) {
+        let pattern = if is_struct {
+            quote!(Self)
+        } else {
+            let variant_name = data.variant_name();
+            quote!(Self::#variant_name)
+        };
+
+        // Only the first route attribute is used for building the path. Variants without one
+        // (like a `#[forward]`ing fallback variant) have no path.
+        let route = match data.routes().first() {
+            Some(route) => route,
+            None => {
+                exhaustive = false;
+                continue;
+            }
+        };
+
+        let fn_name = path_fn_name(data);
+        let params = route.placeholders();
+        let tys = params
+            .iter()
+            .map(|param| placeholder_ty(variant, data, param))
+            .collect::<Vec<_>>();
+        // Optional placeholders (`{name?}`) are passed as `Option`s
+        let param_tys = params.iter().zip(&tys).map(|(param, ty)| {
+            if route.path().is_optional(param) {
+                quote!(::std::option::Option<&#ty>)
+            } else {
+                quote!(&#ty)
+            }
+        });
+        // The `for<'a>` turns these into bounds that are only checked when the function is
+        // used, so that placeholder types without a `Display` impl can still be routed.
+        let bounds = tys
+            .iter()
+            .map(|ty| quote!(for<'__hyperdrive> #ty: ::std::fmt::Display))
+            .collect::<Vec<_>>();
+        let path = build_path(route);
+        to_path_bounds.extend(bounds.iter().cloned());
+
+        let self_name = if is_struct {
+            format!("`{}`", name)
+        } else {
+            format!("`{}::{}`", name, data.variant_name())
+        };
+        let doc = format!(
+            "Returns the request path matched by the `{}` route of {}.",
+            route, self_name,
+        );
+        path_fns.push(quote! {
+            #[doc = #doc]
+            #vis fn #fn_name(#( #params: #param_tys ),*) -> ::std::string::String
+            where #(#bounds),*
+            {
+                #path
+            }
+        });
+
+        // `Option` fields can only be filled into a placeholder that isn't optional itself if
+        // they're `Some`. Otherwise, the next route not containing them is tried, and if there's
+        // none, the value has no path.
+        let mut always_matches = false;
+        for (i, route) in data.routes().iter().enumerate() {
+            let params = route.placeholders();
+            let (fields, args): (Vec<_>, Vec<_>) = params
+                .iter()
+                .map(|param| {
+                    match (
+                        data.is_optional_placeholder(param),
+                        route.path().is_optional(param),
+                    ) {
+                        (true, true) => (quote!(#param), quote!(#param.as_ref())),
+                        (true, false) => (quote!(#param: Some(#param)), quote!(#param)),
+                        (false, true) => (quote!(#param), quote!(Some(#param))),
+                        (false, false) => (quote!(#param), quote!(#param)),
+                    }
+                })
+                .unzip();
+            let build = if i == 0 {
+                quote!(Self::#fn_name(#(#args),*))
+            } else {
+                for param in params {
+                    let ty = placeholder_ty(variant, data, param);
+                    to_path_bounds.push(quote!(for<'__hyperdrive> #ty: ::std::fmt::Display));
+                }
+                // `build_path` expects optional placeholders to be `Option`s
+                let optional = params
+                    .iter()
+                    .filter(|param| route.path().is_optional(param))
+                    .map(|param| {
+                        if data.is_optional_placeholder(param) {
+                            quote!(let #param = #param.as_ref();)
+                        } else {
+                            quote!(let #param = Some(#param);)
+                        }
+                    });
+                let path = build_path(route);
+                quote!({
+                    #(#optional)*
+                    #path
+                })
+            };
+            to_path_arms.push(quote! {
+                #pattern { #( #fields, )* .. } => ::std::option::Option::Some(#build),
+            });
+
+            let needs_some = params.iter().any(|param| {
+                data.is_optional_placeholder(param) && !route.path().is_optional(param)
+            });
+            if !needs_some {
+                // Always matches, so later routes are never used
+                always_matches = true;
+                break;
+            }
+        }
+        exhaustive &= always_matches;
+    }
+
+    if path_fns.is_empty() {
+        return TokenStream::new();
+    }
+
+    if !exhaustive {
+        to_path_arms.push(quote! {
+            _ => ::std::option::Option::None,
+        });
+    }
+
+    quote! {
+        #[allow(dead_code)]
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#path_fns)*
+
+            /// Returns the request path matched by the route of `self`.
+            ///
+            /// The values of all placeholders are formatted using their `Display`
+            /// implementation and percent-encoded. If a variant has multiple route
+            /// attributes, the first one is used, skipping routes with `Option`al
+            /// placeholders that are `None`.
+            ///
+            /// Returns `None` if no route can represent `self`: When it's a variant
+            /// without route attribute (for example, a `#[forward]`ing fallback
+            /// variant), or when every route of the variant has a `None` placeholder.
+            #vis fn to_path(&self) -> ::std::option::Option<::std::string::String>
+            where #(#to_path_bounds),*
+            {
+                match self {
+                    #(#to_path_arms)*
+                }
+            }
+        }
+    }
+}
+
+/// Returns the name of the `_path` function generated for a variant (or struct).
+fn path_fn_name(data: &VariantData) -> Ident {
+    Ident::new(
+        &format!("{}_path", snake_case(&data.variant_name().to_string())),
+        Span::call_site(),
+    )
+}
+
+/// Reports an error for every routed variant whose `_path` function would clash with the
+/// `to_path` method or with the `_path` function of another variant.
+pub fn check_path_fn_names(variants: &[VariantData], is_struct: bool, errors: &mut Errors) {
+    let what = if is_struct { "struct" } else { "variant" };
+    let mut seen: Vec<(Ident, &Ident)> = Vec::new();
+    for data in variants.iter().filter(|data| !data.routes().is_empty()) {
+        let fn_name = path_fn_name(data);
+        let variant = data.variant_name();
+        if fn_name == "to_path" {
+            errors.push(syn::Error::new_spanned(
+                variant,
+                format!(
+                    "the reverse routing function of {} `{}` would be called `to_path`, which \
+                     clashes with the generated `to_path` method (rename the {})",
+                    what, variant, what,
+                ),
+            ));
+        } else if let Some((_, other)) = seen.iter().find(|(name, _)| *name == fn_name) {
+            errors.push(syn::Error::new_spanned(
+                variant,
+                format!(
+                    "the reverse routing functions of variants `{}` and `{}` would both be \
+                     called `{}` (rename one of the variants)",
+                    other, variant, fn_name,
+                ),
+            ));
+        } else {
+            seen.push((fn_name, variant));
+        }
+    }
+}
+
+/// Returns an expression that builds the path of `route` from variables named like the
+/// placeholders (which must be references to the placeholder values, wrapped in an `Option` for
+/// optional placeholders).
+fn build_path(route: &Route) -> TokenStream {
+    let segments = route.path().segments();
+    if segments.is_empty() {
+        // asterisk path
+        return quote!(::std::string::String::from("*"));
+    }
+
+    let pushes = push_segments(segments);
+    quote! {
+        // Named so that it can't collide with any placeholder
+        let mut __hyperdrive_path = ::std::string::String::new();
+        #pushes
+        __hyperdrive_path
+    }
+}
+
+/// Returns statements appending `segments` (each preceded by a `/`) to `__hyperdrive_path`.
+///
+/// An optional placeholder that is `None` ends the path, since the placeholders after it can't
+/// be represented.
+fn push_segments(segments: &[PathSegment]) -> TokenStream {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return TokenStream::new(),
+    };
+
+    let rest = push_segments(rest);
+    match segment {
+        PathSegment::Optional(ident, _) => quote! {
+            if let ::std::option::Option::Some(#ident) = #ident {
+                __hyperdrive_path.push('/');
+                ::hyperdrive::support::push_segment(&mut __hyperdrive_path, #ident);
+                #rest
+            }
+        },
+        _ => {
+            let push = push_segment(segment);
+            quote! {
+                __hyperdrive_path.push('/');
+                #push
+                #rest
+            }
+        }
+    }
+}
+
+/// Returns statements appending `segment` to `__hyperdrive_path`.
+fn push_segment(segment: &PathSegment) -> TokenStream {
+    match segment {
+        PathSegment::Literal(lit) => quote!(__hyperdrive_path.push_str(#lit);),
+        PathSegment::Placeholder(ident, _) => quote! {
+            ::hyperdrive::support::push_segment(&mut __hyperdrive_path, #ident);
+        },
+        PathSegment::Rest(ident) => quote! {
+            ::hyperdrive::support::push_rest(&mut __hyperdrive_path, #ident);
+        },
+        PathSegment::Mixed(parts) => parts.iter().map(push_segment).collect(),
+        PathSegment::Optional(..) => {
+            unreachable!("optional placeholders are handled by `push_segments`")
+        }
+    }
+}
+
+/// Returns the type of the placeholder `name`, which is the `Option`'s inner type for optional
+/// placeholders.
+fn placeholder_ty(variant: &VariantInfo<'_>, data: &VariantData, name: &Ident) -> syn::Type {
+    let ty = field_ty(variant, name);
+    match option_inner(ty) {
+        Some(inner) if data.is_optional_placeholder(name) => inner.clone(),
+        _ => ty.clone(),
+    }
+}
+
+/// Returns the type of the field `name` of `variant`.
+fn field_ty<'a>(variant: &VariantInfo<'a>, name: &Ident) -> &'a syn::Type {
+    &variant
+        .ast()
+        .fields
+        .iter()
+        .find(|field| field.ident.as_ref() == Some(name))
+        .expect("internal error: couldn't find field by name")
+        .ty
+}
@@ -0,0 +1,198 @@
+//! Route introspection: Generates the `ROUTES` constant listing all routes of the type, and the
+//! `openapi_operations` function building on it.
+
+use super::parse::{FieldKind, VariantData};
+use crate::utils::{option_inner, type_name};
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::Field;
+use synstructure::Structure;
+
+/// Generates an inherent impl block containing the `ROUTES` constant and `openapi_operations`.
+pub fn derive_route_table(s: &Structure<'_>, variants: &[VariantData]) -> TokenStream {
+    let ast = s.ast();
+    let name = &ast.ident;
+    let vis = &ast.vis;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let routes = variants.iter().flat_map(|data| {
+        let variant = data.variant_name().to_string();
+        let doc = data.doc();
+
+        let infos = |kind: FieldKind| {
+            data.field_uses()
+                .filter(|(_, k)| *k == kind)
+                .map(|(field, _)| field_info(field))
+                .collect::<Vec<_>>()
+        };
+        let single = |kind: FieldKind| match infos(kind).pop() {
+            Some(info) => quote!(Some(#info)),
+            None => quote!(None),
+        };
+        let body = single(FieldKind::Body);
+        let query_params = single(FieldKind::QueryParams);
+        let forward = single(FieldKind::Forward);
+        // Guards from `#[guard]` attributes have no field, so their name is empty. They come first,
+        // since they're checked first.
+        let guards = data
+            .guards()
+            .iter()
+            .map(|ty| {
+                let ty = type_name(ty);
+                quote!(::hyperdrive::support::field_info("", #ty))
+            })
+            .chain(infos(FieldKind::Guard))
+            .collect::<Vec<_>>();
+        let query = data
+            .query_fields()
+            .iter()
+            .map(|(field, name)| param_info(field, name))
+            .collect::<Vec<_>>();
+        let headers = data
+            .header_fields()
+            .iter()
+            .map(|(field, name)| param_info(field, name))
+            .collect::<Vec<_>>();
+        let cookies = data
+            .cookie_fields()
+            .iter()
+            .map(|(field, name)| param_info(field, name))
+            .collect::<Vec<_>>();
+        let host = match data.host() {
+            Some(host) => {
+                let raw = host.raw();
+                quote!(Some(#raw))
+            }
+            None => quote!(None),
+        };
+        let host_placeholders = data
+            .host()
+            .map_or(&[][..], |host| host.placeholders())
+            .iter()
+            .map(|placeholder| field_info(find_field(data, placeholder)))
+            .collect::<Vec<_>>();
+
+        let consumes = data
+            .consumes()
+            .iter()
+            .map(|range| range.as_str())
+            .collect::<Vec<_>>();
+        let produces = data
+            .produces()
+            .iter()
+            .map(|range| range.as_str())
+            .collect::<Vec<_>>();
+
+        data.routes().iter().map(move |route| {
+            let method = route.method().to_string();
+            let rank = route.rank();
+            let path = route.path().raw();
+            let guards = &guards;
+            let query = &query;
+            let headers = &headers;
+            let cookies = &cookies;
+            let host = &host;
+            let host_placeholders = &host_placeholders;
+            let consumes = &consumes;
+            let produces = &produces;
+            // Ordered like the placeholders in the path, not like the fields
+            let placeholders = route
+                .placeholders()
+                .iter()
+                .map(|placeholder| field_info(find_field(data, placeholder)));
+
+            quote! {
+                ::hyperdrive::RouteInfo::new(#method, #path, #variant)
+                    .with_doc(#doc)
+                    .with_placeholders(&[ #(#placeholders),* ])
+                    .with_host(#host)
+                    .with_host_placeholders(&[ #(#host_placeholders),* ])
+                    .with_rank(#rank)
+                    .with_consumes(&[ #(#consumes),* ])
+                    .with_produces(&[ #(#produces),* ])
+                    .with_body(#body)
+                    .with_query_params(#query_params)
+                    .with_query(&[ #(#query),* ])
+                    .with_headers(&[ #(#headers),* ])
+                    .with_cookies(&[ #(#cookies),* ])
+                    .with_forward(#forward)
+                    .with_guards(&[ #(#guards),* ])
+            }
+        })
+    });
+
+    // One `Operation` per entry in `ROUTES`, with the query parameter names filled in
+    let mut query_bounds = Vec::new();
+    let operations = variants
+        .iter()
+        .flat_map(|data| {
+            let query_ty = data
+                .field_uses()
+                .find(|(_, kind)| *kind == FieldKind::QueryParams)
+                .map(|(field, _)| &field.ty);
+            data.routes().iter().map(move |_| query_ty)
+        })
+        .enumerate()
+        .map(|(i, query_ty)| {
+            let query_params = match query_ty {
+                Some(ty) => {
+                    // Like the reverse routing functions, this only requires the bound to
+                    // hold when the function is actually used.
+                    query_bounds.push(quote!(
+                        for<'__hyperdrive> #ty: ::hyperdrive::serde::de::DeserializeOwned
+                    ));
+                    quote!(::hyperdrive::support::query_param_names::<#ty>())
+                }
+                None => quote!(::std::vec::Vec::new()),
+            };
+            quote!(::hyperdrive::openapi::Operation::new(&Self::ROUTES[#i], #query_params))
+        })
+        .collect::<Vec<_>>();
+
+    quote! {
+        #[allow(dead_code)]
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// All routes that are matched by the `FromRequest` implementation, in declaration
+            /// order.
+            #vis const ROUTES: &'static [::hyperdrive::RouteInfo] = &[
+                #(#routes),*
+            ];
+
+            /// Returns a description of all routes, to be added to an OpenAPI document.
+            #vis fn openapi_operations() -> ::std::vec::Vec<::hyperdrive::openapi::Operation>
+            where #(#query_bounds),*
+            {
+                vec![ #(#operations),* ]
+            }
+        }
+    }
+}
+
+/// Returns the field bound to the placeholder `name`.
+fn find_field<'a>(data: &'a VariantData, name: &Ident) -> &'a Field {
+    data.field_uses()
+        .map(|(field, _)| field)
+        .find(|field| field.ident.as_ref() == Some(name))
+        .expect("internal error: couldn't find placeholder field")
+}
+
+fn field_info(field: &Field) -> TokenStream {
+    let name = field
+        .ident
+        .as_ref()
+        .expect("#[derive(FromRequest)] requires named fields")
+        .to_string();
+    let ty = type_name(&field.ty);
+    quote! {
+        ::hyperdrive::support::field_info(#name, #ty)
+    }
+}
+
+/// Generates a `ParamInfo` for a field bound to the query parameter, header or cookie `name`.
+fn param_info(field: &Field, name: &str) -> TokenStream {
+    let field_info = field_info(field);
+    let required = option_inner(&field.ty).is_none();
+    quote! {
+        ::hyperdrive::support::param_info(#name, #field_info, #required)
+    }
+}
@@ -0,0 +1,311 @@
+//! The segment trie matching request paths against all routes at once.
+//!
+//! The trie is compiled into a function that walks the request path segment by segment. Literal
+//! segments are compared using a `match` on `&str`, and placeholders are captured in the same pass.
+//! Only placeholders with a constraint (`{id:int}`) and segments mixing literal text and
+//! placeholders (`{name}.{ext}`) are matched using a regex, which is stored in the generated
+//! `SEGMENTS` static.
+
+use super::parse::{PathMap, PathSegment};
+use indexmap::{IndexMap, IndexSet};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// A trie of all paths in a `PathMap`, keyed by path segment.
+pub struct Trie {
+    root: Node,
+    /// Index of the asterisk path `*`, if it's used.
+    asterisk: Option<usize>,
+    /// Anchored regexes matching a single segment, with a capture group for each placeholder.
+    segment_regexes: IndexSet<String>,
+    /// The maximum number of placeholders in any path.
+    captures: usize,
+}
+
+#[derive(Default)]
+struct Node {
+    /// Paths (by index in the `PathMap`) ending at this node, along with the number of optional
+    /// placeholders (`{name?}`) left out, whose captures have to be cleared.
+    ends: Vec<(usize, usize)>,
+    /// Paths continuing with a `{rest...}` placeholder at this node, along with the literal
+    /// suffix that has to follow it (eg. `/blob` for `/repos/{path...}/blob`).
+    rests: Vec<(usize, String)>,
+    /// Children reached by a literal segment.
+    literals: IndexMap<String, Node>,
+    /// Children reached by a segment containing placeholders, in the order they were added.
+    placeholders: Vec<(Matcher, Node)>,
+}
+
+/// How a segment containing placeholders is matched.
+#[derive(PartialEq)]
+enum Matcher {
+    /// Matches any non-empty segment and captures it.
+    Any,
+    /// Matches the segment against the regex with the given index in `SEGMENTS`, capturing
+    /// the given number of groups.
+    Regex(usize, usize),
+}
+
+impl Node {
+    fn child(&mut self, matcher: Matcher) -> &mut Node {
+        let pos = match self.placeholders.iter().position(|(m, _)| *m == matcher) {
+            Some(pos) => pos,
+            None => {
+                self.placeholders.push((matcher, Node::default()));
+                self.placeholders.len() - 1
+            }
+        };
+        &mut self.placeholders[pos].1
+    }
+}
+
+impl Trie {
+    pub fn build(pathmap: &PathMap) -> Self {
+        let mut this = Self {
+            root: Node::default(),
+            asterisk: None,
+            segment_regexes: IndexSet::new(),
+            captures: 0,
+        };
+
+        for (index, pathinfo) in pathmap.paths().enumerate() {
+            this.captures = this.captures.max(pathinfo.regex().captures_len());
+
+            let path = pathinfo.path();
+            let segments = path.matched_segments();
+            if segments.is_empty() {
+                this.asterisk = Some(index);
+                continue;
+            }
+
+            // Every prefix containing all required segments is matched
+            for omitted in 0..=path.optional_segments() {
+                let segments = &segments[..segments.len() - omitted];
+                this.insert(index, segments, omitted, false);
+                if path.ignores_trailing_slash() {
+                    this.insert(index, segments, omitted, true);
+                }
+            }
+        }
+
+        this
+    }
+
+    /// Adds the path with the given `index` and `segments`, optionally followed by a trailing
+    /// slash (an empty segment).
+    ///
+    /// `omitted` is the number of optional placeholders that were left out of `segments`.
+    fn insert(
+        &mut self,
+        index: usize,
+        segments: &[PathSegment],
+        omitted: usize,
+        trailing_slash: bool,
+    ) {
+        let mut node = &mut self.root;
+        for (i, segment) in segments.iter().enumerate() {
+            node = match segment {
+                PathSegment::Literal(lit) => node.literals.entry(lit.clone()).or_default(),
+                PathSegment::Placeholder(_, constraint) | PathSegment::Optional(_, constraint)
+                    if constraint.is_any() =>
+                {
+                    node.child(Matcher::Any)
+                }
+                PathSegment::Placeholder(..)
+                | PathSegment::Optional(..)
+                | PathSegment::Mixed(_) => {
+                    let regex = format!("^{}$", segment.regex());
+                    let (regex, _) = self.segment_regexes.insert_full(regex);
+                    node.child(Matcher::Regex(regex, segment.placeholders().count()))
+                }
+                PathSegment::Rest(_) => {
+                    // Only literal segments can follow, which are matched as a suffix
+                    let mut suffix = String::new();
+                    for segment in &segments[i + 1..] {
+                        match segment {
+                            PathSegment::Literal(lit) => {
+                                suffix.push('/');
+                                suffix.push_str(lit);
+                            }
+                            _ => unreachable!("non-literal segment after rest placeholder"),
+                        }
+                    }
+                    if trailing_slash {
+                        suffix.push('/');
+                    }
+                    node.rests.push((index, suffix));
+                    return;
+                }
+            };
+        }
+
+        if trailing_slash {
+            node = node.literals.entry(String::new()).or_default();
+        }
+        node.ends.push((index, omitted));
+    }
+
+    /// Returns the maximum number of placeholders in any path.
+    pub fn captures(&self) -> usize {
+        self.captures
+    }
+
+    /// Returns the regexes that have to be stored in the generated `SEGMENTS` static.
+    pub fn segment_regexes(&self) -> impl Iterator<Item = &str> {
+        self.segment_regexes.iter().map(String::as_str)
+    }
+
+    /// Generates a function `match_path` matching a request path against all paths in the trie.
+    ///
+    /// If `all` is `false`, the function returns the index of the first matching path along with
+    /// its captured placeholders, as an `Option<(usize, [&str; N])>`. If `all` is `true`, it
+    /// returns a `Vec` of all matching paths instead.
+    pub fn match_path_fn(&self, all: bool) -> TokenStream {
+        let n = self.captures;
+        let found = |index: usize| {
+            if all {
+                quote!(matches.push((#index, caps));)
+            } else {
+                quote!(return Some((#index, caps));)
+            }
+        };
+        let (ret, init, result) = if all {
+            (
+                quote!(Vec<(usize, [&str; #n])>),
+                quote!(let mut matches = Vec::new();),
+                quote!(matches),
+            )
+        } else {
+            (quote!(Option<(usize, [&str; #n])>), quote!(), quote!(None))
+        };
+
+        let asterisk = self.asterisk.map(|index| {
+            let found = found(index);
+            quote! {
+                if path == "*" {
+                    #found
+                }
+            }
+        });
+        let root = self.root.code(0, &found);
+
+        quote! {
+            fn match_path(path: &str) -> #ret {
+                #init
+                let mut caps: [&str; #n] = [""; #n];
+                #asterisk
+                if path.starts_with('/') {
+                    let pos = Some(1);
+                    #root
+                }
+                #result
+            }
+        }
+    }
+}
+
+impl Node {
+    /// Generates the code matching the rest of the path against this node.
+    ///
+    /// The code expects `path` to be the request path and `pos` to be the byte offset of the next
+    /// segment in it (`None` if the whole path was matched already). `caps` holds the captured
+    /// placeholders, `offset` of which have been captured by the parent nodes.
+    fn code(&self, offset: usize, found: &dyn Fn(usize) -> TokenStream) -> TokenStream {
+        let ends = self.ends.iter().map(|&(index, omitted)| {
+            // Captures of left out optional placeholders might be left over from another branch
+            let found = found(index);
+            let slots = offset..offset + omitted;
+            quote! {
+                #( caps[#slots] = ""; )*
+                #found
+            }
+        });
+        let rests = if self.rests.is_empty() {
+            quote!()
+        } else {
+            let rests = self.rests.iter().map(|(index, suffix)| {
+                let found = found(*index);
+                if suffix.is_empty() {
+                    quote! {
+                        caps[#offset] = &path[start..];
+                        #found
+                    }
+                } else {
+                    quote! {
+                        if let Some(rest) = path[start..].strip_suffix(#suffix) {
+                            caps[#offset] = rest;
+                            #found
+                        }
+                    }
+                }
+            });
+            quote!(#(#rests)*)
+        };
+
+        let literals = if self.literals.is_empty() {
+            quote!()
+        } else {
+            let (literals, children): (Vec<_>, Vec<_>) = self
+                .literals
+                .iter()
+                .map(|(lit, child)| (lit, child.code(offset, found)))
+                .unzip();
+            quote! {
+                match segment {
+                    #( #literals => { #children } )*
+                    _ => {}
+                }
+            }
+        };
+
+        let placeholders = self.placeholders.iter().map(|(matcher, child)| match matcher {
+            Matcher::Any => {
+                let child = child.code(offset + 1, found);
+                quote! {
+                    if !segment.is_empty() {
+                        caps[#offset] = segment;
+                        #child
+                    }
+                }
+            }
+            Matcher::Regex(index, captures) => {
+                let child = child.code(offset + captures, found);
+                let slots = offset..offset + captures;
+                let groups = 1..=*captures;
+                quote! {
+                    if let Some(segment_caps) = SEGMENTS[#index].captures(segment) {
+                        #(
+                            caps[#slots] = segment_caps
+                                .get(#groups)
+                                .expect("internal error: capture group did not match anything")
+                                .as_str();
+                        )*
+                        #child
+                    }
+                }
+            }
+        });
+
+        let segment = if self.literals.is_empty() && self.placeholders.is_empty() {
+            quote!()
+        } else {
+            quote! {
+                let (segment, pos) = hyperdrive::support::next_segment(path, start);
+                #literals
+                #(#placeholders)*
+            }
+        };
+
+        quote! {
+            match pos {
+                None => {
+                    #(#ends)*
+                }
+                Some(start) => {
+                    #rests
+                    #segment
+                }
+            }
+        }
+    }
+}
@@ -5,7 +5,7 @@
 //!
 //! [`hyperdrive`]: https://docs.rs/hyperdrive
 
-#![recursion_limit = "256"]
+#![recursion_limit = "512"]
 #![warn(rust_2018_idioms)]
 
 use synstructure::decl_derive;
@@ -20,12 +20,14 @@ use request_context::derive_request_context;
 decl_derive!([FromRequest, attributes(
     // Attributes need to be kept in sync with from_request/parse.rs
 
-    context, body, forward, query_params,
+    context, prefix, host, consumes, produces, trailing_slash, guard,
+    body, forward, query_params, query, header, cookie, raw, default,
 
     // We support all HTTP verbs from RFC 7231 as well as PATCH
-    get, head, post, put, delete, connect, options, trace, patch
+    get, head, post, put, delete, connect, options, trace, patch,
 
-    // FIXME support arbitrary HTTP verbs (eg. for WebDAV)
+    // Any other HTTP verb (eg. for WebDAV), or every verb
+    route, any
 )] => derive_from_request);
 
 decl_derive!([RequestContext, attributes(
@@ -1,42 +1,52 @@
+use crate::utils::{gen_impl, Errors};
 use proc_macro2::TokenStream;
 use quote::{quote, ToTokens};
 use syn::{Attribute, Data, Index, Meta};
 use synstructure::Structure;
 
 pub fn derive_request_context(s: Structure<'_>) -> TokenStream {
-    deny_attr("as_ref", &s.ast().attrs);
+    expand(s).unwrap_or_else(|errors| errors.to_compile_error())
+}
+
+/// Generates the `AsRef` and `RequestContext` impls, or returns all errors found in the input.
+fn expand(s: Structure<'_>) -> Result<TokenStream, Errors> {
+    let mut errors = Errors::new();
+    deny_attr("as_ref", &s.ast().attrs, &mut errors);
     let additional_impls = match &s.ast().data {
         Data::Struct(st) => {
             let mut impls = Vec::new();
             for (index, field) in st.fields.iter().enumerate() {
-                let as_ref_count = field
+                let as_ref_attrs = field
                     .attrs
                     .iter()
-                    .filter(|attr| match attr.parse_meta() {
-                        Ok(ref meta) if meta.name() == "as_ref" => {
-                            if let Meta::Word(_) = meta {
-                                true
-                            } else {
-                                if let Some(field) = &field.ident {
-                                    panic!(
-                                        "invalid syntax for #[as_ref] attribute on field `{}`",
-                                        field
-                                    );
-                                } else {
-                                    panic!(
-                                        "invalid syntax for #[as_ref] attribute on field of type `{}`",
-                                        field.ty.clone().into_token_stream()
-                                    );
-                                }
-                            }
-                        }
-                        _ => false,
+                    .filter_map(|attr| match attr.parse_meta() {
+                        Ok(ref meta) if meta.name() == "as_ref" => Some((attr, meta.clone())),
+                        _ => None,
                     })
-                    .count();
+                    .collect::<Vec<_>>();
+
+                for (attr, meta) in &as_ref_attrs {
+                    if let Meta::Word(_) = meta {
+                        continue;
+                    }
+
+                    let message = if let Some(field) = &field.ident {
+                        format!(
+                            "invalid syntax for #[as_ref] attribute on field `{}`",
+                            field
+                        )
+                    } else {
+                        format!(
+                            "invalid syntax for #[as_ref] attribute on field of type `{}`",
+                            field.ty.clone().into_token_stream()
+                        )
+                    };
+                    errors.push(syn::Error::new_spanned(attr, message));
+                }
 
-                match as_ref_count {
-                    0 => {} // no AsRef impl generated
-                    1 => {
+                match as_ref_attrs.as_slice() {
+                    [] => {} // no AsRef impl generated
+                    [_] => {
                         let ty = &field.ty;
                         let field_name = if let Some(name) = &field.ident {
                             quote!(#name)
@@ -44,22 +54,25 @@ pub fn derive_request_context(s: Structure<'_>) -> TokenStream {
                             let index = Index::from(index);
                             quote!(#index)
                         };
-                        impls.push(s.gen_impl(quote! {
+                        impls.push(gen_impl(&s, quote! {
                             gen impl AsRef<#ty> for @Self {
                                 fn as_ref(&self) -> &#ty { &self.#field_name }
                             }
                         }));
                     }
-                    _ => {
+                    [_, (extra, _), ..] => {
                         let name = if let Some(name) = &field.ident {
                             name.into_token_stream()
                         } else {
                             field.ty.clone().into_token_stream()
                         };
-                        panic!(
-                            "too many #[as_ref] attributes on `{}` (only one is permitted)",
-                            name
-                        )
+                        errors.push(syn::Error::new_spanned(
+                            extra,
+                            format!(
+                                "too many #[as_ref] attributes on `{}` (only one is permitted)",
+                                name
+                            ),
+                        ));
                     }
                 }
             }
@@ -67,23 +80,24 @@ pub fn derive_request_context(s: Structure<'_>) -> TokenStream {
         }
         Data::Enum(e) => {
             for variant in &e.variants {
-                deny_attr("as_ref", &variant.attrs);
+                deny_attr("as_ref", &variant.attrs, &mut errors);
 
                 for field in &variant.fields {
-                    deny_attr("as_ref", &field.attrs);
+                    deny_attr("as_ref", &field.attrs, &mut errors);
                 }
             }
             Vec::new()
         }
         Data::Union(u) => {
             for field in &u.fields.named {
-                deny_attr("as_ref", &field.attrs);
+                deny_attr("as_ref", &field.attrs, &mut errors);
             }
             Vec::new()
         }
     };
+    errors.finish(())?;
 
-    let asref_nocontext = s.gen_impl(quote!(
+    let asref_nocontext = gen_impl(&s, quote!(
         extern crate hyperdrive;
         use hyperdrive::NoContext;
 
@@ -91,19 +105,19 @@ pub fn derive_request_context(s: Structure<'_>) -> TokenStream {
             fn as_ref(&self) -> &NoContext { &NoContext }
         }
     ));
-    let asref_self = s.gen_impl(quote!(
+    let asref_self = gen_impl(&s, quote!(
         gen impl AsRef<Self> for @Self {
             fn as_ref(&self) -> &Self { self }
         }
     ));
-    let request_context = s.gen_impl(quote!(
+    let request_context = gen_impl(&s, quote!(
         extern crate hyperdrive;
         use hyperdrive::RequestContext;
 
         gen impl RequestContext for @Self {}
     ));
 
-    quote!(
+    Ok(quote!(
         #asref_nocontext
 
         #asref_self
@@ -111,17 +125,20 @@ pub fn derive_request_context(s: Structure<'_>) -> TokenStream {
         #(#additional_impls)*
 
         #request_context
-    )
+    ))
 }
 
-fn deny_attr<'a, I>(name: &str, attrs: I)
+fn deny_attr<'a, I>(name: &str, attrs: I, errors: &mut Errors)
 where
     I: IntoIterator<Item = &'a Attribute>,
 {
     for attr in attrs {
         if let Ok(meta) = attr.parse_meta() {
             if meta.name() == name {
-                panic!("#[{}] attribute is only allowed on struct fields", name);
+                errors.push(syn::Error::new_spanned(
+                    attr,
+                    format!("#[{}] attribute is only allowed on struct fields", name),
+                ));
             }
         }
     }
@@ -129,21 +146,21 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::derive_request_context;
-    use synstructure::test_derive;
+    use super::expand;
+    use synstructure::Structure;
 
     /// Expands the given item by putting a `#[derive(RequestContext)]` on it.
+    ///
+    /// Panics with all error messages (one per line) if the derive rejects the item.
     macro_rules! expand {
         (
             $i:item
-        ) => {
-            test_derive! {
-                derive_request_context {
-                    $i
-                }
-                expands to {} no_build
+        ) => {{
+            let input: syn::DeriveInput = syn::parse_quote!($i);
+            if let Err(errors) = expand(Structure::new(&input)) {
+                panic!("{}", errors);
             }
-        };
+        }};
     }
 
     #[test]
@@ -1,4 +1,83 @@
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use quote::quote;
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use synstructure::Structure;
+
+/// Calls `Structure::gen_impl` and silences the `non_local_definitions` lint for the result.
+///
+/// `gen_impl` wraps the generated impl inside a named dummy `const`, which newer compilers
+/// consider to be a non-local impl definition.
+pub fn gen_impl(s: &Structure<'_>, cfg: TokenStream) -> TokenStream {
+    let generated = s.gen_impl(cfg);
+    quote! {
+        #[allow(unknown_lints, non_local_definitions)]
+        #generated
+    }
+}
+
+/// Collects the errors found in the input of a custom derive, so that all of them can be reported
+/// at once (instead of stopping at the first one).
+#[derive(Default)]
+pub struct Errors(Vec<syn::Error>);
+
+impl Errors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, error: syn::Error) {
+        self.0.push(error);
+    }
+
+    /// Records the error in `result`, if any, and returns the value otherwise.
+    pub fn ok<T>(&mut self, result: syn::Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.push(e);
+                None
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns `value` if no errors were recorded, and `self` otherwise.
+    pub fn finish<T>(self, value: T) -> Result<T, Self> {
+        if self.is_empty() {
+            Ok(value)
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Turns the errors into `compile_error!` invocations pointing at the offending tokens.
+    pub fn to_compile_error(&self) -> TokenStream {
+        self.0.iter().map(syn::Error::to_compile_error).collect()
+    }
+}
+
+impl From<syn::Error> for Errors {
+    fn from(error: syn::Error) -> Self {
+        Errors(vec![error])
+    }
+}
+
+/// Lists the error messages, one per line.
+impl fmt::Display for Errors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
 
 /// Stores an object of type `T` and implements traits by calling a function
 /// returning a proxy `H`.
@@ -46,3 +125,159 @@ impl<T, H: PartialEq + ?Sized> PartialEq for ByProxy<T, H> {
 }
 
 impl<T, H: Eq + ?Sized> Eq for ByProxy<T, H> {}
+
+/// Converts a `CamelCase` identifier to `snake_case`.
+pub fn snake_case(ident: &str) -> String {
+    let chars = ident.chars().collect::<Vec<_>>();
+    let mut snake = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            // Start a new word when a lowercase letter or digit is followed by an uppercase one,
+            // or at the last capital of an acronym (`HTTPServer` -> `http_server`).
+            let prev = if i == 0 { None } else { Some(chars[i - 1]) };
+            let next = chars.get(i + 1);
+            let new_word = match prev {
+                None | Some('_') => false,
+                Some(prev) if prev.is_lowercase() || prev.is_numeric() => true,
+                Some(prev) => prev.is_uppercase() && next.is_some_and(|n| n.is_lowercase()),
+            };
+            if new_word {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// If `ty` is written as `Option<T>` (possibly with a path like `std::option::Option<T>`), returns
+/// `T`.
+///
+/// This is purely syntactic, so type aliases of `Option` aren't detected.
+pub fn option_inner(ty: &syn::Type) -> Option<&syn::Type> {
+    let path = match ty {
+        syn::Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+    let segment = path.segments.last()?.into_value();
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => {
+            match args.args.first()?.into_value() {
+                syn::GenericArgument::Type(inner) => Some(inner),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Formats a type the way it would usually be written by hand.
+///
+/// Unlike the `Display` impl of `TokenStream`, this doesn't put spaces between every token
+/// (`Vec<u8>` instead of `Vec < u8 >`).
+pub fn type_name(ty: &syn::Type) -> String {
+    let mut name = String::new();
+    write_tokens(&mut name, quote!(#ty));
+    name.trim().to_string()
+}
+
+fn write_tokens(out: &mut String, tokens: TokenStream) {
+    // Whether the last token written was an identifier or literal, in which case the next one
+    // needs to be separated by a space
+    let mut prev_word = false;
+    for tt in tokens {
+        match tt {
+            TokenTree::Ident(ident) => {
+                if prev_word {
+                    out.push(' ');
+                }
+                out.push_str(&ident.to_string());
+                prev_word = true;
+            }
+            TokenTree::Literal(lit) => {
+                if prev_word {
+                    out.push(' ');
+                }
+                out.push_str(&lit.to_string());
+                prev_word = true;
+            }
+            TokenTree::Punct(punct) => {
+                match punct.as_char() {
+                    ',' | ';' => {
+                        out.push(punct.as_char());
+                        out.push(' ');
+                    }
+                    '+' | '=' => {
+                        out.push(' ');
+                        out.push(punct.as_char());
+                        out.push(' ');
+                    }
+                    '-' => out.push_str(" -"),
+                    '>' if out.ends_with('-') => out.push_str("> "),
+                    c => out.push(c),
+                }
+                prev_word = false;
+            }
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::None => ("", ""),
+                };
+                out.push_str(open);
+                write_tokens(out, group.stream());
+                out.push_str(close);
+                prev_word = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{option_inner, snake_case, type_name};
+
+    #[test]
+    fn snake() {
+        assert_eq!(snake_case("Index"), "index");
+        assert_eq!(snake_case("UserInfo"), "user_info");
+        assert_eq!(snake_case("HTTPServer"), "http_server");
+        assert_eq!(snake_case("V2Api"), "v2_api");
+        assert_eq!(snake_case("Already_Snake"), "already_snake");
+        assert_eq!(snake_case("lower"), "lower");
+    }
+
+    #[test]
+    fn type_names() {
+        let name = |ty: &str| type_name(&syn::parse_str(ty).unwrap());
+
+        assert_eq!(name("u32"), "u32");
+        assert_eq!(name("Vec < u8 >"), "Vec<u8>");
+        assert_eq!(name("::std::string::String"), "::std::string::String");
+        assert_eq!(name("HashMap<String, Vec<u8>>"), "HashMap<String, Vec<u8>>");
+        assert_eq!(name("&'a mut str"), "&'a mut str");
+        assert_eq!(name("[u8; 4]"), "[u8; 4]");
+        assert_eq!(name("(u8, u16)"), "(u8, u16)");
+        assert_eq!(name("Box<dyn Error + Send>"), "Box<dyn Error + Send>");
+        assert_eq!(name("Box<dyn Iterator<Item = u8>>"), "Box<dyn Iterator<Item = u8>>");
+        assert_eq!(name("fn(u8) -> u8"), "fn(u8) -> u8");
+    }
+
+    #[test]
+    fn option() {
+        let inner = |ty: &str| option_inner(&syn::parse_str(ty).unwrap()).map(type_name);
+
+        assert_eq!(inner("Option<u32>").as_deref(), Some("u32"));
+        assert_eq!(inner("std::option::Option<Vec<u8>>").as_deref(), Some("Vec<u8>"));
+        assert_eq!(inner("u32"), None);
+        assert_eq!(inner("Vec<Option<u32>>"), None);
+        assert_eq!(inner("Option"), None);
+    }
+}
@@ -40,10 +40,11 @@ macro_rules! deref {
 
 /// Decodes an `x-www-form-urlencoded` request body (eg. sent by an HTML form).
 ///
-/// This uses [`serde_urlencoded`] to deserialize the request body.
-/// The `Content-Type` and `Content-Length` headers are ignored.
+/// This uses the [`query`] module to deserialize the request body, so
+/// repeated and nested fields (`tag=a&tag=b`, `address[city]=Berlin`) are
+/// supported. The `Content-Type` and `Content-Length` headers are ignored.
 ///
-/// [`serde_urlencoded`]: https://github.com/nox/serde_urlencoded
+/// [`query`]: ../query/index.html
 ///
 /// # Examples
 ///
@@ -90,10 +91,6 @@ macro_rules! deref {
 #[derive(Debug, PartialEq, Eq)]
 pub struct HtmlForm<T: DeserializeOwned + Send + 'static>(pub T);
 
-// Note that `serde_qs` offers more functionality than `serde_urlencoded`, but
-// uses error-chain, so its error type isn't `Sync`, which unfortunately is
-// rather annoying here.
-
 impl<T: DeserializeOwned + Send + 'static> FromBody for HtmlForm<T> {
     type Context = NoContext;
 
@@ -105,7 +102,7 @@ impl<T: DeserializeOwned + Send + 'static> FromBody for HtmlForm<T> {
         _context: &Self::Context,
     ) -> Self::Result {
         Box::new(body.concat2().map_err(Into::into).and_then(|body| {
-            match serde_urlencoded::from_bytes(&body) {
+            match crate::query::from_bytes(&body) {
                 Ok(t) => Ok(HtmlForm(t)),
                 Err(e) => Err(e.into()),
             }
@@ -15,6 +15,10 @@ pub struct Error {
     /// In case of a `405 Method Not Allowed` error, stores the allowed HTTP
     /// methods.
     allowed_methods: Cow<'static, [&'static http::Method]>,
+    /// In case of a redirect, stores the target of the redirect.
+    location: Option<String>,
+    /// Whether the request should be forwarded to the next matching route.
+    forward: bool,
     source: Option<BoxedError>,
 }
 
@@ -33,6 +37,8 @@ impl Error {
         Self {
             status,
             allowed_methods,
+            location: None,
+            forward: false,
             source,
         }
     }
@@ -121,6 +127,83 @@ impl Error {
         Self::new(StatusCode::METHOD_NOT_ALLOWED, allowed_methods.into(), None)
     }
 
+    /// Creates a `308 Permanent Redirect` response pointing the client to
+    /// `location`.
+    ///
+    /// While not an error in the HTTP sense, this allows a `FromRequest`
+    /// implementation to reject a request by redirecting it to a different
+    /// URL. It is used by the code generated by `#[derive(FromRequest)]` with
+    /// `#[trailing_slash(redirect)]`.
+    ///
+    /// Calling `Error::response` on the returned error will include a
+    /// `Location` header containing `location`.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `location` is not a valid header value (eg. because
+    /// it contains a newline).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperdrive::Error;
+    /// use http::{StatusCode, header::LOCATION};
+    ///
+    /// let err = Error::redirect("/users/");
+    /// assert_eq!(err.http_status(), StatusCode::PERMANENT_REDIRECT);
+    /// assert_eq!(err.response().headers()[LOCATION], "/users/");
+    /// ```
+    pub fn redirect<L>(location: L) -> Self
+    where
+        L: Into<String>,
+    {
+        let location = location.into();
+        assert!(
+            http::HeaderValue::from_str(&location).is_ok(),
+            "invalid redirect location `{}`",
+            location,
+        );
+
+        Self {
+            status: StatusCode::PERMANENT_REDIRECT,
+            allowed_methods: (&[][..]).into(),
+            location: Some(location),
+            forward: false,
+            source: None,
+        }
+    }
+
+    /// Creates an error that forwards the request to the next route matching
+    /// it.
+    ///
+    /// When a [`Guard`] returns this error, the code generated by
+    /// `#[derive(FromRequest)]` doesn't fail the request, but continues with
+    /// the next variant whose route accepts it (eg. one with the same path and
+    /// a higher rank). If there is none, the request is answered as if the
+    /// forwarding variant didn't exist, usually with `404 Not Found`.
+    ///
+    /// Outside of a guard, or once the request body has been consumed, this
+    /// is a regular `404 Not Found` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperdrive::Error;
+    /// use http::StatusCode;
+    ///
+    /// let err = Error::forward();
+    /// assert!(err.is_forward());
+    /// assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+    /// ```
+    ///
+    /// [`Guard`]: trait.Guard.html
+    pub fn forward() -> Self {
+        Self {
+            forward: true,
+            ..Self::from_status(StatusCode::NOT_FOUND)
+        }
+    }
+
     /// Returns the HTTP status code that describes this error.
     pub fn http_status(&self) -> StatusCode {
         self.status
@@ -188,6 +271,10 @@ impl Error {
             builder.header(http::header::ALLOW, allowed);
         }
 
+        if let Some(location) = &self.location {
+            builder.header(http::header::LOCATION, location.as_str());
+        }
+
         builder
             .body(())
             .expect("could not build HTTP response for error")
@@ -213,6 +300,21 @@ impl Error {
             None
         }
     }
+
+    /// If `self` is a redirect created by [`Error::redirect`], returns the
+    /// URL the client is redirected to.
+    ///
+    /// [`Error::redirect`]: #method.redirect
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    /// Returns whether `self` was created by [`Error::forward`].
+    ///
+    /// [`Error::forward`]: #method.forward
+    pub fn is_forward(&self) -> bool {
+        self.forward
+    }
 }
 
 impl fmt::Display for Error {
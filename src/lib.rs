@@ -153,10 +153,16 @@ TODO:
 
 pub mod body;
 mod error;
+pub mod openapi;
+pub mod query;
 mod readme;
+mod route_info;
 pub mod service;
+#[doc(hidden)]
+pub mod support;
 
 pub use error::*;
+pub use route_info::*;
 pub use hyperderive::*;
 
 // Reexport public deps for use by the custom derive
@@ -235,10 +241,9 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// requests will still be handled in parallel, so this should not negatively
 /// affect performance.
 ///
-/// In order to keep the implementation simple and user code more easily
-/// understandable, overlapping paths are not allowed (unless the paths are
-/// *exactly* the same, and the method differs), so the following will fail to
-/// compile:
+/// In order to keep user code easily understandable, overlapping paths are not
+/// allowed by default (unless the paths are *exactly* the same, and the method
+/// differs), so the following will fail to compile:
 ///
 /// ```compile_fail
 /// use from_request::{FromRequest, body::Json};
@@ -254,7 +259,24 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// }
 /// ```
 ///
-/// To fix this, you can define a custom type implementing `FromStr` and use
+/// To fix this, you can restrict the segments matched by the placeholder using
+/// a constraint (see [below](#placeholder-constraints)), so that it no longer
+/// matches `me`:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/users/{id:int}")]
+///     User { id: u32 },
+///
+///     #[get("/users/me")]
+///     Me,
+/// }
+/// ```
+///
+/// Alternatively, you can define a custom type implementing `FromStr` and use
 /// that:
 ///
 /// ```
@@ -288,6 +310,46 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// }
 /// ```
 ///
+/// ## Route Ranking
+///
+/// Overlapping routes can also be allowed explicitly by giving them different
+/// *ranks* using a `rank = N` argument. When a request matches several routes,
+/// they are tried in order of ascending rank, and routes without a `rank` have
+/// rank 0:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/users/{id}", rank = 1)]
+///     User { id: u32 },
+///
+///     #[get("/users/new")]
+///     NewUser,
+///
+///     #[get("/posts/{id}")]
+///     PostById { id: u32 },
+///
+///     #[get("/posts/{slug}", rank = 1)]
+///     PostBySlug { slug: String },
+/// }
+/// ```
+///
+/// A route is skipped, and the next one is tried, if it doesn't accept the
+/// request method, or if the `FromStr` implementation of one of its path
+/// placeholders fails. In the example above, `/posts/123` results in
+/// `PostById` while `/posts/hello` results in `PostBySlug`. If no route accepts
+/// the request, the usual `404 Not Found` or `405 Method Not Allowed` error is
+/// returned. Note that guards, `#[body]` and `#[forward]` fields are not taken
+/// into account: once a route is selected, their errors are returned directly,
+/// unless a guard [forwards](#falling-through-to-the-next-route-errorforward)
+/// the request.
+///
+/// Routes that overlap must still have different ranks. The `rank` argument
+/// can be used with every route attribute, including
+/// `#[route(METHOD, "/path", rank = N)]`.
+///
 /// ## Implicit `HEAD` routes
 ///
 /// The custom derive will create a `HEAD` route for every defined `GET` route,
@@ -297,6 +359,227 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// [`FromRequest::from_request`][`from_request`], you have to make sure no body
 /// is sent back for `HEAD` requests.
 ///
+/// ## Path Prefixes
+///
+/// If all routes of a type share a common prefix, it can be specified once by
+/// putting a `#[prefix("/path")]` attribute on the type. The prefix is
+/// prepended to the path of every route attribute (except for the asterisk
+/// path `*`), so the following type matches `/api/v2/users/{id}`:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// #[prefix("/api/v2")]
+/// enum Routes {
+///     #[get("/users/{id}")]
+///     User { id: u32 },
+/// }
+///
+/// assert_eq!(Routes::user_path(&5), "/api/v2/users/5");
+/// ```
+///
+/// The prefix must start with a `/`, and must not end with one. Note that a
+/// `#[get("/")]` route will then match the prefix followed by a `/`
+/// (`/api/v2/`). The prefix is also used for reverse routing, the `ROUTES`
+/// constant and in error messages.
+///
+/// ## Trailing Slashes
+///
+/// By default, `/users` and `/users/` are different paths, and a request for
+/// the one that has no route results in a `404 Not Found` error. This can be
+/// changed with a `#[trailing_slash(...)]` attribute on the type:
+///
+/// * `#[trailing_slash(strict)]` is the default behavior described above.
+/// * `#[trailing_slash(redirect)]` answers requests whose path only matches a
+///   route after adding or removing a trailing slash with a
+///   `308 Permanent Redirect` to the path declared in the route (keeping the
+///   query string). The redirect is returned as a [`hyperdrive::Error`],
+///   whose [`response`] includes the `Location` header. Redirects take
+///   precedence over a `#[forward]` fallback variant.
+/// * `#[trailing_slash(match_both)]` makes every route match its path with
+///   and without trailing slash. Consequently, `#[get("/users")]` and
+///   `#[get("/users/")]` are considered duplicates.
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// #[trailing_slash(redirect)]
+/// enum Routes {
+///     #[get("/users/")]
+///     Users,
+/// }
+/// ```
+///
+/// The root path `/`, the asterisk path `*` and paths ending in a
+/// `{rest...}` placeholder are not affected by the policy.
+///
+/// ## Host-based Routing
+///
+/// A `#[host("pattern")]` attribute restricts routes to requests addressed to
+/// a matching host. It can be placed on the type, where it applies to all
+/// routes, or on individual variants, overriding the type-level attribute.
+/// Host patterns consist of dot-separated labels, and a label can be a
+/// `{placeholder}` that is bound to a field using `FromStr`, just like path
+/// placeholders:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// #[host("{tenant}.example.com")]
+/// enum Routes {
+///     #[get("/users")]
+///     Users { tenant: String },
+///
+///     #[get("/users")]
+///     #[host("admin.example.com")]
+///     AllUsers,
+/// }
+/// ```
+///
+/// The host is taken from the request URI if it is in absolute form, and from
+/// the `Host` header otherwise. The port is ignored, and host names are
+/// matched (and passed to placeholders) in lowercase. Placeholders always match
+/// an entire label and are not percent-decoded.
+///
+/// Routes with the same path and method can be declared multiple times with
+/// different hosts. Host patterns with fewer placeholders are tried first, and
+/// routes without a `#[host]` attribute are tried last, matching any host. Two
+/// such routes must not be ambiguous, ie. share a host and have the same
+/// number of placeholders. If no host matches, the request is handled as if
+/// the path didn't exist.
+///
+/// Apart from that, hosts are not taken into account when checking for
+/// overlapping routes, so `#[get("/users/{id}")]` and `#[get("/users/me")]`
+/// can't be combined even if they use different hosts. Reverse routing only
+/// builds the path of a route, so host placeholders aren't passed to the
+/// `*_path` functions.
+///
+/// ## Request Media Types
+///
+/// A `#[consumes("type/subtype", ...)]` attribute on a variant restricts its
+/// routes to requests whose `Content-Type` matches one of the given media
+/// types. This allows several variants to share the same path and method, as
+/// long as they accept different media types:
+///
+/// ```
+/// use hyperdrive::{FromRequest, body::{HtmlForm, Json}};
+/// # use serde::Deserialize;
+/// # #[derive(Deserialize)]
+/// # struct Item {}
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[post("/items")]
+///     #[consumes("application/json")]
+///     CreateJson {
+///         #[body]
+///         item: Json<Item>,
+///     },
+///
+///     #[post("/items")]
+///     #[consumes("application/x-www-form-urlencoded")]
+///     CreateForm {
+///         #[body]
+///         item: HtmlForm<Item>,
+///     },
+/// }
+/// ```
+///
+/// Media types are matched case-insensitively, and parameters of the
+/// `Content-Type` (like `charset`) are ignored. The subtype (or both type and
+/// subtype) can be the wildcard `*`, as in `text/*`. Routes without
+/// `#[consumes]` accept any request, but variants with `#[consumes]` are tried
+/// first. If the path and method of a request match, but none of the routes
+/// accepts its `Content-Type` (or it has none), a `415 Unsupported Media Type`
+/// error is returned, unless there is a fallback variant.
+///
+/// ## Content Negotiation
+///
+/// Similarly, `#[produces("type/subtype", ...)]` declares the media types a
+/// variant responds with. Variants with the same path and method can produce
+/// different media types, and the one preferred by the request's `Accept`
+/// header is selected:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/items")]
+///     #[produces("text/html")]
+///     ItemsPage,
+///
+///     #[get("/items")]
+///     #[produces("application/json")]
+///     ItemsJson,
+/// }
+/// ```
+///
+/// Quality values (`q=0.5`) and wildcards (`text/*`, `*/*`) in the `Accept`
+/// header are honored, with more specific media ranges taking precedence. If
+/// several variants are equally preferred, or the request has no `Accept`
+/// header, the first one is used. Variants without `#[produces]` are only
+/// used if none of the variants with `#[produces]` is acceptable. If no variant
+/// is acceptable, a `406 Not Acceptable` error is returned, unless there is a
+/// fallback variant.
+///
+/// ## Other HTTP methods
+///
+/// There are dedicated route attributes for all methods defined in RFC 7231, as
+/// well as for `PATCH`. Any other method, such as those used by WebDAV, can be
+/// matched using the `#[route(METHOD, "/path")]` attribute. Methods that
+/// aren't valid Rust identifiers can be given as a string literal instead:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/files/{path...}")]
+///     Get { path: String },
+///
+///     #[route(PROPFIND, "/files/{path...}")]
+///     PropFind { path: String },
+///
+///     #[route("VERSION-CONTROL", "/files/{path...}")]
+///     VersionControl { path: String },
+/// }
+/// ```
+///
+/// Like in requests, the method name is case-sensitive.
+///
+/// ### Matching any method (`#[any]`)
+///
+/// The `#[any("/path")]` attribute matches requests using any method, which is
+/// useful for proxies, health checks and catch-all endpoints. The request
+/// method can be obtained with an `http::Method` field, which is a guard:
+///
+/// ```
+/// use hyperdrive::{FromRequest, http::Method};
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/health")]
+///     Health,
+///
+///     #[any("/health")]
+///     HealthOther { method: Method },
+///
+///     #[any("/proxy/{path...}")]
+///     Proxy { method: Method, path: String },
+/// }
+/// ```
+///
+/// Routes for a specific method (including implicit `HEAD` routes) are
+/// preferred over `#[any]` routes with the same path, regardless of their
+/// rank. `#[any]` routes are only tried when none of them accepts the request
+/// (eg. `GET /health` results in `Health`, while `DELETE /health` results in
+/// `HealthOther`). Since every method is accepted, paths with an `#[any]`
+/// route never result in `405 Method Not Allowed`.
+///
 /// ## Extracting Request Data
 ///
 /// The custom derive provides easy access to various kinds of data encoded in a
@@ -304,6 +587,7 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 ///
 /// * The Request path (`/users/or/other/stuff`)
 /// * Query parameters (`?name=val`)
+/// * Request headers and cookies
 /// * The request body
 ///
 /// ### Extracting Path Segments (`{field}` syntax)
@@ -315,6 +599,19 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// #[get("/users/{id}")]
 /// ```
 ///
+/// Placeholders can also make up just a part of a segment, and a segment may
+/// contain multiple placeholders, as long as they are separated by literal
+/// text:
+///
+/// ```notrust
+/// #[get("/files/{name}.{ext}")]
+/// #[get("/reports/report-{year}.csv")]
+/// ```
+///
+/// When a segment could be split up in multiple ways, earlier placeholders
+/// match as much as possible (so `/files/archive.tar.gz` results in a `name`
+/// of `archive.tar` and an `ext` of `gz`).
+///
 /// To extract multiple path segments this way, the `{field...}` syntax can be
 /// used at the end of the path, which will consume the rest of the path:
 ///
@@ -322,10 +619,123 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// #[get("/static/{path...}")]
 /// ```
 ///
+/// A `{field...}` placeholder can also be followed by literal segments, which
+/// then have to make up the end of the request path. For example, this route
+/// matches `/repos/hyperdrive/src/lib.rs/blob` with a `path` of
+/// `hyperdrive/src/lib.rs`:
+///
+/// ```notrust
+/// #[get("/repos/{path...}/blob")]
+/// ```
+///
+/// Before the `FromStr` conversion, the segment is percent-decoded, so a
+/// request for `/users/J%C3%B6rg` will pass `Jörg` to `FromStr`. Rest
+/// placeholders (`{field...}`) are decoded as well, except for encoded slashes
+/// (`%2F`), which are kept as-is so that they can still be told apart from the
+/// path separator. For the same reason, encoded percent signs (`%25`) are kept
+/// too, so every `%` in a rest placeholder starts one of these two escapes. If
+/// the decoded segment is not valid UTF-8, the route does not match.
+///
+/// To opt out of decoding and receive the segment exactly as it appears in the
+/// request, mark the field with `#[raw]`:
+///
+/// ```notrust
+/// #[get("/files/{name}")]
+/// File {
+///     #[raw]
+///     name: String,
+/// },
+/// ```
+///
 /// If the `FromStr` conversion fails, the generated `FromRequest`
 /// implementation will bail out with an error (in other words, this feature
 /// cannot be used to try multiple routes in sequence until one matches).
 ///
+/// #### Placeholder Constraints
+///
+/// By default, a placeholder matches any non-empty path segment. A constraint
+/// can be appended to restrict the matched segments, either by naming a
+/// predefined constraint (`{id:int}`) or with a regular expression
+/// (`{id:[0-9]+}`). The predefined constraints are:
+///
+/// * `int`: An optionally negative decimal number (`-?[0-9]+`).
+/// * `uint`: A decimal number (`[0-9]+`).
+/// * `alpha`: ASCII letters (`[a-zA-Z]+`).
+/// * `alnum`: ASCII letters and digits (`[a-zA-Z0-9]+`).
+/// * `uuid`: A hyphenated UUID.
+///
+/// The constraint has to match the whole segment, and it is never able to match
+/// a `/`. Since segments that don't satisfy the constraint don't match the
+/// route, constrained placeholders will not overlap with literal segments they
+/// don't match or with placeholders whose constraints are disjoint. Note that
+/// constraints are matched against the segment before percent-decoding it.
+///
+/// #### Multiple Routes
+///
+/// A variant can have several route attributes, which don't need to use the
+/// same placeholders. Each field is filled in from the placeholder of the same
+/// name in the route that matched, regardless of its position. Fields that
+/// are missing from some of the routes must either be an `Option`, which is
+/// `None` if the route doesn't contain the placeholder, or be marked with
+/// `#[default]`, which uses the type's `Default` implementation instead:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/u/{id}")]
+///     #[get("/users/{name}/{id}")]
+///     User { id: u32, name: Option<String> },
+///
+///     #[get("/posts/{slug}")]
+///     #[get("/posts/{slug}/page/{page}")]
+///     Post {
+///         slug: String,
+///         #[default]
+///         page: u32,
+///     },
+/// }
+/// ```
+///
+/// Routes of the same variant that match the same paths (eg. `#[get]` and
+/// `#[post]` routes with the same path) still have to use the same
+/// placeholders in the same order.
+///
+/// #### Optional Segments
+///
+/// Placeholders at the end of a path can be made optional with a `?` after
+/// their name (`{month?}`, or `{month?:uint}` with a constraint). The route
+/// then also matches paths that leave out the optional segments, starting with
+/// the last one. An optional placeholder has to make up an entire segment, it
+/// can't be the first segment of the path, and it can only be followed by
+/// other optional placeholders. Like fields missing from some routes, their
+/// fields have to be an `Option` or be marked with `#[default]`:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     // Matches `/archive/2019`, `/archive/2019/5` and `/archive/2019/5/17`
+///     #[get("/archive/{year}/{month?}/{day?}")]
+///     Archive {
+///         year: u16,
+///         month: Option<u8>,
+///         day: Option<u8>,
+///     },
+///
+///     #[get("/feed/{page?:uint}")]
+///     Feed {
+///         #[default]
+///         page: u32,
+///     },
+/// }
+/// ```
+///
+/// Routes with optional segments overlap with every route matching one of the
+/// paths they accept, so `/archive/{year}` could not be added to the example.
+///
 /// ### Extracting the request body (`#[body]` attribute)
 ///
 /// Putting `#[body]` on a field of a variant will deserialize the request body
@@ -374,8 +784,95 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// the `pagination` field.
 ///
 /// The type of the `#[query_params]` field must implement serde's `Deserialize`
-/// trait and the conversion will be performed using the `serde_urlencoded`
-/// crate.
+/// trait and the conversion will be performed using the [`query`] module. It
+/// supports repeated parameters (`?tag=a&tag=b` for a `Vec<String>`) and
+/// nested structs and maps using bracket notation (`?filter[status]=open`).
+///
+/// [`query`]: query/index.html
+///
+/// For routes that only take one or two parameters, a dedicated struct is
+/// often overkill. Instead, individual query parameters can be bound to fields
+/// using `#[query("name")]`, or `#[query]` to use the field name:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/users")]
+///     UserList {
+///         #[query("per-page")]
+///         per_page: Option<u32>,
+///         #[query]
+///         start_id: u32,
+///     },
+/// }
+/// ```
+///
+/// Like path segments, these fields are parsed using `FromStr`. The query
+/// string is only decoded once for all of them. A field of type `Option<T>`
+/// makes its parameter optional, while any other field rejects requests
+/// without it with a `400 Bad Request` error naming the parameter. If a
+/// parameter is repeated, the first value is used.
+///
+/// `#[query]` fields can be combined with a `#[query_params]` struct. The
+/// parameters bound to `#[query]` fields are then not passed to the struct, so
+/// each parameter is only decoded into one field.
+///
+/// ### Extracting request headers (`#[header]` attribute)
+///
+/// A single request header can be extracted by marking a field with
+/// `#[header("Header-Name")]`. The header value is parsed using the field's
+/// `FromStr` implementation:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/items/{id}")]
+///     Item {
+///         id: u32,
+///         #[header("X-Request-Id")]
+///         request_id: u64,
+///         #[header("If-None-Match")]
+///         etag: Option<String>,
+///     },
+/// }
+/// ```
+///
+/// Header names are case-insensitive. If the header is missing or its value
+/// can't be parsed, the request is rejected with a `400 Bad Request` error
+/// naming the header. Fields of type `Option<T>` make the header optional: They
+/// are set to `None` when the header is missing, and `T` is used to parse the
+/// value otherwise. If a header is sent multiple times, only the first value is
+/// used.
+///
+/// ### Extracting cookies (`#[cookie]` attribute)
+///
+/// Similarly, `#[cookie("name")]` extracts the value of a single cookie from
+/// the request's `Cookie` headers:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     #[get("/account")]
+///     Account {
+///         #[cookie("session")]
+///         session_id: String,
+///         #[cookie("theme")]
+///         theme: Option<String>,
+///     },
+/// }
+/// ```
+///
+/// Cookie names are case-sensitive. The cookie value is stripped of
+/// surrounding double quotes and percent-decoded before being passed to
+/// `FromStr`. Missing and invalid cookies are handled like headers: They
+/// result in a `400 Bad Request` error, unless the field is an `Option` and
+/// the cookie is missing.
 ///
 /// ## Guards
 ///
@@ -421,6 +918,114 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// }
 /// ```
 ///
+/// ### Guards without a field (`#[guard]` attribute)
+///
+/// If a guard is only needed to reject requests and its value is never used,
+/// it can be specified with `#[guard(Type)]` on the variant (or struct)
+/// instead of adding a field. Putting `#[guard(Type)]` on an enum applies the
+/// guard to every variant, including the fallback variant (if any).
+///
+/// Guards from attributes are checked before any guard fields: First the ones
+/// on the enum, then the ones on the variant, each in the order they are
+/// written. Like with guard fields, the first guard that fails rejects the
+/// request with its error, and the remaining guards aren't checked.
+///
+/// ```
+/// use hyperdrive::{FromRequest, Guard};
+/// # use hyperdrive::{BoxedError, NoContext};
+/// # use std::sync::Arc;
+///
+/// struct User;
+/// struct Admin;
+///
+/// impl Guard for User {
+///     // (omitted for brevity)
+/// #     type Context = NoContext;
+/// #     type Result = Result<Self, BoxedError>;
+/// #     fn from_request(_: &Arc<http::Request<()>>, _: &NoContext) -> Result<Self, BoxedError> {
+/// #         Ok(User)
+/// #     }
+/// }
+///
+/// impl Guard for Admin {
+///     // (omitted for brevity)
+/// #     type Context = NoContext;
+/// #     type Result = Result<Self, BoxedError>;
+/// #     fn from_request(_: &Arc<http::Request<()>>, _: &NoContext) -> Result<Self, BoxedError> {
+/// #         Ok(Admin)
+/// #     }
+/// }
+///
+/// #[derive(FromRequest)]
+/// #[guard(User)]
+/// enum AdminRoute {
+///     #[get("/admin")]
+///     Dashboard,
+///
+///     #[post("/admin/shutdown")]
+///     #[guard(Admin)]
+///     Shutdown,
+/// }
+/// ```
+///
+/// ### Falling through to the next route (`Error::forward`)
+///
+/// A guard can fail with [`Error::forward`] instead of a regular error to
+/// let another variant handle the request. The request is then routed again
+/// as if the variant didn't exist, so the route with the next higher rank
+/// that accepts the request is tried. If no route is left, the usual `404 Not
+/// Found` (or `405 Method Not Allowed`) error is returned, or the request is
+/// passed to the fallback variant.
+///
+/// This allows serving different variants depending on who makes the
+/// request:
+///
+/// ```
+/// use hyperdrive::{FromRequest, Guard, Error, BoxedError};
+/// # use hyperdrive::NoContext;
+/// # use std::sync::Arc;
+///
+/// struct User;
+/// struct Admin;
+///
+/// impl Guard for Admin {
+///     type Context = NoContext;
+///     type Result = Result<Self, BoxedError>;
+///
+///     fn from_request(request: &Arc<http::Request<()>>, _: &NoContext) -> Self::Result {
+///         if request.headers().contains_key("X-Admin") {
+///             Ok(Admin)
+///         } else {
+///             // Not an admin, try the next route
+///             Err(Error::forward().into())
+///         }
+///     }
+/// }
+///
+/// impl Guard for User {
+///     // (omitted for brevity)
+/// #     type Context = NoContext;
+/// #     type Result = Result<Self, BoxedError>;
+/// #     fn from_request(_: &Arc<http::Request<()>>, _: &NoContext) -> Result<Self, BoxedError> {
+/// #         Ok(User)
+/// #     }
+/// }
+///
+/// #[derive(FromRequest)]
+/// enum Route {
+///     #[get("/dashboard")]
+///     AdminDashboard { admin: Admin },
+///
+///     #[get("/dashboard", rank = 1)]
+///     UserDashboard { user: User },
+/// }
+/// ```
+///
+/// Only guards (fields and `#[guard]` attributes) can forward requests, since
+/// the request body hasn't been consumed when they're checked. An
+/// [`Error::forward`] returned by a `#[body]` or `#[forward]` field, or by the
+/// fallback variant, is a regular `404 Not Found` error.
+///
 /// ## Forwarding
 ///
 /// A field whose type implements `FromRequest` can be marked with `#[forward]`.
@@ -465,6 +1070,104 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// }
 /// ```
 ///
+/// ## Reverse Routing
+///
+/// The custom derive also generates code for the opposite direction: Turning a
+/// route back into the request path it was decoded from. This avoids
+/// hardcoding paths in templates or redirects, which could get out of sync
+/// with the route attributes.
+///
+/// Every variant with a route attribute gets an associated function named
+/// after the variant (in `snake_case`, with a `_path` suffix), which takes
+/// references to the values of all placeholders and returns the path. For
+/// convenience, a `to_path` method is also generated that works on any value,
+/// returning `None` if there's no route for it:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Route {
+///     #[get("/")]
+///     Index,
+///
+///     #[get("/users/{id}")]
+///     UserInfo { id: u32 },
+///
+///     #[get("/users/{name}/files/{path...}")]
+///     UserFile { name: String, path: String },
+/// }
+///
+/// assert_eq!(Route::Index.to_path().unwrap(), "/");
+/// assert_eq!(Route::UserInfo { id: 5 }.to_path().unwrap(), "/users/5");
+/// assert_eq!(Route::user_info_path(&5), "/users/5");
+///
+/// let file = Route::UserFile {
+///     name: "J\u{f6}rg".to_string(),
+///     path: "photos/me and bob.jpg".to_string(),
+/// };
+/// assert_eq!(file.to_path().unwrap(), "/users/J%C3%B6rg/files/photos/me%20and%20bob.jpg");
+/// ```
+///
+/// The `_path` functions are useful for variants that also contain guards or a
+/// request body, which are not needed to build the path.
+///
+/// Placeholder values are formatted with their `Display` implementation and
+/// percent-encoded (for `{rest...}` placeholders, the `/` separators and the
+/// `%25` and `%2F` escapes are kept, so decoded values round-trip).
+/// Placeholder types that don't implement `Display` can still be used in
+/// routes, but the generated functions can't be called for them. If a variant
+/// has multiple route attributes, the first one determines the path. `to_path`
+/// skips routes with `Option` placeholders that are `None`, and the `_path`
+/// function takes the `Option`'s inner type. Optional placeholders
+/// (`{name?}`) are passed to the `_path` function as an `Option` instead, and
+/// a `None` leaves out its segment along with all segments after it.
+///
+/// `to_path` returns `None` for variants without route attribute (like a
+/// `#[forward]`ing fallback variant), and when every route of the variant has
+/// an `Option` placeholder that is `None`.
+///
+/// Since the function names are derived from the variant names, the derive
+/// rejects variants whose function would be called `to_path` (a variant named
+/// `To`) or would have the same name as another variant's.
+///
+/// ## Route Introspection
+///
+/// The derive also emits an associated constant `ROUTES` that lists all
+/// routes of the type as [`RouteInfo`] values, in declaration order. This can
+/// be used to print a list of routes at startup, or to write tests asserting
+/// the exposed API surface:
+///
+/// ```
+/// use hyperdrive::FromRequest;
+///
+/// #[derive(FromRequest)]
+/// enum Route {
+///     /// The start page.
+///     #[get("/")]
+///     Index,
+///
+///     #[get("/users/{id}")]
+///     #[head("/users/{id}")]
+///     UserInfo { id: u32 },
+/// }
+///
+/// for route in Route::ROUTES {
+///     println!("{} {} => {}", route.method, route.path, route.variant);
+/// }
+///
+/// let routes = Route::ROUTES
+///     .iter()
+///     .map(|route| (route.method, route.path))
+///     .collect::<Vec<_>>();
+/// assert_eq!(routes, [("GET", "/"), ("GET", "/users/{id}"), ("HEAD", "/users/{id}")]);
+/// assert_eq!(Route::ROUTES[0].doc, "The start page.");
+/// ```
+///
+/// Additionally, an `openapi_operations` function is generated, which can be
+/// used to generate an OpenAPI document for the routes. Refer to the
+/// [`openapi`] module for details.
+///
 /// ## Changing the `Context` type
 ///
 /// By default, the generated code will use [`NoContext`] as the associated
@@ -497,6 +1200,11 @@ pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 /// [`RequestContext`]: trait.RequestContext.html
 /// [`Guard`]: trait.Guard.html
 /// [`NoContext`]: struct.NoContext.html
+/// [`RouteInfo`]: struct.RouteInfo.html
+/// [`openapi`]: openapi/index.html
+/// [`hyperdrive::Error`]: struct.Error.html
+/// [`Error::forward`]: struct.Error.html#method.forward
+/// [`response`]: struct.Error.html#method.response
 /// [`DefaultFuture`]: type.DefaultFuture.html
 /// [`body`]: body/index.html
 /// [`from_request`]: #tymethod.from_request
@@ -710,6 +1418,19 @@ pub trait Guard: Sized {
     fn from_request(request: &Arc<http::Request<()>>, context: &Self::Context) -> Self::Result;
 }
 
+/// Provides the method of the request.
+///
+/// This is mostly useful in variants with an `#[any("/path")]` route, which
+/// accepts requests using any method.
+impl Guard for http::Method {
+    type Context = NoContext;
+    type Result = Result<Self, BoxedError>;
+
+    fn from_request(request: &Arc<http::Request<()>>, _: &NoContext) -> Self::Result {
+        Ok(request.method().clone())
+    }
+}
+
 /// Asynchronous conversion from an HTTP request body.
 ///
 /// Types implementing this trait are provided in the [`body`] module. They
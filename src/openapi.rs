@@ -0,0 +1,407 @@
+//! Generates [OpenAPI 3] documents from `#[derive(FromRequest)]` types.
+//!
+//! The custom derive generates an associated function `openapi_operations`,
+//! which describes all routes of the type. Those can be added to a
+//! [`Document`], which can then be turned into JSON (or, since
+//! `serde_json::Value` implements `Serialize`, any other format supported by
+//! serde, such as YAML).
+//!
+//! The generated document contains:
+//!
+//! * All paths and methods (asterisk routes (`*`), `#[any]` routes and routes
+//!   using `CONNECT` or extension methods cannot be represented and are
+//!   skipped). Routes with optional placeholders (`{name?}`) are listed under
+//!   every path they match.
+//! * Path parameters, one for each placeholder.
+//! * Query parameters, one for each `#[query]` field and for every field of
+//!   the struct marked with `#[query_params]` (this only works with types that
+//!   deserialize from a struct; maps are not supported). The types of these
+//!   fields are not known, so they are described as optional strings.
+//! * Header and cookie parameters, one for each `#[header]` and `#[cookie]`
+//!   field.
+//! * The request body, with the media types listed in `#[consumes]`, or if the
+//!   `#[body]` field uses [`Json`] or [`HtmlForm`] (detected by the type name).
+//! * The media types of the default response, as listed in `#[produces]`.
+//! * A summary and description taken from the doc comment of the variant.
+//!
+//! Routes that share a path and method (eg. because they consume or produce
+//! different media types) are merged into a single operation.
+//!
+//! Since Rust types carry no schema information, only the schemas of path
+//! placeholders and `#[query]`, `#[header]` and `#[cookie]` fields are filled
+//! in (based on the name of primitive types). Responses aren't known either,
+//! so every operation only lists a default response.
+//!
+//! [OpenAPI 3]: https://spec.openapis.org/oas/v3.0.3
+//! [`Document`]: struct.Document.html
+//! [`Json`]: ../body/struct.Json.html
+//! [`HtmlForm`]: ../body/struct.HtmlForm.html
+//!
+//! # Examples
+//!
+//! ```
+//! use hyperdrive::{FromRequest, body::Json, openapi::Document};
+//! # use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Pagination {
+//!     page: u32,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Login {
+//!     user: String,
+//!     password: String,
+//! }
+//!
+//! #[derive(FromRequest)]
+//! enum Route {
+//!     /// Lists all users.
+//!     #[get("/users")]
+//!     Users {
+//!         #[query_params]
+//!         pagination: Pagination,
+//!     },
+//!
+//!     /// Shows information about a single user.
+//!     #[get("/users/{id}")]
+//!     User { id: u32 },
+//!
+//!     #[post("/login")]
+//!     Login {
+//!         #[body]
+//!         data: Json<Login>,
+//!     },
+//! }
+//!
+//! let doc = Document::new("User Service", "1.0.0")
+//!     .operations(Route::openapi_operations())
+//!     .to_json();
+//!
+//! assert_eq!(doc["openapi"], "3.0.3");
+//! assert_eq!(doc["paths"]["/users"]["get"]["summary"], "Lists all users.");
+//! assert_eq!(doc["paths"]["/users"]["get"]["parameters"][0]["name"], "page");
+//! assert_eq!(doc["paths"]["/users/{id}"]["get"]["parameters"][0]["in"], "path");
+//! assert!(doc["paths"]["/login"]["post"]["requestBody"]["content"]["application/json"].is_object());
+//! ```
+
+use crate::RouteInfo;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Description of a single route, as returned by the generated
+/// `openapi_operations` function.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    route: &'static RouteInfo,
+    query_params: Vec<&'static str>,
+}
+
+impl Operation {
+    /// Creates an operation from a route and the names of its query parameters.
+    ///
+    /// This is called by the code generated by `#[derive(FromRequest)]`.
+    #[doc(hidden)]
+    pub fn new(route: &'static RouteInfo, query_params: Vec<&'static str>) -> Self {
+        Self {
+            route,
+            query_params,
+        }
+    }
+
+    /// Returns the route described by this operation.
+    pub fn route(&self) -> &'static RouteInfo {
+        self.route
+    }
+
+    /// Returns the names of all query parameters accepted by the route.
+    pub fn query_params(&self) -> &[&'static str] {
+        &self.query_params
+    }
+
+    /// Returns the OpenAPI path templates (`{rest...}` placeholders are turned
+    /// into regular parameters, and constraints are removed).
+    ///
+    /// OpenAPI has no optional path parameters, so a route with optional
+    /// placeholders (`{name?}`) results in one template for every number of
+    /// them that can be present.
+    fn paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        let mut path = String::new();
+        let mut chars = self.route.path.chars();
+        while let Some(c) = chars.next() {
+            path.push(c);
+            if c == '{' {
+                // Optional placeholders always make up a whole segment
+                let segment_start = path.len() - 2;
+
+                // Copy the placeholder name and skip everything else up to the
+                // matching `}` (constraints may contain braces themselves)
+                let mut depth = 1;
+                let mut in_name = true;
+                for c in &mut chars {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        '?' if in_name => {
+                            paths.push(path[..segment_start].to_string());
+                            in_name = false;
+                        }
+                        ':' | '.' => in_name = false,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        path.push('}');
+                        break;
+                    } else if in_name {
+                        path.push(c);
+                    }
+                }
+            }
+        }
+        paths.push(path);
+        paths
+    }
+
+    /// Returns the Operation Object describing the route when it matches
+    /// the path template `path` (one of `self.paths()`).
+    fn to_json(&self, path: &str) -> Value {
+        let route = self.route;
+        let mut op = Map::new();
+
+        if !route.doc.is_empty() {
+            let summary = route.doc.lines().next().unwrap_or("");
+            op.insert("summary".into(), summary.into());
+            op.insert("description".into(), route.doc.into());
+        }
+
+        let mut params = route
+            .placeholders
+            .iter()
+            // Optional placeholders are left out of some paths
+            .filter(|field| path.contains(&format!("{{{}}}", field.name)))
+            .map(|field| {
+                json!({
+                    "name": field.name,
+                    "in": "path",
+                    "required": true,
+                    // `Option` fields are always present in paths containing them
+                    "schema": schema_for(strip_option(field.ty)),
+                })
+            })
+            .collect::<Vec<_>>();
+        params.extend(route.query.iter().map(|param| {
+            json!({
+                "name": param.name,
+                "in": "query",
+                "required": param.required,
+                "schema": schema_for(strip_option(param.field.ty)),
+            })
+        }));
+        // The types of `#[query_params]` fields are unknown, but every parameter needs a schema
+        params.extend(self.query_params.iter().map(|name| {
+            json!({
+                "name": name,
+                "in": "query",
+                "required": false,
+                "schema": {"type": "string"},
+            })
+        }));
+        let headers = route.headers.iter().map(|param| ("header", param));
+        let cookies = route.cookies.iter().map(|param| ("cookie", param));
+        params.extend(headers.chain(cookies).map(|(location, param)| {
+            json!({
+                "name": param.name,
+                "in": location,
+                "required": param.required,
+                "schema": schema_for(strip_option(param.field.ty)),
+            })
+        }));
+        if !params.is_empty() {
+            op.insert("parameters".into(), params.into());
+        }
+
+        let media_types = if route.consumes.is_empty() {
+            route
+                .body
+                .and_then(|body| media_type_for(body.ty))
+                .into_iter()
+                .collect::<Vec<_>>()
+        } else {
+            route.consumes.to_vec()
+        };
+        if !media_types.is_empty() {
+            let content = media_types
+                .into_iter()
+                .map(|media_type| (media_type.to_string(), json!({ "schema": {} })))
+                .collect::<Map<_, _>>();
+            op.insert(
+                "requestBody".into(),
+                json!({
+                    "required": true,
+                    "content": content,
+                }),
+            );
+        }
+
+        let mut response = json!({ "description": "Default response" });
+        if !route.produces.is_empty() {
+            let content = route
+                .produces
+                .iter()
+                .map(|media_type| (media_type.to_string(), json!({ "schema": {} })))
+                .collect::<Map<_, _>>();
+            response["content"] = content.into();
+        }
+        op.insert("responses".into(), json!({ "default": response }));
+
+        op.into()
+    }
+}
+
+/// An OpenAPI 3 document.
+///
+/// Refer to the [module documentation] for an example.
+///
+/// [module documentation]: index.html
+#[derive(Debug, Clone)]
+pub struct Document {
+    title: String,
+    version: String,
+    description: Option<String>,
+    operations: Vec<Operation>,
+}
+
+impl Document {
+    /// Creates an empty document describing an API with the given title and
+    /// version.
+    pub fn new<T, V>(title: T, version: V) -> Self
+    where
+        T: Into<String>,
+        V: Into<String>,
+    {
+        Self {
+            title: title.into(),
+            version: version.into(),
+            description: None,
+            operations: Vec::new(),
+        }
+    }
+
+    /// Sets the description of the API.
+    pub fn description<D: Into<String>>(mut self, description: D) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Adds operations to the document.
+    ///
+    /// The operations are usually obtained by calling the `openapi_operations`
+    /// function generated by `#[derive(FromRequest)]`. This can be called
+    /// multiple times to document several route types in a single document.
+    pub fn operations<I>(mut self, operations: I) -> Self
+    where
+        I: IntoIterator<Item = Operation>,
+    {
+        self.operations.extend(operations);
+        self
+    }
+
+    /// Converts the document to its JSON representation.
+    pub fn to_json(&self) -> Value {
+        let mut paths = BTreeMap::<String, Map<String, Value>>::new();
+        for op in &self.operations {
+            // Neither can be represented in OpenAPI 3
+            if op.route.path == "*" || !OPENAPI_METHODS.contains(&op.route.method) {
+                continue;
+            }
+
+            let method = op.route.method.to_lowercase();
+            for path in op.paths() {
+                let mut json = op.to_json(&path);
+                let ops = paths.entry(path).or_default();
+                if let Some(prev) = ops.get(&method) {
+                    merge_operations(prev, &mut json);
+                }
+                ops.insert(method.clone(), json);
+            }
+        }
+
+        let mut info = Map::new();
+        info.insert("title".into(), self.title.clone().into());
+        info.insert("version".into(), self.version.clone().into());
+        if let Some(description) = &self.description {
+            info.insert("description".into(), description.clone().into());
+        }
+
+        json!({
+            "openapi": "3.0.3",
+            "info": info,
+            "paths": paths,
+        })
+    }
+}
+
+/// The HTTP methods that can be described by a Path Item Object.
+const OPENAPI_METHODS: &[&str] = &[
+    "GET", "PUT", "POST", "DELETE", "OPTIONS", "HEAD", "PATCH", "TRACE",
+];
+
+/// Guesses a schema from the name of a placeholder type.
+fn schema_for(ty: &str) -> Value {
+    match ty {
+        "u8" | "u16" | "u32" | "u64" | "u128" | "usize" => {
+            json!({ "type": "integer", "minimum": 0 })
+        }
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" => json!({ "type": "integer" }),
+        "f32" | "f64" => json!({ "type": "number" }),
+        "bool" => json!({ "type": "boolean" }),
+        _ => json!({ "type": "string" }),
+    }
+}
+
+/// Returns `T` if `ty` is the name of an `Option<T>`.
+fn strip_option(ty: &str) -> &str {
+    ty.strip_prefix("Option<")
+        .and_then(|inner| inner.strip_suffix('>'))
+        .unwrap_or(ty)
+}
+
+/// Merges the request body and response media types of `prev` into `op`,
+/// which describes the same path and method (eg. because the routes use
+/// different `#[consumes]` or `#[produces]` attributes).
+fn merge_operations(prev: &Value, op: &mut Value) {
+    merge_content(prev, op, "requestBody");
+    merge_content(&prev["responses"], &mut op["responses"], "default");
+}
+
+/// Adds the media types in `prev[key]["content"]` to `op[key]`.
+fn merge_content(prev: &Value, op: &mut Value, key: &str) {
+    let prev_content = match prev[key]["content"].as_object() {
+        Some(content) => content,
+        None => return,
+    };
+
+    match op[key]["content"].as_object_mut() {
+        Some(content) => {
+            for (media_type, value) in prev_content {
+                content
+                    .entry(media_type.clone())
+                    .or_insert_with(|| value.clone());
+            }
+        }
+        None => op[key] = prev[key].clone(),
+    }
+}
+
+/// Returns the media type of a `#[body]` field, based on the name of its type.
+fn media_type_for(ty: &str) -> Option<&'static str> {
+    // Strip generic arguments and the module path (`hyperdrive::body::Json<T>` -> `Json`)
+    let name = ty.split('<').next().unwrap_or(ty);
+    let name = name.rsplit("::").next().unwrap_or(name).trim();
+    match name {
+        "Json" => Some("application/json"),
+        "HtmlForm" => Some("application/x-www-form-urlencoded"),
+        _ => None,
+    }
+}
@@ -0,0 +1,394 @@
+//! Decodes query strings and `x-www-form-urlencoded` data into Rust types.
+//!
+//! This is the decoder used by `#[query_params]` fields and the [`HtmlForm`]
+//! body type. In addition to plain `name=value` pairs, it supports the
+//! following conventions for structured data:
+//!
+//! * Repeated keys are collected into a sequence: `tag=a&tag=b` decodes into
+//!   a `tag: Vec<String>` field. A key that only appears once can still be
+//!   decoded into a sequence with one element.
+//! * An empty pair of brackets appends to a sequence: `tag[]=a&tag[]=b` is
+//!   equivalent to the above.
+//! * A key in brackets accesses a field of a nested struct or map:
+//!   `filter[status]=open&filter[user][id]=7` decodes into a `filter` field
+//!   whose type has a `status` and a `user` field (which has an `id` field).
+//! * Numeric keys in brackets (`ids[0]=7&ids[1]=9`) can be decoded into a
+//!   sequence, in the order of the indices.
+//!
+//! Values are parsed from their string representation when the target type
+//! asks for a number, `bool` or `char`. As with `serde_urlencoded`, `+` is
+//! decoded as a space and all keys and values are percent-decoded. The decoded
+//! data must be valid UTF-8.
+//!
+//! [`HtmlForm`]: ../body/struct.HtmlForm.html
+//!
+//! # Examples
+//!
+//! ```
+//! use hyperdrive::query;
+//! # use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Search {
+//!     tag: Vec<String>,
+//!     filter: Filter,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Filter {
+//!     status: String,
+//!     min_votes: Option<u32>,
+//! }
+//!
+//! let search: Search =
+//!     query::from_str("tag=rust&tag=http&filter[status]=open&filter[min_votes]=10").unwrap();
+//!
+//! assert_eq!(search.tag, ["rust", "http"]);
+//! assert_eq!(search.filter.status, "open");
+//! assert_eq!(search.filter.min_votes, Some(10));
+//! ```
+
+use percent_encoding::percent_decode;
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Maximum number of bracketed keys in a single parameter name.
+///
+/// This prevents stack overflows when decoding maliciously nested data.
+const MAX_DEPTH: usize = 32;
+
+/// Deserializes a `T` from a query string (without the leading `?`).
+pub fn from_str<T: DeserializeOwned>(query: &str) -> Result<T, Error> {
+    from_bytes(query.as_bytes())
+}
+
+/// Deserializes a `T` from `x-www-form-urlencoded` data.
+pub fn from_bytes<T: DeserializeOwned>(input: &[u8]) -> Result<T, Error> {
+    let pairs = parse_pairs(input)?;
+    from_pairs(pairs.iter().map(|(key, value)| (key.as_str(), value.as_str())))
+}
+
+/// Splits `input` into its percent-decoded name-value pairs.
+///
+/// Empty pairs are skipped, and a pair without `=` has an empty value.
+pub(crate) fn parse_pairs(input: &[u8]) -> Result<Vec<(String, String)>, Error> {
+    input
+        .split(|&b| b == b'&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, |&b| b == b'=');
+            let key = decode(parts.next().unwrap_or(&[]))?;
+            let value = decode(parts.next().unwrap_or(&[]))?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Deserializes a `T` from already decoded name-value pairs.
+pub(crate) fn from_pairs<'a, T, I>(pairs: I) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut root = Map::default();
+    for (key, value) in pairs {
+        let (name, path) = split_key(key)?;
+        root.insert(name, &path, value.to_string())?;
+    }
+
+    T::deserialize(Value::Map(root))
+}
+
+fn decode(input: &[u8]) -> Result<String, Error> {
+    let replaced = input
+        .iter()
+        .map(|&b| if b == b'+' { b' ' } else { b })
+        .collect::<Vec<_>>();
+    percent_decode(&replaced)
+        .decode_utf8()
+        .map(|decoded| decoded.into_owned())
+        .map_err(|_| Error::new("query data is not valid UTF-8"))
+}
+
+/// A key following the name of a parameter.
+#[derive(Debug, PartialEq)]
+enum Key<'a> {
+    /// `[name]`
+    Name(&'a str),
+    /// `[]`
+    Push,
+}
+
+/// Splits a parameter name like `filter[user][id]` into the name and the
+/// bracketed keys following it.
+///
+/// Names that don't consist of well-formed brackets are used as-is.
+fn split_key(key: &str) -> Result<(&str, Vec<Key<'_>>), Error> {
+    let start = match key.find('[') {
+        Some(0) | None => return Ok((key, Vec::new())),
+        Some(start) => start,
+    };
+
+    let mut path = Vec::new();
+    let mut rest = &key[start..];
+    while !rest.is_empty() {
+        let end = match (rest.starts_with('['), rest.find(']')) {
+            (true, Some(end)) => end,
+            _ => return Ok((key, Vec::new())),
+        };
+        path.push(match &rest[1..end] {
+            "" => Key::Push,
+            name => Key::Name(name),
+        });
+        rest = &rest[end + 1..];
+    }
+
+    if path.len() > MAX_DEPTH {
+        return Err(Error::new(format!(
+            "query parameter `{}` is nested too deeply",
+            key
+        )));
+    }
+    Ok((&key[..start], path))
+}
+
+/// A decoded value: Either a string, or a collection of values.
+#[derive(Debug)]
+enum Value {
+    Str(String),
+    Seq(Vec<Value>),
+    Map(Map),
+}
+
+/// Named values, in the order they first appeared in.
+#[derive(Debug, Default)]
+struct Map {
+    entries: Vec<(String, Value)>,
+    index: HashMap<String, usize>,
+}
+
+impl Map {
+    /// Stores `value` under `name`, followed by the keys in `path`.
+    fn insert(&mut self, name: &str, path: &[Key<'_>], value: String) -> Result<(), Error> {
+        let conflict = || Error::new(format!("conflicting values for query parameter `{}`", name));
+        let entries = &mut self.entries;
+        let entry = self.index.get(name).map(|&i| &mut entries[i].1);
+
+        let new = match (path.split_first(), entry) {
+            // `name=value` and `name[]=value` both append to any existing values
+            (None, None) | (Some((Key::Push, [])), None) => Value::Str(value),
+            (None, Some(Value::Seq(values))) | (Some((Key::Push, [])), Some(Value::Seq(values))) => {
+                values.push(Value::Str(value));
+                return Ok(());
+            }
+            (None, Some(existing @ Value::Str(_)))
+            | (Some((Key::Push, [])), Some(existing @ Value::Str(_))) => {
+                let first = std::mem::replace(existing, Value::Seq(Vec::new()));
+                *existing = Value::Seq(vec![first, Value::Str(value)]);
+                return Ok(());
+            }
+            (Some((Key::Push, _)), _) => {
+                return Err(Error::new(format!(
+                    "`[]` must be the last key of query parameter `{}`",
+                    name
+                )));
+            }
+            (Some((Key::Name(key), path)), None) => {
+                let mut map = Map::default();
+                map.insert(key, path, value)?;
+                Value::Map(map)
+            }
+            (Some((Key::Name(key), path)), Some(Value::Map(map))) => {
+                return map.insert(key, path, value);
+            }
+            (None, Some(Value::Map(_))) | (Some((Key::Name(_), _)), Some(_)) => {
+                return Err(conflict());
+            }
+        };
+
+        self.index.insert(name.to_string(), self.entries.len());
+        self.entries.push((name.to_string(), new));
+        Ok(())
+    }
+
+    /// Turns a map with numeric keys (eg. from `ids[0]=7&ids[1]=9`) into a
+    /// sequence ordered by the keys, and any other map into a sequence of
+    /// `(name, value)` pairs (with one pair per value of repeated names).
+    fn into_seq(self) -> Vec<Value> {
+        let indices = self
+            .entries
+            .iter()
+            .map(|(name, _)| name.parse::<usize>().ok())
+            .collect::<Option<Vec<_>>>();
+        match indices {
+            Some(indices) => {
+                let mut entries = indices.into_iter().zip(self.entries).collect::<Vec<_>>();
+                entries.sort_by_key(|(index, _)| *index);
+                entries.into_iter().map(|(_, (_, value))| value).collect()
+            }
+            None => self
+                .entries
+                .into_iter()
+                .flat_map(|(name, value)| {
+                    let values = match value {
+                        Value::Seq(values) => values,
+                        value => vec![value],
+                    };
+                    values
+                        .into_iter()
+                        .map(move |value| Value::Seq(vec![Value::Str(name.clone()), value]))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Implements deserialization methods by parsing a string value.
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self {
+                Value::Str(s) => match s.parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(e) => Err(Error::new(format!("invalid value `{}`: {}", s, e))),
+                },
+                value => value.deserialize_any(visitor),
+            }
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::Str(s) => visitor.visit_string(s),
+            Value::Seq(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            Value::Map(map) => visitor.visit_map(MapDeserializer::new(map.entries.into_iter())),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        // Missing values are handled by serde's `Option` support
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let values = match self {
+            Value::Str(s) => vec![Value::Str(s)],
+            Value::Seq(values) => values,
+            Value::Map(map) => map.into_seq(),
+        };
+        visitor.visit_seq(SeqDeserializer::new(values.into_iter()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            Value::Str(s) => visitor.visit_enum(s.into_deserializer()),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf map struct identifier
+    }
+}
+
+/// Error returned when query data can't be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    message: String,
+}
+
+impl Error {
+    fn new<M: Into<String>>(message: M) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self::new(msg.to_string())
+    }
+}
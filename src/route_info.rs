@@ -0,0 +1,227 @@
+//! Static information about the routes of a `#[derive(FromRequest)]` type.
+
+/// Describes a single route of a type implementing [`FromRequest`] via
+/// `#[derive(FromRequest)]`.
+///
+/// A list of all routes of a type is available as the generated associated
+/// constant `ROUTES`. Variants with multiple route attributes create one
+/// `RouteInfo` per attribute. Fallback variants without a route attribute are
+/// not included.
+///
+/// All type names are stored as they are written in the source code, so they
+/// might not be fully qualified.
+///
+/// More fields may be added in the future, so `RouteInfo` (like [`FieldInfo`]
+/// and [`ParamInfo`]) can't be constructed or exhaustively destructured outside of hyperdrive.
+///
+/// # Examples
+///
+/// ```
+/// use hyperdrive::{FromRequest, RouteInfo};
+///
+/// #[derive(FromRequest)]
+/// enum Routes {
+///     /// Shows information about a user.
+///     #[get("/users/{id}")]
+///     User { id: u32 },
+/// }
+///
+/// let route: &RouteInfo = &Routes::ROUTES[0];
+/// assert_eq!(route.method, "GET");
+/// assert_eq!(route.path, "/users/{id}");
+/// assert_eq!(route.variant, "User");
+/// assert_eq!(route.doc, "Shows information about a user.");
+/// assert_eq!(route.placeholders[0].name, "id");
+/// assert_eq!(route.placeholders[0].ty, "u32");
+/// ```
+///
+/// [`FromRequest`]: trait.FromRequest.html
+/// [`FieldInfo`]: struct.FieldInfo.html
+/// [`ParamInfo`]: struct.ParamInfo.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RouteInfo {
+    /// The HTTP method of the route (eg. `"GET"`), or `"*"` for `#[any]`
+    /// routes.
+    pub method: &'static str,
+    /// The path pattern, as written in the route attribute.
+    pub path: &'static str,
+    /// Name of the enum variant or struct carrying the route attribute.
+    pub variant: &'static str,
+    /// Doc comment attached to the variant (or struct), or an empty string if
+    /// there is none.
+    pub doc: &'static str,
+    /// Fields bound to path placeholders, in order of appearance in the path.
+    pub placeholders: &'static [FieldInfo],
+    /// The host pattern specified with `#[host]`, if any.
+    pub host: Option<&'static str>,
+    /// Fields bound to host placeholders, in order of appearance in the host
+    /// pattern.
+    pub host_placeholders: &'static [FieldInfo],
+    /// The rank of the route (0 if not specified with `rank = N`).
+    pub rank: u32,
+    /// Media types accepted in the `Content-Type` of requests, as specified
+    /// with `#[consumes]` (in lowercase). Empty if any request is accepted.
+    pub consumes: &'static [&'static str],
+    /// Media types of the response, as specified with `#[produces]` (in
+    /// lowercase). Empty if the route doesn't take part in content
+    /// negotiation.
+    pub produces: &'static [&'static str],
+    /// The field marked with `#[body]`.
+    pub body: Option<FieldInfo>,
+    /// The field marked with `#[query_params]`.
+    pub query_params: Option<FieldInfo>,
+    /// Fields marked with `#[query]`, in declaration order.
+    pub query: &'static [ParamInfo],
+    /// Fields marked with `#[header]`, in declaration order.
+    pub headers: &'static [ParamInfo],
+    /// Fields marked with `#[cookie]`, in declaration order.
+    pub cookies: &'static [ParamInfo],
+    /// The field marked with `#[forward]`.
+    pub forward: Option<FieldInfo>,
+    /// All [`Guard`]s checked by the route, in the order they're checked.
+    ///
+    /// Guards specified with a `#[guard(Type)]` attribute on the type or
+    /// variant come first and have an empty `name`, followed by all fields
+    /// containing guards.
+    ///
+    /// [`Guard`]: trait.Guard.html
+    pub guards: &'static [FieldInfo],
+}
+
+// `RouteInfo` is `#[non_exhaustive]`, so the code generated by the derive can't
+// use a struct expression. It calls `new` and sets all other fields through
+// these hidden builder methods instead.
+impl RouteInfo {
+    #[doc(hidden)]
+    pub const fn new(method: &'static str, path: &'static str, variant: &'static str) -> Self {
+        Self {
+            method,
+            path,
+            variant,
+            doc: "",
+            placeholders: &[],
+            host: None,
+            host_placeholders: &[],
+            rank: 0,
+            consumes: &[],
+            produces: &[],
+            body: None,
+            query_params: None,
+            query: &[],
+            headers: &[],
+            cookies: &[],
+            forward: None,
+            guards: &[],
+        }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_doc(mut self, doc: &'static str) -> Self {
+        self.doc = doc;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_placeholders(mut self, placeholders: &'static [FieldInfo]) -> Self {
+        self.placeholders = placeholders;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_host(mut self, host: Option<&'static str>) -> Self {
+        self.host = host;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_host_placeholders(mut self, host_placeholders: &'static [FieldInfo]) -> Self {
+        self.host_placeholders = host_placeholders;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_rank(mut self, rank: u32) -> Self {
+        self.rank = rank;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_consumes(mut self, consumes: &'static [&'static str]) -> Self {
+        self.consumes = consumes;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_produces(mut self, produces: &'static [&'static str]) -> Self {
+        self.produces = produces;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_body(mut self, body: Option<FieldInfo>) -> Self {
+        self.body = body;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_query_params(mut self, query_params: Option<FieldInfo>) -> Self {
+        self.query_params = query_params;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_query(mut self, query: &'static [ParamInfo]) -> Self {
+        self.query = query;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_headers(mut self, headers: &'static [ParamInfo]) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_cookies(mut self, cookies: &'static [ParamInfo]) -> Self {
+        self.cookies = cookies;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_forward(mut self, forward: Option<FieldInfo>) -> Self {
+        self.forward = forward;
+        self
+    }
+
+    #[doc(hidden)]
+    pub const fn with_guards(mut self, guards: &'static [FieldInfo]) -> Self {
+        self.guards = guards;
+        self
+    }
+}
+
+/// Name and type of a field used by a route.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FieldInfo {
+    /// Name of the field.
+    pub name: &'static str,
+    /// Type of the field, as written in the source code.
+    pub ty: &'static str,
+}
+
+/// A field bound to a single named value of the request, like a query
+/// parameter, header or cookie.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParamInfo {
+    /// Name of the value, as written in the attribute (eg. `"page"`,
+    /// `"X-Request-Id"` or `"session"`).
+    pub name: &'static str,
+    /// The field the value is decoded into.
+    pub field: FieldInfo,
+    /// Whether requests without the value are rejected. This is `false` if
+    /// the field is an `Option`.
+    pub required: bool,
+}
@@ -230,6 +230,7 @@ where
         // Closures aren't debug-printable, so we print a few Arc stats instead
 
         #[derive(Debug)]
+        #[allow(dead_code)] // only read by the `Debug` impl
         struct HandlerRef {
             strong_count: usize,
             weak_count: usize,
@@ -429,6 +430,7 @@ where
         // Closures aren't debug-printable, so we print a few Arc stats instead
 
         #[derive(Debug)]
+        #[allow(dead_code)] // only read by the `Debug` impl
         struct HandlerRef {
             strong_count: usize,
             weak_count: usize,
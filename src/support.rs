@@ -0,0 +1,533 @@
+//! Runtime support code for the code generated by `#[derive(FromRequest)]`.
+//!
+//! Nothing in here is part of the public API.
+
+use crate::{query, BoxedError, FieldInfo, ParamInfo};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt::{self, Display, Write};
+use std::str::FromStr;
+
+/// Characters that have to be percent-encoded inside a single path segment.
+///
+/// This leaves all `pchar`s defined in RFC 3986 alone, except `%`, which would
+/// otherwise be interpreted as the start of a percent-encoded byte.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// Appends the `Display` representation of `value` to `path`, percent-encoded
+/// so that it forms a single path segment.
+pub fn push_segment<T: Display + ?Sized>(path: &mut String, value: &T) {
+    let value = value.to_string();
+    write!(path, "{}", utf8_percent_encode(&value, SEGMENT)).unwrap();
+}
+
+/// Appends the `Display` representation of `value` to `path`, percent-encoding
+/// every `/`-separated part of it, but keeping the `/`s.
+///
+/// This is used for `{rest...}` placeholders, and is the inverse of
+/// `decode_rest`: The `%25` and `%2F` escapes it leaves in place are kept as
+/// well, and any other `%` is encoded.
+pub fn push_rest<T: Display + ?Sized>(path: &mut String, value: &T) {
+    let value = value.to_string();
+    let mut rest = &value[..];
+    while let Some(pos) = rest.find(['/', '%']) {
+        write!(path, "{}", utf8_percent_encode(&rest[..pos], SEGMENT)).unwrap();
+        rest = &rest[pos..];
+        let escape = rest
+            .get(..3)
+            .filter(|escape| is_kept_escape(escape.as_bytes()));
+        let len = match escape {
+            Some(escape) => {
+                path.push_str(escape);
+                3
+            }
+            None if rest.starts_with('/') => {
+                path.push('/');
+                1
+            }
+            None => {
+                path.push_str("%25");
+                1
+            }
+        };
+        rest = &rest[len..];
+    }
+    write!(path, "{}", utf8_percent_encode(rest, SEGMENT)).unwrap();
+}
+
+/// Percent-decodes a single captured path segment.
+///
+/// Fails if the decoded segment is not valid UTF-8.
+pub fn decode_segment(segment: &str) -> Result<Cow<'_, str>, BoxedError> {
+    Ok(percent_decode_str(segment).decode_utf8()?)
+}
+
+/// Percent-decodes a `{rest...}` capture spanning any number of segments.
+///
+/// Encoded slashes (`%2F`) are kept as-is, so that they can't be confused with
+/// the `/` separating the segments. Encoded percent signs (`%25`) are kept as
+/// well, so that they can't be confused with the start of such an escape, and
+/// a `%` that doesn't start an escape is encoded to `%25`. This makes every `%`
+/// in the result the start of `%25` or `%2F`, which `push_rest` relies on.
+///
+/// Fails if the decoded path is not valid UTF-8.
+pub fn decode_rest(path: &str) -> Result<Cow<'_, str>, BoxedError> {
+    if !path.contains('%') {
+        return Ok(Cow::Borrowed(path));
+    }
+
+    let raw = path.as_bytes();
+    let mut decoded = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        let escape = &raw[i..raw.len().min(i + 3)];
+        let byte = match escape {
+            [b'%', hi, lo] => hex_value(*hi).and_then(|hi| Some(hi * 16 + hex_value(*lo)?)),
+            _ => None,
+        };
+        match byte {
+            Some(_) if is_kept_escape(escape) => decoded.extend_from_slice(escape),
+            Some(byte) => decoded.push(byte),
+            None if raw[i] == b'%' => decoded.extend_from_slice(b"%25"),
+            None => decoded.push(raw[i]),
+        }
+        i += if byte.is_some() { 3 } else { 1 };
+    }
+
+    Ok(Cow::Owned(String::from_utf8(decoded)?))
+}
+
+/// Returns whether `escape` is `%25` or `%2F`, which `decode_rest` doesn't decode.
+fn is_kept_escape(escape: &[u8]) -> bool {
+    escape == b"%25" || escape.eq_ignore_ascii_case(b"%2F")
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+/// Splits the next segment off a request path.
+///
+/// `start` is the byte offset of the segment in `path`. Returns the segment and
+/// the offset of the following one, or `None` if this is the last segment.
+pub fn next_segment(path: &str, start: usize) -> (&str, Option<usize>) {
+    let rest = &path[start..];
+    match rest.find('/') {
+        Some(end) => (&rest[..end], Some(start + end + 1)),
+        None => (rest, None),
+    }
+}
+
+/// Returns the URL to redirect to if `uri`'s path isn't matched by
+/// `is_route`, but is after adding or removing a trailing slash.
+///
+/// The query string is preserved.
+pub fn trailing_slash_redirect<F>(uri: &http::Uri, is_route: F) -> Option<String>
+where
+    F: Fn(&str) -> bool,
+{
+    let path = uri.path();
+    let alternative = if path == "/" || !path.starts_with('/') {
+        return None;
+    } else if let Some(stripped) = path.strip_suffix('/') {
+        stripped.to_string()
+    } else {
+        format!("{}/", path)
+    };
+
+    if !is_route(&alternative) {
+        return None;
+    }
+
+    Some(match uri.query() {
+        Some(query) => format!("{}?{}", alternative, query),
+        None => alternative,
+    })
+}
+
+/// Returns the host a request is addressed to, in lowercase and without port.
+///
+/// The authority of an absolute request URI takes precedence over the `Host`
+/// header. Returns `None` if neither is present (or the header isn't valid).
+pub fn request_host(request: &http::Request<()>) -> Option<String> {
+    let host = match request.uri().host() {
+        Some(host) => host,
+        None => {
+            let header = request.headers().get(http::header::HOST)?.to_str().ok()?;
+            if header.starts_with('[') {
+                // IPv6 address, which contains colons itself
+                header.find(']').map_or(header, |end| &header[..=end])
+            } else {
+                header.split(':').next().unwrap_or(header)
+            }
+        }
+    };
+
+    // A trailing dot denotes the same fully-qualified host
+    let host = host.strip_suffix('.').unwrap_or(host);
+    Some(host.to_ascii_lowercase())
+}
+
+/// Matches a (lowercased) request host against the labels of a `#[host]`
+/// pattern, where `None` stands for a placeholder.
+///
+/// Returns the labels matched by the placeholders, or `None` if the host
+/// doesn't match.
+pub fn match_host<'a>(host: &'a str, pattern: &[Option<&str>]) -> Option<Vec<&'a str>> {
+    if host.split('.').count() != pattern.len() {
+        return None;
+    }
+
+    let mut captures = Vec::new();
+    for (label, expected) in host.split('.').zip(pattern) {
+        match expected {
+            Some(expected) if label != *expected => return None,
+            Some(_) => {}
+            None if label.is_empty() => return None,
+            None => captures.push(label),
+        }
+    }
+    Some(captures)
+}
+
+/// Returns the media type of the request body, as given by the `Content-Type`
+/// header, in lowercase and without parameters.
+pub fn media_type(request: &http::Request<()>) -> Option<String> {
+    let header = request
+        .headers()
+        .get(http::header::CONTENT_TYPE)?
+        .to_str()
+        .ok()?;
+    let essence = header.split(';').next().unwrap_or(header).trim();
+    Some(essence.to_ascii_lowercase())
+}
+
+/// Returns whether the request's media type (see `media_type`) is matched by
+/// any of the media ranges in `accepted`, which may use `*` wildcards.
+pub fn media_type_matches(media_type: Option<&str>, accepted: &[&str]) -> bool {
+    let media_type = match media_type {
+        Some(media_type) => media_type,
+        None => return false,
+    };
+    let (ty, subtype) = match media_type.find('/') {
+        Some(pos) => (&media_type[..pos], &media_type[pos + 1..]),
+        None => return false,
+    };
+
+    accepted.iter().any(|range| match range.find('/') {
+        Some(pos) => {
+            let (range_ty, range_subtype) = (&range[..pos], &range[pos + 1..]);
+            (range_ty == "*" || range_ty == ty) && (range_subtype == "*" || range_subtype == subtype)
+        }
+        None => false,
+    })
+}
+
+/// Returns whether `error` is a `hyperdrive::Error` created by
+/// `Error::forward`, meaning the request should be tried with the next
+/// matching variant.
+pub fn is_forward(error: &BoxedError) -> bool {
+    error
+        .downcast_ref::<crate::Error>()
+        .is_some_and(crate::Error::is_forward)
+}
+
+/// The media ranges listed in a request's `Accept` header, along with their
+/// quality values.
+#[derive(Debug)]
+pub struct Accept {
+    /// Lowercase media ranges and their quality (in thousandths).
+    ranges: Vec<(String, u32)>,
+}
+
+impl Accept {
+    /// Parses the `Accept` header(s) of `request`.
+    ///
+    /// A missing header accepts all media types (like `*/*`). Entries that
+    /// aren't valid media ranges are ignored.
+    pub fn parse(request: &http::Request<()>) -> Self {
+        let headers = request.headers().get_all(http::header::ACCEPT);
+        if headers.iter().next().is_none() {
+            return Self {
+                ranges: vec![("*/*".to_string(), 1000)],
+            };
+        }
+
+        let ranges = headers
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|entry| {
+                let mut params = entry.split(';');
+                let range = params.next()?.trim().to_ascii_lowercase();
+                if range.split('/').count() != 2 {
+                    return None;
+                }
+
+                let mut quality = 1000;
+                for param in params {
+                    let mut parts = param.splitn(2, '=');
+                    let name = parts.next().unwrap_or("").trim();
+                    if name.eq_ignore_ascii_case("q") {
+                        let q = parts.next()?.trim().parse::<f32>().ok()?;
+                        quality = (q.clamp(0.0, 1.0) * 1000.0).round() as u32;
+                    }
+                }
+                Some((range, quality))
+            })
+            .collect();
+        Self { ranges }
+    }
+
+    /// Returns the quality (in thousandths) with which the best of
+    /// `media_types` is accepted, or `None` if none of them is acceptable.
+    ///
+    /// The quality of each media type is taken from the most specific matching
+    /// media range (`type/subtype` over `type/*` over `*/*`).
+    pub fn quality(&self, media_types: &[&str]) -> Option<u32> {
+        media_types
+            .iter()
+            .filter_map(|media_type| {
+                let ty = media_type.split('/').next().unwrap_or(media_type);
+                self.ranges
+                    .iter()
+                    .filter_map(|(range, quality)| {
+                        let specificity = if range == media_type {
+                            2
+                        } else if range.strip_suffix("/*") == Some(ty) {
+                            1
+                        } else if range == "*/*" {
+                            0
+                        } else {
+                            return None;
+                        };
+                        Some((specificity, *quality))
+                    })
+                    .max_by_key(|(specificity, _)| *specificity)
+                    .map(|(_, quality)| quality)
+            })
+            .max()
+            .filter(|quality| *quality > 0)
+    }
+}
+
+/// Parses the value of the request header `name` using `T`'s `FromStr` impl.
+///
+/// Returns `Ok(None)` if the header is missing. If it's present multiple
+/// times, the first value is used.
+pub fn parse_header<T>(request: &http::Request<()>, name: &'static str) -> Result<Option<T>, ParamError>
+where
+    T: FromStr,
+    T::Err: Error + Send + Sync + 'static,
+{
+    let value = match request.headers().get(name) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    value
+        .to_str()
+        .map_err(BoxedError::from)
+        .and_then(|value| value.parse().map_err(BoxedError::from))
+        .map(Some)
+        .map_err(|e| ParamError::Invalid("header", name, e))
+}
+
+/// Parses the value of the cookie `name` using `T`'s `FromStr` impl.
+///
+/// All `Cookie` headers of the request are searched, and the first cookie
+/// with that name is used. Its value is unquoted and percent-decoded before
+/// parsing it. Returns `Ok(None)` if there's no such cookie.
+pub fn parse_cookie<T>(request: &http::Request<()>, name: &'static str) -> Result<Option<T>, ParamError>
+where
+    T: FromStr,
+    T::Err: Error + Send + Sync + 'static,
+{
+    let value = request
+        .headers()
+        .get_all(http::header::COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(';'))
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let cookie = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            if cookie == name {
+                Some(value)
+            } else {
+                None
+            }
+        })
+        .next();
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let unquoted = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value);
+    percent_decode_str(unquoted)
+        .decode_utf8()
+        .map_err(BoxedError::from)
+        .and_then(|value| value.parse().map_err(BoxedError::from))
+        .map(Some)
+        .map_err(|e| ParamError::Invalid("cookie", name, e))
+}
+
+/// The decoded name-value pairs of a query string, used by `#[query]` fields.
+#[derive(Debug)]
+pub struct QueryPairs {
+    pairs: Vec<(String, String)>,
+}
+
+impl QueryPairs {
+    /// Decodes the query string `query` (without the leading `?`).
+    pub fn parse(query: &str) -> Result<Self, BoxedError> {
+        Ok(Self {
+            pairs: query::parse_pairs(query.as_bytes())?,
+        })
+    }
+
+    /// Parses the value of the query parameter `name` using `T`'s `FromStr`
+    /// impl.
+    ///
+    /// Returns `Ok(None)` if the parameter is missing. If it's present
+    /// multiple times, the first value is used.
+    pub fn parse_param<T>(&self, name: &'static str) -> Result<Option<T>, ParamError>
+    where
+        T: FromStr,
+        T::Err: Error + Send + Sync + 'static,
+    {
+        let value = match self.pairs.iter().find(|(param, _)| param == name) {
+            Some((_, value)) => value,
+            None => return Ok(None),
+        };
+
+        value
+            .parse()
+            .map(Some)
+            .map_err(|e| ParamError::Invalid("query parameter", name, BoxedError::from(e)))
+    }
+
+    /// Deserializes a `#[query_params]` struct from all parameters except
+    /// those in `names`.
+    ///
+    /// This is used to keep parameters bound to `#[query]` fields from being
+    /// passed to the struct.
+    pub fn deserialize_without<T: DeserializeOwned>(&self, names: &[&str]) -> Result<T, query::Error> {
+        query::from_pairs(
+            self.pairs
+                .iter()
+                .filter(|(param, _)| !names.contains(&param.as_str()))
+                .map(|(param, value)| (param.as_str(), value.as_str())),
+        )
+    }
+}
+
+/// Error produced when a `#[query]`, `#[header]` or `#[cookie]` field can't be
+/// decoded.
+///
+/// The first string is the kind of value (eg. `"header"`), the second one its
+/// name.
+#[derive(Debug)]
+pub enum ParamError {
+    /// The value is required, but missing from the request.
+    Missing(&'static str, &'static str),
+    /// The value isn't valid UTF-8 or couldn't be parsed.
+    Invalid(&'static str, &'static str, BoxedError),
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamError::Missing(kind, name) => write!(f, "missing {} `{}`", kind, name),
+            ParamError::Invalid(kind, name, e) => write!(f, "invalid {} `{}`: {}", kind, name, e),
+        }
+    }
+}
+
+impl Error for ParamError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParamError::Missing(..) => None,
+            ParamError::Invalid(_, _, e) => Some(&**e),
+        }
+    }
+}
+
+/// Creates a `FieldInfo` for the `ROUTES` table.
+pub const fn field_info(name: &'static str, ty: &'static str) -> FieldInfo {
+    FieldInfo { name, ty }
+}
+
+/// Creates a `ParamInfo` for the `ROUTES` table.
+pub const fn param_info(name: &'static str, field: FieldInfo, required: bool) -> ParamInfo {
+    ParamInfo {
+        name,
+        field,
+        required,
+    }
+}
+
+/// Returns the field names of a struct deserialized from query parameters.
+///
+/// This runs `T`'s `Deserialize` impl against a deserializer that records the
+/// fields passed to `deserialize_struct` and then bails out. Types that don't
+/// deserialize from a struct yield an empty list.
+pub fn query_param_names<T: DeserializeOwned>() -> Vec<&'static str> {
+    let fields = Cell::new(&[][..]);
+    // This always fails, we're only interested in the recorded fields
+    let _ = T::deserialize(FieldRecorder { fields: &fields });
+    fields.get().to_vec()
+}
+
+struct FieldRecorder<'a> {
+    fields: &'a Cell<&'static [&'static str]>,
+}
+
+impl<'de, 'a> Deserializer<'de> for FieldRecorder<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.fields.set(fields);
+        Err(de::Error::custom("recorded struct fields"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
@@ -1,6 +1,8 @@
 //! This example shows how to return a `500 Internal Server Error` response when
 //! any part of the request panics.
 
+#![allow(clippy::enum_variant_names)]
+
 use futures::Future;
 use http::{Response, StatusCode};
 use hyper::{Body, Server};
@@ -68,7 +70,7 @@ fn main() {
             Ok(Response::builder()
                 .status(StatusCode::INTERNAL_SERVER_ERROR)
                 .header("Content-Type", "text/html")
-                .body(Body::from(format!(
+                .body(Body::from(
                     r#"
                     <!DOCTYPE html>
                     <html>
@@ -80,8 +82,8 @@ fn main() {
                         </p>
                     </body>
                     </html>
-                "#
-                )))
+                "#,
+                ))
                 .expect("couldn't build response"))
         })
         .make_service_by_cloning(),
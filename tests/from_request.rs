@@ -140,6 +140,7 @@ fn user_app() {
 fn context() {
     #[derive(FromRequest, Debug)]
     #[context(SpecialContext)]
+    #[allow(dead_code)]
     enum Routes {
         #[get("/")]
         Variant {
@@ -186,6 +187,7 @@ fn struct_context() {
     #[derive(FromRequest, Debug)]
     #[context(SpecialContext)]
     #[get("/")]
+    #[allow(dead_code)]
     struct Route {
         /// Takes a `SpecialContext`.
         special: SpecialGuard,
@@ -383,6 +385,61 @@ fn query_params() {
     );
 }
 
+#[test]
+fn nested_query_params() {
+    #[derive(FromRequest, PartialEq, Eq, Debug)]
+    enum Routes {
+        #[get("/issues")]
+        Issues {
+            #[query_params]
+            search: Search,
+        },
+    }
+
+    #[derive(Deserialize, PartialEq, Eq, Debug)]
+    struct Search {
+        #[serde(default)]
+        label: Vec<String>,
+        filter: Filter,
+    }
+
+    #[derive(Deserialize, PartialEq, Eq, Debug)]
+    struct Filter {
+        status: String,
+        assignee: Option<String>,
+    }
+
+    let route = invoke::<Routes>(
+        Request::get("/issues?label=bug&filter[status]=open&label=ui")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::Issues {
+            search: Search {
+                label: vec!["bug".to_string(), "ui".to_string()],
+                filter: Filter {
+                    status: "open".to_string(),
+                    assignee: None,
+                },
+            }
+        }
+    );
+
+    let err: Box<Error> = invoke::<Routes>(
+        Request::get("/issues?label=bug")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert_eq!(err.source().unwrap().to_string(), "missing field `filter`");
+}
+
 /// Tests that the derive works on generic enums and structs.
 #[test]
 fn generic() {
@@ -611,6 +668,7 @@ fn forward_allowed_methods() {
     }
 
     #[derive(PartialEq, Eq, Debug)]
+    #[allow(dead_code)]
     struct AlwaysErr;
 
     impl FromStr for AlwaysErr {
@@ -821,3 +879,2013 @@ fn klepto_arc() {
     assert_eq!(route.guard.request.uri(), "/");
     assert_eq!(route.guard.request.method(), "GET");
 }
+
+#[test]
+fn reverse_routing() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/")]
+        Index,
+
+        #[get("/users/{id}")]
+        #[get("/u/{id}")]
+        User { id: u32 },
+
+        #[get("/users/{name}/files/{path...}")]
+        File { name: String, path: String },
+
+        #[post("/users/{id}/avatar")]
+        Avatar { id: u32, guard: MyGuard },
+
+        #[options("*")]
+        Options,
+
+        Fallback {
+            #[forward]
+            inner: Inner,
+        },
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[get("/inner/{id}")]
+    struct Inner {
+        id: u8,
+    }
+
+    assert_eq!(Routes::Index.to_path().as_deref(), Some("/"));
+    assert_eq!(
+        Routes::User { id: 5 }.to_path().as_deref(),
+        Some("/users/5")
+    );
+    assert_eq!(Routes::user_path(&5), "/users/5");
+    assert_eq!(
+        Routes::File {
+            name: "J\u{f6}rg".to_string(),
+            path: "a b/c?.txt".to_string(),
+        }
+        .to_path()
+        .as_deref(),
+        Some("/users/J%C3%B6rg/files/a%20b/c%3F.txt")
+    );
+    assert_eq!(Routes::avatar_path(&7), "/users/7/avatar");
+    assert_eq!(Routes::Options.to_path().as_deref(), Some("*"));
+    assert_eq!(Inner { id: 3 }.to_path().as_deref(), Some("/inner/3"));
+    assert_eq!(Inner::inner_path(&3), "/inner/3");
+
+    let route = invoke::<Routes>(
+        Request::get(Routes::User { id: 42 }.to_path().unwrap())
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(route, Routes::User { id: 42 });
+
+    let file = Routes::File {
+        name: "a/b".to_string(),
+        path: "c d/e%25f".to_string(),
+    };
+    let route = invoke::<Routes>(
+        Request::get(file.to_path().unwrap())
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(route, file);
+}
+
+#[test]
+fn reverse_routing_fallback() {
+    #[derive(FromRequest, Debug)]
+    #[allow(dead_code)]
+    enum Routes {
+        #[get("/users/{id}")]
+        User { id: u32 },
+
+        Fallback {
+            #[forward]
+            inner: Handwritten,
+        },
+
+        NotRouted,
+    }
+
+    /// A `FromRequest` type without reverse routing functions.
+    #[derive(Debug)]
+    struct Handwritten;
+
+    impl FromRequest for Handwritten {
+        type Context = NoContext;
+        type Future = hyperdrive::DefaultFuture<Self, BoxedError>;
+
+        fn from_request_and_body(
+            _request: &Arc<Request<()>>,
+            _body: Body,
+            _context: Self::Context,
+        ) -> Self::Future {
+            Box::new(futures::future::ok(Handwritten))
+        }
+    }
+
+    assert_eq!(
+        Routes::User { id: 5 }.to_path().as_deref(),
+        Some("/users/5")
+    );
+    assert_eq!(Routes::Fallback { inner: Handwritten }.to_path(), None);
+    assert_eq!(Routes::NotRouted.to_path(), None);
+}
+
+#[test]
+fn reverse_routing_unrepresentable() {
+    #[derive(FromRequest, Debug)]
+    enum Routes {
+        #[get("/a/{a}")]
+        #[get("/b/{b}")]
+        Either { a: Option<u32>, b: Option<u32> },
+    }
+
+    assert_eq!(
+        Routes::Either {
+            a: None,
+            b: Some(1)
+        }
+        .to_path()
+        .as_deref(),
+        Some("/b/1")
+    );
+    assert_eq!(Routes::Either { a: None, b: None }.to_path(), None);
+}
+
+#[test]
+fn percent_decoding() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/users/{name}")]
+        User { name: String },
+
+        #[get("/raw/{name}")]
+        Raw {
+            #[raw]
+            name: String,
+        },
+
+        #[get("/files/{path...}")]
+        File { path: String },
+    }
+
+    let route = invoke::<Routes>(
+        Request::get("/users/J%C3%B6rg")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::User {
+            name: "J\u{f6}rg".to_string()
+        }
+    );
+
+    let route = invoke::<Routes>(Request::get("/raw/J%C3%B6rg").body(Body::empty()).unwrap())
+        .unwrap();
+    assert_eq!(
+        route,
+        Routes::Raw {
+            name: "J%C3%B6rg".to_string()
+        }
+    );
+
+    // `%2F` must not turn into a path separator
+    let route = invoke::<Routes>(
+        Request::get("/files/a%20b/c%2Fd%2fe")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::File {
+            path: "a b/c%2Fd%2fe".to_string()
+        }
+    );
+
+    // `%25` is kept as well, so that `%252F` doesn't look like an encoded slash
+    let route = invoke::<Routes>(
+        Request::get("/files/a%252Fb/%25%32F/100%25")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::File {
+            path: "a%252Fb/%252F/100%25".to_string()
+        }
+    );
+
+    // Reverse routing keeps the escapes, so decoded values round-trip
+    for path in &["%25", "100%25", "a%2Fb/c", "a%2fb", "%252F", "%25%2F/%2525"] {
+        let file = Routes::File {
+            path: path.to_string(),
+        };
+        let request = Request::get(file.to_path().unwrap())
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(invoke::<Routes>(request).unwrap(), file, "{}", path);
+    }
+
+    // Any other `%` is encoded, and decoded to `%25`
+    let file = Routes::File {
+        path: "100%".to_string(),
+    };
+    assert_eq!(file.to_path().as_deref(), Some("/files/100%25"));
+
+    // Invalid UTF-8
+    let err: Box<Error> = invoke::<Routes>(Request::get("/users/%FF").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn route_table() {
+    use hyperdrive::{FieldInfo, ParamInfo, RouteInfo};
+
+    #[derive(Deserialize)]
+    struct Login {}
+
+    #[derive(Deserialize)]
+    struct Pagination {}
+
+    #[derive(FromRequest)]
+    #[allow(dead_code)]
+    enum Inner {
+        #[get("/inner")]
+        Index,
+    }
+
+    #[derive(FromRequest)]
+    #[allow(dead_code)]
+    enum Routes {
+        /// Lists all users.
+        ///
+        /// Supports pagination.
+        #[get("/users")]
+        Users {
+            #[query_params]
+            page: Pagination,
+        },
+
+        #[post("/login")]
+        Login {
+            #[body]
+            data: Json<Login>,
+            #[header("X-Csrf-Token")]
+            csrf: Option<String>,
+            guard: MyGuard,
+        },
+
+        #[get("/users/{name}/files/{path...}")]
+        #[head("/users/{name}/files/{path...}")]
+        File {
+            path: String,
+            name: String,
+        },
+
+        /// Not part of the route table.
+        Fallback {
+            #[forward]
+            inner: Inner,
+        },
+    }
+
+    // `RouteInfo` is `#[non_exhaustive]`, so compare it field by field
+    let field = |info: &FieldInfo| (info.name, info.ty);
+    let param = |info: &ParamInfo| (info.name, field(&info.field), info.required);
+    let routes: &[RouteInfo] = Routes::ROUTES;
+    assert_eq!(routes.len(), 4);
+
+    let users = &routes[0];
+    assert_eq!(users.method, "GET");
+    assert_eq!(users.path, "/users");
+    assert_eq!(users.variant, "Users");
+    assert_eq!(users.doc, "Lists all users.\n\nSupports pagination.");
+    assert!(users.placeholders.is_empty());
+    assert_eq!(users.host, None);
+    assert!(users.host_placeholders.is_empty());
+    assert_eq!(users.rank, 0);
+    assert!(users.consumes.is_empty());
+    assert!(users.produces.is_empty());
+    assert_eq!(users.body, None);
+    assert_eq!(
+        users.query_params.as_ref().map(field),
+        Some(("page", "Pagination"))
+    );
+    assert!(users.query.is_empty());
+    assert!(users.headers.is_empty());
+    assert!(users.cookies.is_empty());
+    assert_eq!(users.forward, None);
+    assert!(users.guards.is_empty());
+
+    let login = &routes[1];
+    assert_eq!(login.method, "POST");
+    assert_eq!(login.path, "/login");
+    assert_eq!(login.variant, "Login");
+    assert_eq!(login.doc, "");
+    assert_eq!(
+        login.body.as_ref().map(field),
+        Some(("data", "Json<Login>"))
+    );
+    assert_eq!(login.query_params, None);
+    assert_eq!(
+        login.headers.iter().map(param).collect::<Vec<_>>(),
+        [("X-Csrf-Token", ("csrf", "Option<String>"), false)]
+    );
+    assert_eq!(
+        login.guards.iter().map(field).collect::<Vec<_>>(),
+        [("guard", "MyGuard")]
+    );
+
+    for (file, method) in routes[2..].iter().zip(&["GET", "HEAD"]) {
+        assert_eq!(file.method, *method);
+        assert_eq!(file.path, "/users/{name}/files/{path...}");
+        assert_eq!(file.variant, "File");
+        // Ordered like the placeholders in the path, not like the fields
+        assert_eq!(
+            file.placeholders.iter().map(field).collect::<Vec<_>>(),
+            [("name", "String"), ("path", "String")]
+        );
+        assert_eq!(file.body, None);
+        assert!(file.guards.is_empty());
+    }
+
+    // Generic structs have a route table too
+    #[derive(FromRequest)]
+    #[get("/{id}")]
+    #[allow(dead_code)]
+    struct Generic<T: FromStr>
+    where
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        id: T,
+    }
+
+    assert_eq!(Generic::<u8>::ROUTES[0].placeholders[0].ty, "T");
+}
+
+#[test]
+fn extension_methods() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/files/{path...}")]
+        Get { path: String },
+
+        #[route(PROPFIND, "/files/{path...}")]
+        PropFind { path: String },
+
+        #[route("VERSION-CONTROL", "/files/{path...}")]
+        VersionControl { path: String },
+
+        #[route(MKCOL, "/collections/{name}")]
+        MkCol { name: String },
+
+        // Same as `#[post("/")]`
+        #[route(POST, "/")]
+        Post,
+    }
+
+    let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+    let route = invoke::<Routes>(
+        Request::builder()
+            .method(propfind.clone())
+            .uri("/files/a/b")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::PropFind {
+            path: "a/b".to_string()
+        }
+    );
+
+    let route = invoke::<Routes>(
+        Request::builder()
+            .method("VERSION-CONTROL")
+            .uri("/files/a")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::VersionControl {
+            path: "a".to_string()
+        }
+    );
+
+    let route = invoke::<Routes>(
+        Request::builder()
+            .method("MKCOL")
+            .uri("/collections/new")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::MkCol {
+            name: "new".to_string()
+        }
+    );
+
+    let route = invoke::<Routes>(Request::post("/").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::Post);
+
+    // Methods are case-sensitive
+    let err: Box<Error> = invoke::<Routes>(
+        Request::builder()
+            .method("propfind")
+            .uri("/files/a")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods(),
+        Some(
+            &[
+                &Method::GET,
+                &propfind,
+                &Method::from_bytes(b"VERSION-CONTROL").unwrap(),
+                &Method::HEAD,
+            ][..]
+        )
+    );
+
+    let err: Box<Error> = invoke::<Routes>(Request::get("/collections/new").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods(),
+        Some(&[&Method::from_bytes(b"MKCOL").unwrap()][..])
+    );
+
+    assert_eq!(Routes::ROUTES[1].method, "PROPFIND");
+}
+
+#[test]
+fn placeholder_constraints() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/users/me")]
+        Me,
+
+        #[get("/users/{id:int}")]
+        User { id: i32 },
+
+        #[get("/users/{name:[A-Z][a-z]*}")]
+        UserByName { name: String },
+
+        #[get("/files/{name:[^/]+\\.txt}")]
+        TextFile { name: String },
+    }
+
+    let get = |path| invoke::<Routes>(Request::get(path).body(Body::empty()).unwrap());
+
+    assert_eq!(get("/users/me").unwrap(), Routes::Me);
+    assert_eq!(get("/users/-12").unwrap(), Routes::User { id: -12 });
+    assert_eq!(
+        get("/users/Bob").unwrap(),
+        Routes::UserByName {
+            name: "Bob".to_string()
+        }
+    );
+    assert_eq!(
+        get("/files/notes.txt").unwrap(),
+        Routes::TextFile {
+            name: "notes.txt".to_string()
+        }
+    );
+
+    // Nothing matches
+    for path in &["/users/bob", "/users/1a", "/files/notes.md", "/files/a/b.txt"] {
+        let err: Box<Error> = get(path).unwrap_err().downcast().unwrap();
+        assert_eq!(err.http_status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+
+    assert_eq!(Routes::user_path(&5), "/users/5");
+    assert_eq!(Routes::ROUTES[1].path, "/users/{id:int}");
+}
+
+#[test]
+fn mixed_segments() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/files/{name}.{ext}")]
+        File { name: String, ext: String },
+
+        #[get("/files/README")]
+        Readme,
+
+        #[get("/report-{year}.csv")]
+        Report { year: u16 },
+
+        #[get("/v{major:uint}/{path...}")]
+        Versioned { major: u32, path: String },
+    }
+
+    let get = |path| invoke::<Routes>(Request::get(path).body(Body::empty()).unwrap());
+
+    assert_eq!(
+        get("/files/archive.tar.gz").unwrap(),
+        Routes::File {
+            name: "archive.tar".to_string(),
+            ext: "gz".to_string(),
+        }
+    );
+    assert_eq!(get("/files/README").unwrap(), Routes::Readme);
+    assert_eq!(get("/report-2019.csv").unwrap(), Routes::Report { year: 2019 });
+    assert_eq!(
+        get("/v2/assets/app.js").unwrap(),
+        Routes::Versioned {
+            major: 2,
+            path: "assets/app.js".to_string(),
+        }
+    );
+
+    for path in &["/files/.txt", "/report-.csv", "/report-2019.pdf", "/vx/app.js"] {
+        let err: Box<Error> = get(path).unwrap_err().downcast().unwrap();
+        assert_eq!(err.http_status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+
+    // `FromStr` failure
+    let err: Box<Error> = get("/report-99999.csv").unwrap_err().downcast().unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+
+    let file = Routes::File {
+        name: "a b".to_string(),
+        ext: "txt".to_string(),
+    };
+    assert_eq!(file.to_path().as_deref(), Some("/files/a%20b.txt"));
+    assert_eq!(get(&file.to_path().unwrap()).unwrap(), file);
+    assert_eq!(Routes::report_path(&2019), "/report-2019.csv");
+}
+
+#[test]
+fn prefix() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[prefix("/api/v2")]
+    enum Routes {
+        #[get("/")]
+        Index,
+
+        #[get("/users/{id}")]
+        User { id: u32 },
+
+        #[post("/users/{id}")]
+        UpdateUser { id: u32 },
+
+        #[options("*")]
+        Options,
+    }
+
+    let get = |path| invoke::<Routes>(Request::get(path).body(Body::empty()).unwrap());
+
+    assert_eq!(get("/api/v2/").unwrap(), Routes::Index);
+    assert_eq!(get("/api/v2/users/1").unwrap(), Routes::User { id: 1 });
+
+    let err: Box<Error> = get("/users/1").unwrap_err().downcast().unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+
+    let err: Box<Error> = invoke::<Routes>(Request::put("/api/v2/users/1").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods(),
+        Some(&[&Method::GET, &Method::POST, &Method::HEAD][..])
+    );
+
+    let route = invoke::<Routes>(Request::options("*").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Routes::Options);
+
+    assert_eq!(Routes::user_path(&1), "/api/v2/users/1");
+    assert_eq!(Routes::ROUTES[0].path, "/api/v2/");
+
+    // Prefixes work on structs as well
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[prefix("/api")]
+    #[get("/status")]
+    struct Status;
+
+    let route = invoke::<Status>(Request::get("/api/status").body(Body::empty()).unwrap()).unwrap();
+    assert_eq!(route, Status);
+}
+
+#[test]
+fn hosts() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[host("{tenant}.example.com")]
+    enum Routes {
+        #[get("/users")]
+        Users { tenant: String },
+
+        #[get("/users/{id}")]
+        User { tenant: String, id: u32 },
+
+        #[get("/users")]
+        #[host("admin.example.com")]
+        AdminUsers,
+
+        #[post("/users")]
+        #[host("admin.example.com")]
+        CreateUser,
+
+        #[get("/status")]
+        #[host("{node}.{region}.internal")]
+        Status { node: String, region: String },
+    }
+
+    let get = |host: &str, path: &str| {
+        invoke::<Routes>(
+            Request::get(path)
+                .header("Host", host)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+
+    assert_eq!(
+        get("acme.example.com", "/users").unwrap(),
+        Routes::Users {
+            tenant: "acme".to_string()
+        }
+    );
+    // Host names are case-insensitive, and the port is ignored
+    assert_eq!(
+        get("ACME.Example.com:8080", "/users/1").unwrap(),
+        Routes::User {
+            tenant: "acme".to_string(),
+            id: 1
+        }
+    );
+    // Literal hosts take precedence over placeholders
+    assert_eq!(get("admin.example.com", "/users").unwrap(), Routes::AdminUsers);
+    assert_eq!(
+        get("n1.eu.internal", "/status").unwrap(),
+        Routes::Status {
+            node: "n1".to_string(),
+            region: "eu".to_string()
+        }
+    );
+
+    // The authority of an absolute URI is used before the `Host` header
+    let route = invoke::<Routes>(
+        Request::get("http://acme.example.com/users")
+            .header("Host", "admin.example.com")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        Routes::Users {
+            tenant: "acme".to_string()
+        }
+    );
+
+    for (host, path) in &[
+        ("example.com", "/users"),
+        ("a.b.example.com", "/users"),
+        ("acme.example.com", "/status"),
+    ] {
+        let err: Box<Error> = get(host, path).unwrap_err().downcast().unwrap();
+        assert_eq!(err.http_status(), StatusCode::NOT_FOUND, "{}{}", host, path);
+    }
+    let err: Box<Error> = invoke::<Routes>(Request::get("/users").body(Body::empty()).unwrap())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+
+    // Allowed methods only include routes available on the requested host
+    let post = |host: &str| {
+        invoke::<Routes>(
+            Request::post("/users")
+                .header("Host", host)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    assert_eq!(post("admin.example.com").unwrap(), Routes::CreateUser);
+    let err: Box<Error> = invoke::<Routes>(
+        Request::delete("/users")
+            .header("Host", "acme.example.com")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        err.allowed_methods(),
+        Some(&[&Method::GET, &Method::HEAD][..])
+    );
+
+    assert_eq!(Routes::ROUTES[0].host, Some("{tenant}.example.com"));
+    assert_eq!(Routes::ROUTES[0].host_placeholders[0].name, "tenant");
+    assert!(Routes::ROUTES[1].placeholders.iter().all(|p| p.name == "id"));
+
+    // Routes without `#[host]` match any host
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Mixed {
+        #[get("/")]
+        #[host("api.example.com")]
+        Api,
+
+        #[get("/")]
+        Index,
+    }
+
+    let route = invoke::<Mixed>(
+        Request::get("/")
+            .header("Host", "api.example.com")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(route, Mixed::Api);
+    let route = invoke::<Mixed>(
+        Request::get("/")
+            .header("Host", "www.example.com")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(route, Mixed::Index);
+
+    // Requests to other hosts are passed to the fallback variant
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum WithFallback {
+        #[get("/")]
+        #[host("api.example.com")]
+        Api,
+
+        Fallback {
+            #[forward]
+            inner: Mixed,
+        },
+    }
+
+    let route = invoke::<WithFallback>(
+        Request::get("/")
+            .header("Host", "www.example.com")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        route,
+        WithFallback::Fallback {
+            inner: Mixed::Index
+        }
+    );
+}
+
+#[test]
+fn trailing_slash() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[trailing_slash(redirect)]
+    enum Redirect {
+        #[get("/")]
+        Index,
+
+        #[get("/users")]
+        Users,
+
+        #[get("/users/{id}/")]
+        User { id: u32 },
+    }
+
+    let get = |path| invoke::<Redirect>(Request::get(path).body(Body::empty()).unwrap());
+
+    assert_eq!(get("/users").unwrap(), Redirect::Users);
+    assert_eq!(get("/users/1/").unwrap(), Redirect::User { id: 1 });
+
+    let redirect = |path| {
+        let err: Box<Error> = get(path).unwrap_err().downcast().unwrap();
+        assert_eq!(err.http_status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            err.response().headers()[hyperdrive::http::header::LOCATION],
+            err.location().unwrap()
+        );
+        err.location().unwrap().to_string()
+    };
+    assert_eq!(redirect("/users/"), "/users");
+    assert_eq!(redirect("/users/1"), "/users/1/");
+    assert_eq!(redirect("/users/1?page=2"), "/users/1/?page=2");
+
+    let err: Box<Error> = get("/posts/").unwrap_err().downcast().unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+    assert_eq!(err.location(), None);
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[trailing_slash(match_both)]
+    #[prefix("/api")]
+    enum MatchBoth {
+        #[get("/")]
+        Index,
+
+        #[get("/users")]
+        Users,
+
+        #[post("/users/")]
+        CreateUser,
+
+        #[get("/users/{id}/")]
+        User { id: u32 },
+    }
+
+    let get = |path| invoke::<MatchBoth>(Request::get(path).body(Body::empty()).unwrap());
+
+    assert_eq!(get("/api").unwrap(), MatchBoth::Index);
+    assert_eq!(get("/api/").unwrap(), MatchBoth::Index);
+    assert_eq!(get("/api/users").unwrap(), MatchBoth::Users);
+    assert_eq!(get("/api/users/").unwrap(), MatchBoth::Users);
+    assert_eq!(get("/api/users/1").unwrap(), MatchBoth::User { id: 1 });
+    assert_eq!(get("/api/users/1/").unwrap(), MatchBoth::User { id: 1 });
+
+    let route = invoke::<MatchBoth>(Request::post("/api/users").body(Body::empty()).unwrap());
+    assert_eq!(route.unwrap(), MatchBoth::CreateUser);
+
+    // Reverse routing still uses the declared path
+    assert_eq!(MatchBoth::user_path(&1), "/api/users/1/");
+}
+
+#[test]
+fn ranks() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/users/{id}", rank = 1)]
+        #[delete("/users/{id}", rank = 1)]
+        User { id: String },
+
+        #[get("/users/new")]
+        NewUser,
+
+        #[get("/posts/{id}")]
+        PostById { id: u32 },
+
+        #[get("/posts/{slug}", rank = 1)]
+        PostBySlug { slug: String },
+
+        #[route(PROPFIND, "/files/{path...}", rank = 2)]
+        Files { path: String },
+
+        #[route(PROPFIND, "/files/special")]
+        Special,
+    }
+
+    let request = |method: Method, path: &str| {
+        invoke::<Routes>(
+            Request::builder()
+                .method(method)
+                .uri(path)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+
+    // Lower ranks are tried first
+    assert_eq!(request(Method::GET, "/users/new").unwrap(), Routes::NewUser);
+    assert_eq!(
+        request(Method::GET, "/users/1").unwrap(),
+        Routes::User { id: "1".to_string() }
+    );
+    // If the lower-ranked route doesn't accept the method, the next one is used
+    assert_eq!(
+        request(Method::DELETE, "/users/new").unwrap(),
+        Routes::User {
+            id: "new".to_string()
+        }
+    );
+
+    // If a placeholder can't be parsed, the next route is tried
+    assert_eq!(request(Method::GET, "/posts/1").unwrap(), Routes::PostById { id: 1 });
+    assert_eq!(
+        request(Method::GET, "/posts/hello").unwrap(),
+        Routes::PostBySlug {
+            slug: "hello".to_string()
+        }
+    );
+
+    let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+    assert_eq!(request(propfind.clone(), "/files/special").unwrap(), Routes::Special);
+    assert_eq!(
+        request(propfind, "/files/a/b").unwrap(),
+        Routes::Files {
+            path: "a/b".to_string()
+        }
+    );
+
+    let err: Box<Error> = request(Method::POST, "/posts/1")
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    assert_eq!(Routes::ROUTES[0].rank, 1);
+    assert_eq!(Routes::ROUTES[2].rank, 0);
+}
+
+#[test]
+fn segment_trie() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/a/{x}/c")]
+        Any { x: String },
+
+        #[get("/a/b/d")]
+        Literal,
+
+        #[get("/a/{x:int}/e")]
+        Constrained { x: i32 },
+
+        #[get("/a/{name}.{ext}")]
+        Mixed { name: String, ext: String },
+
+        #[get("/a/b")]
+        Short,
+    }
+
+    let request = |path: &str| {
+        invoke::<Routes>(
+            Request::builder()
+                .method(Method::GET)
+                .uri(path)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    let status = |path: &str| {
+        request(path)
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap()
+            .http_status()
+    };
+
+    // `b` matches the literal segment of `/a/b/d`, but `/a/{x}/c` has to be tried as well
+    assert_eq!(
+        request("/a/b/c").unwrap(),
+        Routes::Any { x: "b".to_string() }
+    );
+    assert_eq!(request("/a/b/d").unwrap(), Routes::Literal);
+    assert_eq!(request("/a/-12/e").unwrap(), Routes::Constrained { x: -12 });
+    assert_eq!(
+        request("/a/x.txt").unwrap(),
+        Routes::Mixed {
+            name: "x".to_string(),
+            ext: "txt".to_string()
+        }
+    );
+    assert_eq!(request("/a/b").unwrap(), Routes::Short);
+
+    assert_eq!(status("/a/b/e"), StatusCode::NOT_FOUND);
+    assert_eq!(status("/a/b/"), StatusCode::NOT_FOUND);
+    assert_eq!(status("/a//c"), StatusCode::NOT_FOUND);
+    assert_eq!(status("/a/b/c/"), StatusCode::NOT_FOUND);
+    assert_eq!(status("/a"), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn consumes() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[post("/items")]
+        #[consumes("application/json")]
+        Json,
+
+        #[post("/items")]
+        #[consumes("application/x-www-form-urlencoded", "text/*")]
+        Form,
+
+        #[put("/items/{id}")]
+        #[consumes("application/json")]
+        Update { id: u32 },
+
+        #[put("/items/{id}")]
+        UpdateAny { id: u32 },
+    }
+
+    let request = |method: Method, path: &str, content_type: Option<&str>| {
+        let mut builder = Request::builder();
+        builder.method(method).uri(path);
+        if let Some(content_type) = content_type {
+            builder.header("Content-Type", content_type);
+        }
+        invoke::<Routes>(builder.body(Body::empty()).unwrap())
+    };
+
+    assert_eq!(
+        request(Method::POST, "/items", Some("application/json")).unwrap(),
+        Routes::Json
+    );
+    // Parameters are ignored, media types are case-insensitive
+    assert_eq!(
+        request(Method::POST, "/items", Some("Application/JSON; charset=utf-8")).unwrap(),
+        Routes::Json
+    );
+    assert_eq!(
+        request(Method::POST, "/items", Some("application/x-www-form-urlencoded")).unwrap(),
+        Routes::Form
+    );
+    assert_eq!(
+        request(Method::POST, "/items", Some("text/csv")).unwrap(),
+        Routes::Form
+    );
+
+    for content_type in &[Some("application/xml"), Some("application"), None] {
+        let err: Box<Error> = request(Method::POST, "/items", *content_type)
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err.http_status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    // Variants without `#[consumes]` accept everything not handled by more specific ones
+    assert_eq!(
+        request(Method::PUT, "/items/1", Some("application/json")).unwrap(),
+        Routes::Update { id: 1 }
+    );
+    assert_eq!(
+        request(Method::PUT, "/items/1", Some("text/plain")).unwrap(),
+        Routes::UpdateAny { id: 1 }
+    );
+    assert_eq!(
+        request(Method::PUT, "/items/1", None).unwrap(),
+        Routes::UpdateAny { id: 1 }
+    );
+
+    // The media type doesn't affect the allowed methods
+    let err: Box<Error> = request(Method::GET, "/items", Some("text/plain"))
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(err.allowed_methods(), Some(&[&Method::POST][..]));
+
+    assert_eq!(Routes::ROUTES[0].consumes, &["application/json"]);
+    assert_eq!(
+        Routes::ROUTES[1].consumes,
+        &["application/x-www-form-urlencoded", "text/*"]
+    );
+    assert!(Routes::ROUTES[3].consumes.is_empty());
+}
+
+#[test]
+fn produces() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/items")]
+        #[produces("text/html")]
+        Html,
+
+        #[get("/items")]
+        #[produces("application/json", "application/vnd.items+json")]
+        Json,
+
+        #[get("/items/{id}")]
+        #[produces("application/json")]
+        ItemJson { id: u32 },
+
+        #[get("/items/{id}")]
+        Item { id: u32 },
+    }
+
+    let request = |path: &str, accept: Option<&str>| {
+        let mut builder = Request::builder();
+        builder.uri(path);
+        if let Some(accept) = accept {
+            builder.header("Accept", accept);
+        }
+        invoke::<Routes>(builder.body(Body::empty()).unwrap())
+    };
+
+    // A browser prefers HTML
+    let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+    assert_eq!(request("/items", Some(browser)).unwrap(), Routes::Html);
+    assert_eq!(
+        request("/items", Some("application/json")).unwrap(),
+        Routes::Json
+    );
+    assert_eq!(
+        request("/items", Some("application/vnd.items+json")).unwrap(),
+        Routes::Json
+    );
+    // q-values decide, and more specific ranges take precedence
+    assert_eq!(
+        request("/items", Some("text/html;q=0.5, application/json")).unwrap(),
+        Routes::Json
+    );
+    assert_eq!(
+        request("/items", Some("*/*, text/html;q=0")).unwrap(),
+        Routes::Json
+    );
+    assert_eq!(
+        request("/items", Some("application/*;q=0.2, TEXT/*;q=0.3")).unwrap(),
+        Routes::Html
+    );
+    // Without `Accept` header, the first variant is used
+    assert_eq!(request("/items", None).unwrap(), Routes::Html);
+    assert_eq!(request("/items", Some("*/*")).unwrap(), Routes::Html);
+
+    for accept in &["image/png", "text/html;q=0, application/*;q=0"] {
+        let err: Box<Error> = request("/items", Some(accept))
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err.http_status(), StatusCode::NOT_ACCEPTABLE);
+    }
+
+    // Variants without `#[produces]` are used if nothing else is acceptable
+    assert_eq!(
+        request("/items/1", Some("application/json")).unwrap(),
+        Routes::ItemJson { id: 1 }
+    );
+    assert_eq!(
+        request("/items/1", Some("text/html")).unwrap(),
+        Routes::Item { id: 1 }
+    );
+    let err: Box<Error> = request("/items/x", Some("application/json"))
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+
+    assert_eq!(Routes::ROUTES[0].produces, &["text/html"]);
+    assert!(Routes::ROUTES[3].produces.is_empty());
+}
+
+#[test]
+fn headers() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/")]
+        Index {
+            #[header("X-Request-Id")]
+            request_id: u64,
+            #[header("if-none-match")]
+            etag: Option<String>,
+        },
+    }
+
+    let request = |headers: &[(&str, &str)]| {
+        let mut builder = Request::builder();
+        builder.uri("/");
+        for (name, value) in headers {
+            builder.header(*name, *value);
+        }
+        invoke::<Routes>(builder.body(Body::empty()).unwrap())
+    };
+
+    assert_eq!(
+        request(&[("x-request-id", "42")]).unwrap(),
+        Routes::Index {
+            request_id: 42,
+            etag: None,
+        }
+    );
+    assert_eq!(
+        request(&[("X-REQUEST-ID", "42"), ("If-None-Match", "\"abc\"")]).unwrap(),
+        Routes::Index {
+            request_id: 42,
+            etag: Some("\"abc\"".to_string()),
+        }
+    );
+
+    let err: Box<Error> = request(&[]).unwrap_err().downcast().unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        err.source().unwrap().to_string(),
+        "missing header `X-Request-Id`"
+    );
+
+    let err: Box<Error> = request(&[("X-Request-Id", "abc")])
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        err.source().unwrap().to_string(),
+        "invalid header `X-Request-Id`: invalid digit found in string"
+    );
+
+    assert_eq!(Routes::ROUTES[0].headers[0].name, "X-Request-Id");
+    assert!(Routes::ROUTES[0].headers[0].required);
+    assert!(!Routes::ROUTES[0].headers[1].required);
+}
+
+#[test]
+fn cookies() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/")]
+        Index {
+            #[cookie("session")]
+            session: String,
+            #[cookie("visits")]
+            visits: Option<u32>,
+        },
+    }
+
+    let request = |cookies: &[&str]| {
+        let mut builder = Request::builder();
+        builder.uri("/");
+        for cookie in cookies {
+            builder.header("Cookie", *cookie);
+        }
+        invoke::<Routes>(builder.body(Body::empty()).unwrap())
+    };
+
+    assert_eq!(
+        request(&["session=abc"]).unwrap(),
+        Routes::Index {
+            session: "abc".to_string(),
+            visits: None,
+        }
+    );
+    // Cookies are collected from all headers, and values are unquoted and percent-decoded
+    assert_eq!(
+        request(&["theme=dark; visits=3", "Session=x; session=\"a%20b\""]).unwrap(),
+        Routes::Index {
+            session: "a b".to_string(),
+            visits: Some(3),
+        }
+    );
+
+    let err: Box<Error> = request(&["Session=abc"]).unwrap_err().downcast().unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert_eq!(err.source().unwrap().to_string(), "missing cookie `session`");
+
+    let err: Box<Error> = request(&["session=abc; visits=many"])
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        err.source().unwrap().to_string(),
+        "invalid cookie `visits`: invalid digit found in string"
+    );
+
+    assert_eq!(Routes::ROUTES[0].cookies[0].name, "session");
+    assert!(Routes::ROUTES[0].cookies[0].required);
+    assert!(!Routes::ROUTES[0].cookies[1].required);
+}
+
+#[test]
+fn query() {
+    #[derive(Deserialize, Debug, PartialEq, Eq)]
+    #[serde(deny_unknown_fields)]
+    struct Filter {
+        status: Option<String>,
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/items")]
+        Items {
+            #[query("page")]
+            page: Option<u32>,
+            #[query]
+            limit: u32,
+            #[query_params]
+            filter: Filter,
+        },
+    }
+
+    let request = |uri: &str| invoke::<Routes>(Request::get(uri).body(Body::empty()).unwrap());
+
+    assert_eq!(
+        request("/items?limit=10").unwrap(),
+        Routes::Items {
+            page: None,
+            limit: 10,
+            filter: Filter { status: None },
+        }
+    );
+    // Parameters bound to `#[query]` fields aren't passed to the `#[query_params]` struct
+    assert_eq!(
+        request("/items?status=open%20now&limit=10&page=2&page=3").unwrap(),
+        Routes::Items {
+            page: Some(2),
+            limit: 10,
+            filter: Filter {
+                status: Some("open now".to_string()),
+            },
+        }
+    );
+
+    let err: Box<Error> = request("/items?page=1").unwrap_err().downcast().unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        err.source().unwrap().to_string(),
+        "missing query parameter `limit`"
+    );
+
+    let err: Box<Error> = request("/items?limit=-1").unwrap_err().downcast().unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        err.source().unwrap().to_string(),
+        "invalid query parameter `limit`: invalid digit found in string"
+    );
+
+    let err: Box<Error> = request("/items?limit=1&sort=asc")
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(Routes::ROUTES[0].query[0].name, "page");
+    assert!(!Routes::ROUTES[0].query[0].required);
+    assert_eq!(Routes::ROUTES[0].query[1].name, "limit");
+    assert!(Routes::ROUTES[0].query[1].required);
+}
+
+/// Guards used by the `guard_attributes` test. Each one requires a header and rejects the request
+/// with its own status code if the header is missing.
+mod guards {
+    use hyperdrive::{http::StatusCode, BoxedError, Error, Guard, NoContext};
+    use std::sync::Arc;
+
+    fn require(
+        request: &http::Request<()>,
+        header: &str,
+        status: StatusCode,
+    ) -> Result<(), BoxedError> {
+        if request.headers().contains_key(header) {
+            Ok(())
+        } else {
+            Err(Error::from_status(status).into())
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct Authenticated;
+
+    impl Guard for Authenticated {
+        type Context = NoContext;
+        type Result = Result<Self, BoxedError>;
+
+        fn from_request(request: &Arc<http::Request<()>>, _: &NoContext) -> Self::Result {
+            require(request, "Authorization", StatusCode::UNAUTHORIZED).map(|_| Authenticated)
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct Admin;
+
+    impl Guard for Admin {
+        type Context = NoContext;
+        type Result = Result<Self, BoxedError>;
+
+        fn from_request(request: &Arc<http::Request<()>>, _: &NoContext) -> Self::Result {
+            require(request, "X-Admin", StatusCode::FORBIDDEN).map(|_| Admin)
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct Teapot;
+
+    impl Guard for Teapot {
+        type Context = NoContext;
+        type Result = Result<Self, BoxedError>;
+
+        fn from_request(request: &Arc<http::Request<()>>, _: &NoContext) -> Self::Result {
+            require(request, "X-Tea", StatusCode::IM_A_TEAPOT).map(|_| Teapot)
+        }
+    }
+
+    fn forward_unless(request: &http::Request<()>, header: &str) -> Result<(), BoxedError> {
+        if request.headers().contains_key(header) {
+            Ok(())
+        } else {
+            Err(Error::forward().into())
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct Staff;
+
+    impl Guard for Staff {
+        type Context = NoContext;
+        type Result = Result<Self, BoxedError>;
+
+        fn from_request(request: &Arc<http::Request<()>>, _: &NoContext) -> Self::Result {
+            forward_unless(request, "X-Staff").map(|_| Staff)
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct User;
+
+    impl Guard for User {
+        type Context = NoContext;
+        type Result = Result<Self, BoxedError>;
+
+        fn from_request(request: &Arc<http::Request<()>>, _: &NoContext) -> Self::Result {
+            forward_unless(request, "X-User").map(|_| User)
+        }
+    }
+}
+
+#[test]
+fn guard_attributes() {
+    use guards::{Admin, Teapot};
+    use hyperdrive::RouteInfo;
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[guard(guards::Authenticated)]
+    enum Routes {
+        #[get("/")]
+        Index,
+
+        #[get("/admin")]
+        #[guard(Admin)]
+        Admin { tea: Teapot },
+
+        Fallback {
+            #[forward]
+            inner: Fallback,
+        },
+    }
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[get("/fallback")]
+    struct Fallback;
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    #[get("/")]
+    #[guard(Admin)]
+    #[guard(Teapot)]
+    struct Struct;
+
+    let status = |result: Result<Routes, BoxedError>| {
+        let err: Box<Error> = result.unwrap_err().downcast().unwrap();
+        err.http_status()
+    };
+    let request = |path: &str, headers: &[&str]| {
+        let mut builder = Request::builder();
+        builder.uri(path);
+        for name in headers {
+            builder.header(*name, "1");
+        }
+        builder.body(Body::empty()).unwrap()
+    };
+
+    assert_eq!(
+        invoke::<Routes>(request("/", &["Authorization"])).unwrap(),
+        Routes::Index
+    );
+    assert_eq!(status(invoke(request("/", &[]))), StatusCode::UNAUTHORIZED);
+
+    // Guards on the enum are checked first, then the ones on the variant, then guard fields
+    assert_eq!(
+        invoke::<Routes>(request("/admin", &["Authorization", "X-Admin", "X-Tea"])).unwrap(),
+        Routes::Admin { tea: Teapot }
+    );
+    assert_eq!(
+        status(invoke(request("/admin", &["X-Admin", "X-Tea"]))),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status(invoke(request("/admin", &["Authorization", "X-Tea"]))),
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status(invoke(request("/admin", &["Authorization", "X-Admin"]))),
+        StatusCode::IM_A_TEAPOT
+    );
+
+    // The fallback variant is guarded too
+    assert_eq!(
+        status(invoke(request("/fallback", &[]))),
+        StatusCode::UNAUTHORIZED
+    );
+
+    invoke::<Struct>(request("/", &["X-Admin", "X-Tea"])).unwrap();
+    let err: Box<Error> = invoke::<Struct>(request("/", &["X-Tea"]))
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err.http_status(), StatusCode::FORBIDDEN);
+
+    // The route table lists guards from attributes (without field name) before guard fields
+    let guards = |route: &RouteInfo| {
+        route
+            .guards
+            .iter()
+            .map(|guard| (guard.name, guard.ty))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(guards(&Routes::ROUTES[0]), [("", "guards::Authenticated")]);
+    assert_eq!(
+        guards(&Routes::ROUTES[1]),
+        [
+            ("", "guards::Authenticated"),
+            ("", "Admin"),
+            ("tea", "Teapot")
+        ]
+    );
+    assert_eq!(guards(&Struct::ROUTES[0]), [("", "Admin"), ("", "Teapot")]);
+}
+
+#[test]
+fn guard_forwarding() {
+    use guards::{Admin, Staff, User};
+
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/dashboard")]
+        StaffDashboard { staff: Staff },
+
+        #[get("/dashboard", rank = 1)]
+        UserDashboard { user: User },
+
+        #[get("/settings")]
+        AdminSettings { admin: Admin },
+
+        #[get("/settings", rank = 1)]
+        UserSettings { user: User },
+
+        #[get("/users/me")]
+        Me { user: User },
+
+        #[get("/users/{id}", rank = 1)]
+        UserById { id: String },
+
+        #[post("/upload")]
+        StaffUpload {
+            staff: Staff,
+            #[body]
+            body: Json<String>,
+        },
+
+        #[post("/upload", rank = 1)]
+        Upload {
+            #[body]
+            body: Json<String>,
+        },
+    }
+
+    let error = |result: Result<Routes, BoxedError>| -> Box<Error> {
+        result.unwrap_err().downcast().unwrap()
+    };
+    let request = |method: &str, path: &str, headers: &[&str]| {
+        let mut builder = Request::builder();
+        builder.method(method).uri(path);
+        for name in headers {
+            builder.header(*name, "1");
+        }
+        builder.body(Body::from(r#""data""#)).unwrap()
+    };
+
+    assert_eq!(
+        invoke::<Routes>(request("GET", "/dashboard", &["X-Staff", "X-User"])).unwrap(),
+        Routes::StaffDashboard { staff: Staff }
+    );
+    assert_eq!(
+        invoke::<Routes>(request("GET", "/dashboard", &["X-User"])).unwrap(),
+        Routes::UserDashboard { user: User }
+    );
+
+    // When every candidate forwards, the request isn't found
+    assert_eq!(
+        error(invoke(request("GET", "/dashboard", &[]))).http_status(),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        error(invoke(request("PUT", "/dashboard", &["X-User"]))).http_status(),
+        StatusCode::METHOD_NOT_ALLOWED
+    );
+
+    // Other errors aren't forwarded
+    assert_eq!(
+        error(invoke(request("GET", "/settings", &["X-User"]))).http_status(),
+        StatusCode::FORBIDDEN
+    );
+
+    // Forwarded requests can end up at a different path pattern
+    assert_eq!(
+        invoke::<Routes>(request("GET", "/users/me", &["X-User"])).unwrap(),
+        Routes::Me { user: User }
+    );
+    assert_eq!(
+        invoke::<Routes>(request("GET", "/users/me", &[])).unwrap(),
+        Routes::UserById { id: "me".to_string() }
+    );
+
+    // The body is still available after forwarding
+    assert_eq!(
+        invoke::<Routes>(request("POST", "/upload", &["X-Staff"])).unwrap(),
+        Routes::StaffUpload {
+            staff: Staff,
+            body: Json("data".to_string()),
+        }
+    );
+    assert_eq!(
+        invoke::<Routes>(request("POST", "/upload", &[])).unwrap(),
+        Routes::Upload {
+            body: Json("data".to_string()),
+        }
+    );
+}
+
+#[test]
+fn route_aliases() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/u/{id}")]
+        #[get("/users/{name}/{id}")]
+        User { id: u32, name: Option<String> },
+
+        #[get("/posts/{slug}")]
+        #[get("/posts/{slug}/page/{page}")]
+        Post {
+            slug: String,
+            #[default]
+            page: u32,
+        },
+
+        #[get("/users/{owner}/files/{path...}")]
+        #[get("/files/{path...}")]
+        File { owner: Option<String>, path: String },
+    }
+
+    let get = |path: &str| invoke::<Routes>(Request::get(path).body(Body::empty()).unwrap());
+
+    // Placeholders are looked up by name, whatever their position in the route
+    assert_eq!(
+        get("/u/5").unwrap(),
+        Routes::User { id: 5, name: None }
+    );
+    assert_eq!(
+        get("/users/jonas/5").unwrap(),
+        Routes::User {
+            id: 5,
+            name: Some("jonas".to_string()),
+        }
+    );
+    let err: Box<Error> = get("/users/jonas/x").unwrap_err().downcast().unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+
+    assert_eq!(
+        get("/posts/hello").unwrap(),
+        Routes::Post {
+            slug: "hello".to_string(),
+            page: 0,
+        }
+    );
+    assert_eq!(
+        get("/posts/hello/page/3").unwrap(),
+        Routes::Post {
+            slug: "hello".to_string(),
+            page: 3,
+        }
+    );
+
+    assert_eq!(
+        get("/files/a/b.txt").unwrap(),
+        Routes::File {
+            owner: None,
+            path: "a/b.txt".to_string(),
+        }
+    );
+    assert_eq!(
+        get("/users/jonas/files/a%20b.txt").unwrap(),
+        Routes::File {
+            owner: Some("jonas".to_string()),
+            path: "a b.txt".to_string(),
+        }
+    );
+
+    // Reverse routing uses the first route, skipping ones with `None` placeholders
+    assert_eq!(
+        Routes::User {
+            id: 5,
+            name: Some("jonas".to_string()),
+        }
+        .to_path()
+        .as_deref(),
+        Some("/u/5")
+    );
+    assert_eq!(
+        Routes::File {
+            owner: Some("jonas".to_string()),
+            path: "a/b.txt".to_string(),
+        }
+        .to_path()
+        .as_deref(),
+        Some("/users/jonas/files/a/b.txt")
+    );
+    assert_eq!(
+        Routes::File {
+            owner: None,
+            path: "a/b.txt".to_string(),
+        }
+        .to_path()
+        .as_deref(),
+        Some("/files/a/b.txt")
+    );
+    assert_eq!(
+        Routes::file_path(&"jonas".to_string(), &"a".to_string()),
+        "/users/jonas/files/a"
+    );
+
+    let user = &Routes::ROUTES[1];
+    assert_eq!(user.placeholders[0].name, "name");
+    assert_eq!(user.placeholders[1].name, "id");
+}
+
+#[test]
+fn optional_segments() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/archive/{year}/{month?}/{day?}")]
+        Archive {
+            year: u16,
+            month: Option<u8>,
+            day: Option<u8>,
+        },
+
+        #[get("/feed/{page?:uint}")]
+        Feed {
+            #[default]
+            page: u32,
+        },
+    }
+
+    let get = |path: &str| invoke::<Routes>(Request::get(path).body(Body::empty()).unwrap());
+    let not_found = |path: &str| {
+        let err: Box<Error> = get(path).unwrap_err().downcast().unwrap();
+        assert_eq!(err.http_status(), StatusCode::NOT_FOUND, "{}", path);
+    };
+
+    assert_eq!(
+        get("/archive/2019").unwrap(),
+        Routes::Archive {
+            year: 2019,
+            month: None,
+            day: None,
+        }
+    );
+    assert_eq!(
+        get("/archive/2019/5").unwrap(),
+        Routes::Archive {
+            year: 2019,
+            month: Some(5),
+            day: None,
+        }
+    );
+    assert_eq!(
+        get("/archive/2019/5/17").unwrap(),
+        Routes::Archive {
+            year: 2019,
+            month: Some(5),
+            day: Some(17),
+        }
+    );
+    not_found("/archive");
+    not_found("/archive/2019/");
+    not_found("/archive/2019/may");
+    not_found("/archive/2019/5/17/1");
+
+    assert_eq!(get("/feed").unwrap(), Routes::Feed { page: 0 });
+    assert_eq!(get("/feed/2").unwrap(), Routes::Feed { page: 2 });
+    not_found("/feed/two");
+
+    // Placeholders following a `None` are left out as well
+    assert_eq!(
+        Routes::Archive {
+            year: 2019,
+            month: Some(5),
+            day: None,
+        }
+        .to_path()
+        .as_deref(),
+        Some("/archive/2019/5")
+    );
+    assert_eq!(
+        Routes::Archive {
+            year: 2019,
+            month: None,
+            day: Some(17),
+        }
+        .to_path()
+        .as_deref(),
+        Some("/archive/2019")
+    );
+    assert_eq!(
+        Routes::archive_path(&2019, Some(&5), Some(&17)),
+        "/archive/2019/5/17"
+    );
+    assert_eq!(
+        Routes::Feed { page: 3 }.to_path().as_deref(),
+        Some("/feed/3")
+    );
+
+    let archive = &Routes::ROUTES[0];
+    assert_eq!(archive.path, "/archive/{year}/{month?}/{day?}");
+    assert_eq!(archive.placeholders.len(), 3);
+}
+
+#[test]
+fn rest_placeholder_suffix() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/repos/{path...}/blob")]
+        Blob { path: String },
+
+        #[get("/repos/{path...}/tree/")]
+        Tree { path: String },
+
+        #[get("/buckets/{bucket}/{key...}/acl")]
+        Acl { bucket: String, key: String },
+
+        #[get("/buckets/{bucket}/{key...}", rank = 1)]
+        Object { bucket: String, key: String },
+    }
+
+    let get = |path: &str| invoke::<Routes>(Request::get(path).body(Body::empty()).unwrap());
+    let not_found = |path: &str| {
+        let err: Box<Error> = get(path).unwrap_err().downcast().unwrap();
+        assert_eq!(err.http_status(), StatusCode::NOT_FOUND, "{}", path);
+    };
+
+    assert_eq!(
+        get("/repos/hyperdrive/blob").unwrap(),
+        Routes::Blob {
+            path: "hyperdrive".to_string()
+        }
+    );
+    assert_eq!(
+        get("/repos/a/b%20c/blob").unwrap(),
+        Routes::Blob {
+            path: "a/b c".to_string()
+        }
+    );
+    // Only the last segments are matched against the suffix
+    assert_eq!(
+        get("/repos/a/blob/blob").unwrap(),
+        Routes::Blob {
+            path: "a/blob".to_string()
+        }
+    );
+    assert_eq!(
+        get("/repos/a/b/tree/").unwrap(),
+        Routes::Tree {
+            path: "a/b".to_string()
+        }
+    );
+    not_found("/repos/blob");
+    not_found("/repos/a/blob/");
+    not_found("/repos/a/tree");
+
+    assert_eq!(
+        get("/buckets/b/photos/me.jpg/acl").unwrap(),
+        Routes::Acl {
+            bucket: "b".to_string(),
+            key: "photos/me.jpg".to_string(),
+        }
+    );
+    assert_eq!(
+        get("/buckets/b/photos/me.jpg").unwrap(),
+        Routes::Object {
+            bucket: "b".to_string(),
+            key: "photos/me.jpg".to_string(),
+        }
+    );
+
+    assert_eq!(
+        Routes::Blob {
+            path: "a/b c".to_string()
+        }
+        .to_path()
+        .as_deref(),
+        Some("/repos/a/b%20c/blob")
+    );
+    assert_eq!(Routes::tree_path(&"a".to_string()), "/repos/a/tree/");
+}
+
+#[test]
+fn any_method() {
+    #[derive(FromRequest, Debug, PartialEq, Eq)]
+    enum Routes {
+        #[get("/health")]
+        Health,
+
+        #[any("/health")]
+        HealthOther { method: Method },
+
+        #[get("/items/{id}")]
+        Item { id: u32 },
+
+        #[any("/items/{key}")]
+        ItemOther { key: String, method: Method },
+
+        #[post("/proxy/upload")]
+        Upload,
+
+        #[any("/proxy/{path...}", rank = 1)]
+        Proxy { path: String, method: Method },
+    }
+
+    let request = |method: &str, path: &str| {
+        invoke::<Routes>(
+            Request::builder()
+                .method(method)
+                .uri(path)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+
+    // Routes for a specific method are preferred
+    assert_eq!(request("GET", "/health").unwrap(), Routes::Health);
+    assert_eq!(request("HEAD", "/health").unwrap(), Routes::Health);
+    assert_eq!(
+        request("DELETE", "/health").unwrap(),
+        Routes::HealthOther {
+            method: Method::DELETE
+        }
+    );
+    assert_eq!(
+        request("PROPFIND", "/health").unwrap(),
+        Routes::HealthOther {
+            method: Method::from_bytes(b"PROPFIND").unwrap()
+        }
+    );
+
+    // ...unless they reject the request
+    assert_eq!(request("GET", "/items/1").unwrap(), Routes::Item { id: 1 });
+    assert_eq!(
+        request("GET", "/items/abc").unwrap(),
+        Routes::ItemOther {
+            key: "abc".to_string(),
+            method: Method::GET,
+        }
+    );
+    assert_eq!(
+        request("PUT", "/items/1").unwrap(),
+        Routes::ItemOther {
+            key: "1".to_string(),
+            method: Method::PUT,
+        }
+    );
+
+    // Ranks decide between overlapping routes as usual
+    assert_eq!(request("POST", "/proxy/upload").unwrap(), Routes::Upload);
+    assert_eq!(
+        request("GET", "/proxy/upload").unwrap(),
+        Routes::Proxy {
+            path: "upload".to_string(),
+            method: Method::GET,
+        }
+    );
+    assert_eq!(
+        request("PATCH", "/proxy/a/b").unwrap(),
+        Routes::Proxy {
+            path: "a/b".to_string(),
+            method: Method::PATCH,
+        }
+    );
+
+    let err: Box<Error> = request("GET", "/nothing").unwrap_err().downcast().unwrap();
+    assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+
+    let routes = Routes::ROUTES
+        .iter()
+        .map(|route| (route.method, route.path))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        routes,
+        [
+            ("GET", "/health"),
+            ("*", "/health"),
+            ("GET", "/items/{id}"),
+            ("*", "/items/{key}"),
+            ("POST", "/proxy/upload"),
+            ("*", "/proxy/{path...}"),
+        ]
+    );
+    assert_eq!(Routes::proxy_path(&"a/b".to_string()), "/proxy/a/b");
+}
@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 use http::Request;
 use hyperdrive::*;
 use std::sync::Arc;
@@ -0,0 +1,404 @@
+use hyperdrive::{
+    body::{HtmlForm, Json},
+    openapi::Document,
+    FromRequest,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+struct Login {
+    user: String,
+    password: String,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+struct Search {
+    q: String,
+    #[serde(rename = "per-page")]
+    per_page: Option<u32>,
+}
+
+#[allow(dead_code)]
+#[derive(FromRequest)]
+enum Route {
+    /// Searches for users.
+    ///
+    /// Returns at most `per-page` results.
+    #[get("/users")]
+    Users {
+        #[query_params]
+        search: Search,
+    },
+
+    #[get("/users/{id}/files/{path...}")]
+    #[head("/users/{id}/files/{path...}")]
+    File { id: u64, path: String },
+
+    #[post("/login")]
+    Login {
+        #[body]
+        data: Json<Login>,
+    },
+
+    #[post("/login-form")]
+    LoginForm {
+        #[body]
+        data: HtmlForm<Login>,
+    },
+
+    #[get("/users/{id:[0-9]{1,9}}/avatar")]
+    Avatar { id: u32 },
+
+    #[options("*")]
+    Options,
+
+    #[route(PROPFIND, "/users")]
+    PropFind,
+}
+
+#[test]
+fn document() {
+    let doc = Document::new("Test", "0.1.0")
+        .description("Test API")
+        .operations(Route::openapi_operations())
+        .to_json();
+
+    let default_response = json!({
+        "default": { "description": "Default response" },
+    });
+    let file = json!({
+        "parameters": [
+            {
+                "name": "id",
+                "in": "path",
+                "required": true,
+                "schema": { "type": "integer", "minimum": 0 },
+            },
+            {
+                "name": "path",
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" },
+            },
+        ],
+        "responses": default_response,
+    });
+
+    assert_eq!(
+        doc,
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Test",
+                "version": "0.1.0",
+                "description": "Test API",
+            },
+            "paths": {
+                "/users": {
+                    "get": {
+                        "summary": "Searches for users.",
+                        "description": "Searches for users.\n\nReturns at most `per-page` results.",
+                        "parameters": [
+                            {
+                                "name": "q",
+                                "in": "query",
+                                "required": false,
+                                "schema": { "type": "string" },
+                            },
+                            {
+                                "name": "per-page",
+                                "in": "query",
+                                "required": false,
+                                "schema": { "type": "string" },
+                            },
+                        ],
+                        "responses": default_response,
+                    },
+                },
+                "/users/{id}/avatar": {
+                    "get": {
+                        "parameters": [
+                            {
+                                "name": "id",
+                                "in": "path",
+                                "required": true,
+                                "schema": { "type": "integer", "minimum": 0 },
+                            },
+                        ],
+                        "responses": default_response,
+                    },
+                },
+                "/users/{id}/files/{path}": {
+                    "get": file,
+                    "head": file,
+                },
+                "/login": {
+                    "post": {
+                        "requestBody": {
+                            "required": true,
+                            "content": {
+                                "application/json": { "schema": {} },
+                            },
+                        },
+                        "responses": default_response,
+                    },
+                },
+                "/login-form": {
+                    "post": {
+                        "requestBody": {
+                            "required": true,
+                            "content": {
+                                "application/x-www-form-urlencoded": { "schema": {} },
+                            },
+                        },
+                        "responses": default_response,
+                    },
+                },
+            },
+        })
+    );
+}
+
+#[test]
+fn generic_query_params() {
+    #[allow(dead_code)]
+    #[derive(FromRequest)]
+    #[get("/")]
+    struct Generic<Q> {
+        #[query_params]
+        query: Q,
+    }
+
+    let ops = Generic::<Search>::openapi_operations();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].route().path, "/");
+    assert_eq!(ops[0].query_params(), &["q", "per-page"]);
+
+    // Maps have no statically known parameters
+    let ops = Generic::<std::collections::HashMap<String, String>>::openapi_operations();
+    assert!(ops[0].query_params().is_empty());
+}
+
+#[test]
+fn consumes() {
+    #[allow(dead_code)]
+    #[derive(FromRequest)]
+    enum Items {
+        #[post("/items")]
+        #[consumes("application/json")]
+        Json {
+            #[body]
+            data: Json<Login>,
+        },
+
+        #[post("/items")]
+        #[consumes("application/x-www-form-urlencoded", "multipart/form-data")]
+        Form,
+    }
+
+    let doc = Document::new("Test", "0.1.0")
+        .operations(Items::openapi_operations())
+        .to_json();
+
+    assert_eq!(
+        doc["paths"]["/items"]["post"]["requestBody"]["content"],
+        json!({
+            "application/json": { "schema": {} },
+            "application/x-www-form-urlencoded": { "schema": {} },
+            "multipart/form-data": { "schema": {} },
+        })
+    );
+}
+
+#[test]
+fn produces() {
+    #[allow(dead_code)]
+    #[derive(FromRequest)]
+    enum Items {
+        #[get("/items")]
+        #[produces("text/html")]
+        Html,
+
+        #[get("/items")]
+        #[produces("application/json")]
+        Json,
+    }
+
+    let doc = Document::new("Test", "0.1.0")
+        .operations(Items::openapi_operations())
+        .to_json();
+
+    assert_eq!(
+        doc["paths"]["/items"]["get"]["responses"],
+        json!({
+            "default": {
+                "description": "Default response",
+                "content": {
+                    "application/json": { "schema": {} },
+                    "text/html": { "schema": {} },
+                },
+            },
+        })
+    );
+}
+
+#[test]
+fn headers() {
+    #[allow(dead_code)]
+    #[derive(FromRequest)]
+    enum Items {
+        #[get("/items/{id}")]
+        Item {
+            id: u32,
+            #[header("X-Request-Id")]
+            request_id: u64,
+            #[header("If-None-Match")]
+            etag: Option<String>,
+        },
+    }
+
+    let doc = Document::new("Test", "0.1.0")
+        .operations(Items::openapi_operations())
+        .to_json();
+
+    assert_eq!(
+        doc["paths"]["/items/{id}"]["get"]["parameters"],
+        json!([
+            {
+                "name": "id",
+                "in": "path",
+                "required": true,
+                "schema": { "type": "integer", "minimum": 0 },
+            },
+            {
+                "name": "X-Request-Id",
+                "in": "header",
+                "required": true,
+                "schema": { "type": "integer", "minimum": 0 },
+            },
+            {
+                "name": "If-None-Match",
+                "in": "header",
+                "required": false,
+                "schema": { "type": "string" },
+            },
+        ])
+    );
+}
+
+#[test]
+fn cookies() {
+    #[allow(dead_code)]
+    #[derive(FromRequest)]
+    enum Account {
+        #[get("/account")]
+        Show {
+            #[cookie("session")]
+            session: String,
+            #[cookie("theme")]
+            theme: Option<String>,
+        },
+    }
+
+    let doc = Document::new("Test", "0.1.0")
+        .operations(Account::openapi_operations())
+        .to_json();
+
+    assert_eq!(
+        doc["paths"]["/account"]["get"]["parameters"],
+        json!([
+            {
+                "name": "session",
+                "in": "cookie",
+                "required": true,
+                "schema": { "type": "string" },
+            },
+            {
+                "name": "theme",
+                "in": "cookie",
+                "required": false,
+                "schema": { "type": "string" },
+            },
+        ])
+    );
+}
+
+#[test]
+fn query() {
+    #[allow(dead_code)]
+    #[derive(FromRequest)]
+    enum Users {
+        #[get("/users")]
+        List {
+            #[query("per-page")]
+            per_page: Option<u32>,
+            #[query]
+            sort: String,
+        },
+    }
+
+    let doc = Document::new("Test", "0.1.0")
+        .operations(Users::openapi_operations())
+        .to_json();
+
+    assert_eq!(
+        doc["paths"]["/users"]["get"]["parameters"],
+        json!([
+            {
+                "name": "per-page",
+                "in": "query",
+                "required": false,
+                "schema": { "type": "integer", "minimum": 0 },
+            },
+            {
+                "name": "sort",
+                "in": "query",
+                "required": true,
+                "schema": { "type": "string" },
+            },
+        ])
+    );
+}
+
+#[test]
+fn optional_placeholders() {
+    #[allow(dead_code)]
+    #[derive(FromRequest)]
+    enum Blog {
+        #[get("/archive/{year}/{month?:uint}")]
+        Archive { year: u16, month: Option<u8> },
+    }
+
+    let doc = Document::new("Test", "0.1.0")
+        .operations(Blog::openapi_operations())
+        .to_json();
+
+    let paths = doc["paths"].as_object().unwrap();
+    assert_eq!(
+        paths.keys().collect::<Vec<_>>(),
+        ["/archive/{year}", "/archive/{year}/{month}"]
+    );
+    assert_eq!(
+        paths["/archive/{year}"]["get"]["parameters"],
+        json!([
+            {
+                "name": "year",
+                "in": "path",
+                "required": true,
+                "schema": { "type": "integer", "minimum": 0 },
+            },
+        ])
+    );
+    assert_eq!(
+        paths["/archive/{year}/{month}"]["get"]["parameters"][1],
+        json!({
+            "name": "month",
+            "in": "path",
+            "required": true,
+            "schema": { "type": "integer", "minimum": 0 },
+        })
+    );
+}
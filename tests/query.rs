@@ -0,0 +1,166 @@
+use hyperdrive::query::{self, Error};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Search {
+    q: String,
+    #[serde(default)]
+    tag: Vec<String>,
+    page: Option<u32>,
+}
+
+#[test]
+fn flat() {
+    assert_eq!(
+        query::from_str::<Search>("q=hello+world%21&page=2").unwrap(),
+        Search {
+            q: "hello world!".to_string(),
+            tag: vec![],
+            page: Some(2),
+        }
+    );
+
+    // Empty pairs are skipped, and a pair without `=` has an empty value
+    assert_eq!(
+        query::from_str::<Search>("&&q&").unwrap(),
+        Search {
+            q: String::new(),
+            tag: vec![],
+            page: None,
+        }
+    );
+
+    assert_eq!(
+        query::from_str::<HashMap<String, String>>("a=1&b=2").unwrap(),
+        vec![("a", "1"), ("b", "2")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    );
+    assert_eq!(
+        query::from_str::<Vec<(String, u32)>>("a=1&b=2&a=3").unwrap(),
+        vec![
+            ("a".to_string(), 1),
+            ("a".to_string(), 3),
+            ("b".to_string(), 2),
+        ]
+    );
+}
+
+#[test]
+fn repeated() {
+    let search = query::from_str::<Search>("tag=a&q=x&tag=b&tag[]=c").unwrap();
+    assert_eq!(search.tag, ["a", "b", "c"]);
+
+    // A single value can be decoded into a sequence
+    let search = query::from_str::<Search>("q=x&tag=a").unwrap();
+    assert_eq!(search.tag, ["a"]);
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Ids {
+        ids: Vec<u32>,
+    }
+
+    assert_eq!(
+        query::from_str::<Ids>("ids[1]=20&ids[0]=10&ids[2]=30").unwrap(),
+        Ids {
+            ids: vec![10, 20, 30]
+        }
+    );
+}
+
+#[test]
+fn nested() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Query {
+        filter: Filter,
+        sort: BTreeMap<String, String>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Filter {
+        status: Status,
+        user: User,
+        labels: Vec<String>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Status {
+        Open,
+        Closed,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct User {
+        id: u64,
+        active: bool,
+    }
+
+    let query = query::from_str::<Query>(
+        "filter[status]=open&filter[user][id]=7&filter[labels][]=bug&sort[name]=asc&\
+         filter[user][active]=true&filter%5Blabels%5D%5B%5D=ui",
+    )
+    .unwrap();
+    assert_eq!(
+        query,
+        Query {
+            filter: Filter {
+                status: Status::Open,
+                user: User {
+                    id: 7,
+                    active: true
+                },
+                labels: vec!["bug".to_string(), "ui".to_string()],
+            },
+            sort: vec![("name".to_string(), "asc".to_string())]
+                .into_iter()
+                .collect(),
+        }
+    );
+
+    // Malformed brackets are part of the name
+    let map = query::from_str::<HashMap<String, String>>("a[b=1&[c]=2&d]=3&e[f]g=4").unwrap();
+    assert_eq!(map["a[b"], "1");
+    assert_eq!(map["[c]"], "2");
+    assert_eq!(map["d]"], "3");
+    assert_eq!(map["e[f]g"], "4");
+}
+
+#[test]
+fn errors() {
+    let err = |input: &str| query::from_str::<Search>(input).unwrap_err().to_string();
+
+    assert_eq!(err("page=1"), "missing field `q`");
+    assert_eq!(
+        err("q=x&page=two"),
+        "invalid value `two`: invalid digit found in string"
+    );
+    assert_eq!(
+        err("q=x&page=1&page=2"),
+        "invalid type: sequence, expected u32"
+    );
+    assert_eq!(
+        err("q=x&tag=a&tag[x]=b"),
+        "conflicting values for query parameter `tag`"
+    );
+    assert_eq!(
+        err("q=x&tag[x]=a&tag=b"),
+        "conflicting values for query parameter `tag`"
+    );
+    assert_eq!(
+        err("q=x&tag[][x]=a"),
+        "`[]` must be the last key of query parameter `tag`"
+    );
+    assert_eq!(err("q=%FF"), "query data is not valid UTF-8");
+
+    let deep = format!("q{}=x", "[a]".repeat(100));
+    assert!(err(&deep).ends_with("is nested too deeply"));
+}
+
+#[test]
+fn error_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync + 'static>() {}
+    assert_send_sync::<Error>();
+}